@@ -0,0 +1,137 @@
+//! Procedural macros for `rocket_async_compression`.  These are re-exported
+//! from the main crate and should be used from there.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, FnArg, ItemFn, LitStr, Pat, ReturnType, Type};
+
+/// Wraps the responder returned by a route in `Compress`, returning a
+/// `ConfiguredCompress`.
+///
+/// Optionally accepts a `level` (`"default"`, `"best"`, `"fastest"` or a
+/// numeric quality) and an `encoding` (`"br"` or `"gzip"`) to force.  The
+/// attribute may be placed either above or below the route attribute.
+///
+/// ```rust,ignore
+/// #[get("/")]
+/// #[compress(level = "best", encoding = "br")]
+/// fn index() -> &'static str {
+///     "Hello, world!"
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn compress(args: TokenStream, input: TokenStream) -> TokenStream {
+    let mut level = None;
+    let mut encoding = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("level") {
+            level = Some(meta.value()?.parse::<LitStr>()?);
+            Ok(())
+        } else if meta.path.is_ident("encoding") {
+            encoding = Some(meta.value()?.parse::<LitStr>()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported `compress` property; expected `level` or `encoding`"))
+        }
+    });
+    parse_macro_input!(args with parser);
+
+    let function = parse_macro_input!(input as ItemFn);
+    match expand(function, level, encoding) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn level_tokens(level: Option<LitStr>) -> syn::Result<TokenStream2> {
+    let Some(level) = level else {
+        return Ok(quote!(::rocket_async_compression::Level::Default));
+    };
+
+    Ok(match level.value().as_str() {
+        "default" => quote!(::rocket_async_compression::Level::Default),
+        "best" => quote!(::rocket_async_compression::Level::Best),
+        "fastest" => quote!(::rocket_async_compression::Level::Fastest),
+        other => {
+            let quality: i32 = other.parse().map_err(|_| {
+                syn::Error::new(
+                    level.span(),
+                    "expected `default`, `best`, `fastest` or a numeric quality",
+                )
+            })?;
+            quote!(::rocket_async_compression::Level::Precise(#quality))
+        }
+    })
+}
+
+fn encoding_tokens(encoding: Option<LitStr>) -> syn::Result<TokenStream2> {
+    let Some(encoding) = encoding else {
+        return Ok(TokenStream2::new());
+    };
+
+    let variant = match encoding.value().as_str() {
+        "br" => quote!(Brotli),
+        "gzip" => quote!(Gzip),
+        _ => return Err(syn::Error::new(encoding.span(), "expected `br` or `gzip`")),
+    };
+    Ok(quote!(.with_encoding(::rocket_async_compression::Encoding::#variant)))
+}
+
+fn expand(
+    mut function: ItemFn,
+    level: Option<LitStr>,
+    encoding: Option<LitStr>,
+) -> syn::Result<TokenStream2> {
+    let level = level_tokens(level)?;
+    let encoding = encoding_tokens(encoding)?;
+
+    // The original body becomes an inner function with the original signature, so that `return`
+    // and `?` inside it still produce the original responder type and borrows of the arguments
+    // keep their lifetimes.  The route keeps the argument names, which Rocket matches against the
+    // route's parameters.
+    let mut inner = function.clone();
+    inner.attrs.clear();
+    inner.vis = syn::Visibility::Inherited;
+    inner.sig.ident = format_ident!("__compress_inner");
+
+    let mut arguments = Vec::new();
+    for (index, input) in function.sig.inputs.iter_mut().enumerate() {
+        let FnArg::Typed(argument) = input else {
+            return Err(syn::Error::new_spanned(
+                input,
+                "`compress` can't be used on methods",
+            ));
+        };
+        match &mut *argument.pat {
+            Pat::Ident(pat) if pat.by_ref.is_none() && pat.subpat.is_none() => {
+                pat.mutability = None;
+                arguments.push(pat.ident.clone());
+            }
+            pat => {
+                let ident = format_ident!("__compress_arg{}", index);
+                *pat = parse_quote!(#ident);
+                arguments.push(ident);
+            }
+        }
+    }
+
+    let responder_type: Type = match &function.sig.output {
+        ReturnType::Default => parse_quote!(()),
+        ReturnType::Type(_, ty) => (**ty).clone(),
+    };
+    function.sig.output =
+        parse_quote!(-> ::rocket_async_compression::ConfiguredCompress<#responder_type>);
+
+    let call = quote!(__compress_inner(#(#arguments),*));
+    let responder = match function.sig.asyncness {
+        Some(_) => quote!(#call.await),
+        None => call,
+    };
+    function.block = parse_quote!({
+        #inner
+        ::rocket_async_compression::Compress::with_level(#responder, #level).configure() #encoding
+    });
+
+    Ok(quote!(#function))
+}
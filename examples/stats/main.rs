@@ -0,0 +1,43 @@
+#[macro_use]
+extern crate rocket;
+
+use rocket::serde::{json::Json, Serialize};
+use rocket::State;
+use rocket_async_compression::{Compression, CompressionStats, SkipReason};
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct Stats {
+    considered: u64,
+    compressed: u64,
+    not_accepted: u64,
+    excluded_type: u64,
+    already_encoded: u64,
+    too_small: u64,
+    bytes_saved: u64,
+}
+
+#[get("/")]
+fn index() -> &'static str {
+    "Hello, world! Hello, world! Hello, world! Hello, world!"
+}
+
+#[get("/stats")]
+fn stats(stats: &State<CompressionStats>) -> Json<Stats> {
+    Json(Stats {
+        considered: stats.responses_considered(),
+        compressed: stats.responses_compressed(),
+        not_accepted: stats.responses_skipped(SkipReason::NotAccepted),
+        excluded_type: stats.responses_skipped(SkipReason::ExcludedType),
+        already_encoded: stats.responses_skipped(SkipReason::AlreadyEncoded),
+        too_small: stats.responses_skipped(SkipReason::TooSmall),
+        bytes_saved: stats.bytes_saved(),
+    })
+}
+
+#[launch]
+async fn rocket() -> _ {
+    rocket::build()
+        .mount("/", routes![index, stats])
+        .attach(Compression::fairing())
+}
@@ -0,0 +1,226 @@
+use rocket::Request;
+
+use crate::Encoding;
+
+/// Qualities are stored in thousandths, the precision allowed by RFC 9110.
+const MAX_QUALITY: u16 = 1000;
+
+/// The content codings accepted by a client, parsed from all of a request's
+/// `Accept-Encoding` headers.
+///
+/// Clients may split `Accept-Encoding` across several header lines, so codings
+/// and qualities are merged across all of them before any decision is made.
+/// When a coding is listed more than once, its highest quality wins.
+#[derive(Debug, Default)]
+pub(crate) struct AcceptEncoding {
+    /// Explicitly listed codings, lowercased and under their canonical names,
+    /// with their qualities.
+    codings: Vec<(String, u16)>,
+    /// Quality of the `*` wildcard, if present.
+    wildcard: Option<u16>,
+}
+
+impl AcceptEncoding {
+    pub(crate) fn from_request(request: &Request<'_>) -> AcceptEncoding {
+        AcceptEncoding::from_request_header(request, None)
+    }
+
+    /// Parses the request's `alternate` header instead of `Accept-Encoding`
+    /// if it is set and present on the request.
+    pub(crate) fn from_request_header(
+        request: &Request<'_>,
+        alternate: Option<&str>,
+    ) -> AcceptEncoding {
+        let headers = request.headers();
+        match alternate {
+            Some(alternate) if headers.contains(alternate) => {
+                AcceptEncoding::parse(headers.get(alternate))
+            }
+            _ => AcceptEncoding::parse(headers.get("Accept-Encoding")),
+        }
+    }
+
+    pub(crate) fn parse<'a>(values: impl Iterator<Item = &'a str>) -> AcceptEncoding {
+        let mut accept = AcceptEncoding::default();
+        for item in values.flat_map(|value| value.split(',')) {
+            let mut params = item.split(';');
+            let coding = params.next().unwrap_or_default().trim();
+            if coding.is_empty() {
+                continue;
+            }
+            // Parsing lowercases codings and maps aliases such as `x-gzip` to their canonical name.
+            let Ok(coding) = coding.parse::<Encoding>();
+            let coding = coding.to_string();
+            let quality = params
+                .find_map(|param| {
+                    let (name, value) = param.split_once('=')?;
+                    name.trim().eq_ignore_ascii_case("q").then(|| value.trim())
+                })
+                .map(parse_quality)
+                .unwrap_or(MAX_QUALITY);
+
+            if coding == "*" {
+                accept.wildcard = Some(accept.wildcard.unwrap_or(0).max(quality));
+            } else if let Some((_, existing)) = accept
+                .codings
+                .iter_mut()
+                .find(|(existing, _)| *existing == coding)
+            {
+                *existing = (*existing).max(quality);
+            } else {
+                accept.codings.push((coding, quality));
+            }
+        }
+        accept
+    }
+
+    /// Returns the quality assigned to `coding`, `0` meaning not acceptable.
+    pub(crate) fn quality(&self, coding: &str) -> u16 {
+        if let Some((_, quality)) = self
+            .codings
+            .iter()
+            .find(|(listed, _)| listed.eq_ignore_ascii_case(coding))
+        {
+            return *quality;
+        }
+
+        match self.wildcard {
+            Some(quality) => quality,
+            // The identity coding is acceptable unless explicitly excluded.
+            None if coding.eq_ignore_ascii_case("identity") => MAX_QUALITY,
+            None => 0,
+        }
+    }
+
+    pub(crate) fn accepts(&self, encoding: &Encoding) -> bool {
+        self.quality(&encoding.to_string()) > 0
+    }
+
+    /// Returns the acceptable encoding with the highest quality, or `None` if
+    /// none of `candidates` are acceptable.  Ties are broken by the order of
+    /// `candidates`.
+    pub(crate) fn preferred<'a>(&self, candidates: &'a [Encoding]) -> Option<&'a Encoding> {
+        let mut best: Option<(&Encoding, u16)> = None;
+        for candidate in candidates {
+            let quality = self.quality(&candidate.to_string());
+            let is_better = match best {
+                Some((_, best_quality)) => quality > best_quality,
+                None => quality > 0,
+            };
+            if is_better {
+                best = Some((candidate, quality));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+}
+
+/// Parses a quality value such as `0.8` into thousandths.  Invalid values are
+/// treated as not acceptable.
+fn parse_quality(value: &str) -> u16 {
+    match value.parse::<f32>() {
+        Ok(quality) if (0.0..=1.0).contains(&quality) => (quality * 1000.0).round() as u16,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(values: &[&str]) -> AcceptEncoding {
+        AcceptEncoding::parse(values.iter().copied())
+    }
+
+    #[test]
+    fn q_zero_excludes_a_coding() {
+        let accept = parse(&["gzip;q=0, br"]);
+        assert!(!accept.accepts(&Encoding::Gzip));
+        assert!(accept.accepts(&Encoding::Brotli));
+    }
+
+    #[test]
+    fn wildcard_applies_to_unlisted_codings() {
+        let accept = parse(&["*;q=0.5, br;q=0"]);
+        assert_eq!(accept.quality("gzip"), 500);
+        assert_eq!(accept.quality("br"), 0);
+        assert_eq!(parse(&["*;q=0"]).quality("identity"), 0);
+    }
+
+    #[test]
+    fn identity_is_acceptable_unless_excluded() {
+        assert_eq!(parse(&[]).quality("identity"), MAX_QUALITY);
+        assert_eq!(parse(&["gzip"]).quality("identity"), MAX_QUALITY);
+        assert_eq!(parse(&["identity;q=0"]).quality("identity"), 0);
+    }
+
+    #[test]
+    fn whitespace_is_ignored() {
+        let accept = parse(&["  gzip ; q = 0.8 ,br;  q=0.2 , , "]);
+        assert_eq!(accept.quality("gzip"), 800);
+        assert_eq!(accept.quality("br"), 200);
+    }
+
+    #[test]
+    fn malformed_qualities_are_not_acceptable() {
+        let accept = parse(&["gzip;q=abc, br;q=1.5, zstd;q=-1, deflate;q"]);
+        assert_eq!(accept.quality("gzip"), 0);
+        assert_eq!(accept.quality("br"), 0);
+        assert_eq!(accept.quality("zstd"), 0);
+        assert_eq!(accept.quality("deflate"), MAX_QUALITY);
+    }
+
+    #[test]
+    fn codings_and_parameters_are_case_insensitive() {
+        let accept = parse(&["GZIP;Q=0.3, Br"]);
+        assert_eq!(accept.quality("gzip"), 300);
+        assert_eq!(accept.quality("BR"), MAX_QUALITY);
+    }
+
+    #[test]
+    fn aliases_count_as_their_canonical_coding() {
+        let accept = parse(&["x-gzip"]);
+        assert!(accept.accepts(&Encoding::Gzip));
+        assert_eq!(
+            accept.preferred(&[Encoding::Brotli, Encoding::Gzip]),
+            Some(&Encoding::Gzip)
+        );
+        assert_eq!(parse(&["gzip;q=0.2, X-Gzip;q=0.6"]).quality("gzip"), 600);
+    }
+
+    #[test]
+    fn codings_are_merged_across_headers_keeping_the_highest_quality() {
+        let accept = parse(&["gzip;q=0.2", "gzip;q=0.7, br;q=0.1"]);
+        assert_eq!(accept.quality("gzip"), 700);
+        assert_eq!(accept.quality("br"), 100);
+    }
+
+    #[test]
+    fn preferred_picks_the_highest_quality() {
+        let accept = parse(&["gzip;q=0.9, br;q=0.4"]);
+        assert_eq!(
+            accept.preferred(&[Encoding::Brotli, Encoding::Gzip]),
+            Some(&Encoding::Gzip)
+        );
+    }
+
+    #[test]
+    fn preferred_breaks_ties_by_candidate_order() {
+        let accept = parse(&["gzip, br"]);
+        assert_eq!(
+            accept.preferred(&[Encoding::Brotli, Encoding::Gzip]),
+            Some(&Encoding::Brotli)
+        );
+        assert_eq!(
+            accept.preferred(&[Encoding::Gzip, Encoding::Brotli]),
+            Some(&Encoding::Gzip)
+        );
+    }
+
+    #[test]
+    fn preferred_is_none_when_nothing_is_acceptable() {
+        let accept = parse(&["gzip;q=0, br;q=0"]);
+        assert_eq!(accept.preferred(&[Encoding::Brotli, Encoding::Gzip]), None);
+        assert_eq!(parse(&[]).preferred(&[Encoding::Gzip]), None);
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    pin::Pin,
+    sync::{Arc, RwLock},
+};
+
+use async_compression::Level;
+use lazy_static::lazy_static;
+use rocket::{tokio::io::AsyncRead, Request, Response};
+
+use crate::{CompressionOptions, CompressionUtils, Encoding, ZstdParams};
+
+/// Transforms response bodies for the compression pipeline.
+///
+/// The [`Compression`](crate::Compression) fairing and [`compress_response`]
+/// take care of negotiating an encoding with the client, skipping excluded,
+/// already encoded and empty responses, setting the `Content-Encoding` and
+/// `Vary` headers and recording statistics.  A backend only supplies the
+/// encoder, which lets applications reuse all of that bookkeeping with their
+/// own body transformation.  [`DefaultBackend`] uses the same encoders as the
+/// rest of the crate.
+///
+/// # Usage
+///
+/// ```rust
+/// use std::pin::Pin;
+///
+/// use rocket::tokio::io::AsyncRead;
+/// use rocket_async_compression::{Compression, CompressionBackend, Encoding, Level};
+///
+/// /// Only ever produces gzip, using an external encoder.
+/// struct GzipOnly;
+///
+/// impl CompressionBackend for GzipOnly {
+///     fn supports(&self, encoding: &Encoding) -> bool {
+///         *encoding == Encoding::Gzip
+///     }
+///
+///     fn encode<'r>(
+///         &self,
+///         body: Pin<Box<dyn AsyncRead + Send + 'r>>,
+///         _encoding: &Encoding,
+///         level: Level,
+///     ) -> Pin<Box<dyn AsyncRead + Send + 'r>> {
+///         # let external_gzip_encoder = |body, _level| body;
+///         external_gzip_encoder(body, level)
+///     }
+/// }
+///
+/// let fairing = Compression::fairing().backend(GzipOnly);
+/// ```
+pub trait CompressionBackend: Send + Sync {
+    /// Returns `true` if the backend can produce `encoding`.  Only encodings
+    /// returning `true` are offered to the client.  The pipeline negotiates
+    /// brotli, gzip and, with the `zstd` feature, zstd.
+    fn supports(&self, encoding: &Encoding) -> bool {
+        let _ = encoding;
+        true
+    }
+
+    /// Wraps `body` in an encoder producing `encoding` at `level` as it is
+    /// read.  `encoding` is always supported by the backend.
+    fn encode<'r>(
+        &self,
+        body: Pin<Box<dyn AsyncRead + Send + 'r>>,
+        encoding: &Encoding,
+        level: Level,
+    ) -> Pin<Box<dyn AsyncRead + Send + 'r>>;
+}
+
+/// Wraps a body in an encoder for a [`CustomCoding`], compressing at the given
+/// level.
+pub type CodingEncoder = Arc<
+    dyn for<'r> Fn(
+            Pin<Box<dyn AsyncRead + Send + 'r>>,
+            Level,
+        ) -> Pin<Box<dyn AsyncRead + Send + 'r>>
+        + Send
+        + Sync,
+>;
+
+/// A content coding registered with
+/// [`Compression::register_coding`](crate::Compression::register_coding).
+///
+/// Custom codings are offered to clients alongside the built-in ones.  When a
+/// client accepts several codings with the same quality, the one with the
+/// highest priority is used.  Built-in codings have a priority of `0`, as do
+/// custom codings unless set with [`CustomCoding::priority`]; codings with the
+/// same priority are tried in the order they were registered, after the
+/// built-in ones.
+///
+/// A custom coding named after a built-in one, such as `gzip`, replaces its
+/// encoder.
+///
+/// # Usage
+///
+/// ```rust
+/// use rocket_async_compression::{Compression, CustomCoding};
+///
+/// # use std::pin::Pin;
+/// # use rocket::tokio::io::AsyncRead;
+/// # use rocket_async_compression::Level;
+/// # fn lz4_frame_encoder<'r>(
+/// #     body: Pin<Box<dyn AsyncRead + Send + 'r>>,
+/// #     _level: Level,
+/// # ) -> Pin<Box<dyn AsyncRead + Send + 'r>> {
+/// #     body
+/// # }
+/// let fairing = Compression::fairing()
+///     .register_coding(CustomCoding::new("lz4f", lz4_frame_encoder).priority(1));
+/// ```
+#[derive(Clone)]
+pub struct CustomCoding {
+    pub(crate) encoding: Encoding,
+    pub(crate) priority: i32,
+    pub(crate) encoder: CodingEncoder,
+}
+
+impl CustomCoding {
+    /// Creates the coding `name`, as it appears in `Accept-Encoding` and
+    /// `Content-Encoding`, whose responses are compressed by `encoder`.
+    pub fn new<F>(name: &str, encoder: F) -> CustomCoding
+    where
+        F: for<'r> Fn(
+                Pin<Box<dyn AsyncRead + Send + 'r>>,
+                Level,
+            ) -> Pin<Box<dyn AsyncRead + Send + 'r>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let encoding = match name.parse::<Encoding>() {
+            Ok(encoding) => encoding,
+            Err(never) => match never {},
+        };
+        CustomCoding {
+            encoding,
+            priority: 0,
+            encoder: Arc::new(encoder),
+        }
+    }
+
+    /// Sets the priority of the coding among codings the client accepts with
+    /// the same quality.  Higher priorities are preferred.
+    pub fn priority(self, priority: i32) -> CustomCoding {
+        CustomCoding { priority, ..self }
+    }
+
+    /// Returns the coding's encoding.
+    pub fn encoding(&self) -> &Encoding {
+        &self.encoding
+    }
+}
+
+lazy_static! {
+    /// Codings registered with [`register_encoder`], in registration order.
+    static ref REGISTERED_CODINGS: RwLock<Vec<CustomCoding>> = RwLock::new(Vec::new());
+}
+
+/// Registers the content coding `name`, compressed by `encoder`, for every
+/// fairing and responder of the application.
+///
+/// Registered codings are offered to clients after the built-in ones and the
+/// codings registered on a fairing with
+/// [`Compression::register_coding`](crate::Compression::register_coding),
+/// which take precedence when a client accepts several codings with the same
+/// quality.  Only codings unknown to the crate, i.e. parsed as
+/// [`Encoding::EncodingExt`], can be registered: registering a built-in coding
+/// such as `gzip` has no effect.  Registering a coding again replaces its
+/// encoder.
+///
+/// ```rust
+/// # use std::pin::Pin;
+/// # use rocket::tokio::io::AsyncRead;
+/// # use rocket_async_compression::Level;
+/// # fn lz4_frame_encoder<'r>(
+/// #     body: Pin<Box<dyn AsyncRead + Send + 'r>>,
+/// #     _level: Level,
+/// # ) -> Pin<Box<dyn AsyncRead + Send + 'r>> {
+/// #     body
+/// # }
+/// rocket_async_compression::register_encoder("lz4f", lz4_frame_encoder);
+/// ```
+pub fn register_encoder<F>(name: &str, encoder: F)
+where
+    F: for<'r> Fn(
+            Pin<Box<dyn AsyncRead + Send + 'r>>,
+            Level,
+        ) -> Pin<Box<dyn AsyncRead + Send + 'r>>
+        + Send
+        + Sync
+        + 'static,
+{
+    let coding = CustomCoding::new(name, encoder);
+    if !matches!(coding.encoding, Encoding::EncodingExt(_)) {
+        warn!(
+            "Ignoring encoder registered for the built-in coding `{}`",
+            name
+        );
+        return;
+    }
+
+    let mut codings = REGISTERED_CODINGS
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    codings.retain(|registered| registered.encoding != coding.encoding);
+    codings.push(coding);
+}
+
+/// Returns the encodings registered with [`register_encoder`].
+pub(crate) fn registered_encodings() -> Vec<Encoding> {
+    REGISTERED_CODINGS
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .iter()
+        .map(|coding| coding.encoding.clone())
+        .collect()
+}
+
+/// Returns the encoder registered for `encoding` with [`register_encoder`].
+pub(crate) fn registered_encoder(encoding: &Encoding) -> Option<CodingEncoder> {
+    REGISTERED_CODINGS
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .iter()
+        .find(|coding| coding.encoding == *encoding)
+        .map(|coding| coding.encoder.clone())
+}
+
+/// The backend used by the fairings and responders unless another one is
+/// configured, compressing with `async-compression`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultBackend;
+
+impl CompressionBackend for DefaultBackend {
+    // The arms only look like `matches!` when every encoder feature is enabled.
+    #[allow(clippy::match_like_matches_macro)]
+    fn supports(&self, encoding: &Encoding) -> bool {
+        match encoding {
+            Encoding::Brotli => cfg!(feature = "brotli"),
+            Encoding::Gzip => cfg!(feature = "gzip"),
+            Encoding::Zstd => cfg!(feature = "zstd"),
+            _ => false,
+        }
+    }
+
+    fn encode<'r>(
+        &self,
+        body: Pin<Box<dyn AsyncRead + Send + 'r>>,
+        encoding: &Encoding,
+        level: Level,
+    ) -> Pin<Box<dyn AsyncRead + Send + 'r>> {
+        let zstd = ZstdParams {
+            level,
+            ..ZstdParams::default()
+        };
+        CompressionUtils::streaming_encoder(body, encoding, level, false, None, &zstd)
+    }
+}
+
+/// Compresses `response` with `backend` the way the
+/// [`Compression`](crate::Compression) fairing does with its default
+/// settings, for use in custom fairings.
+///
+/// The response is left untouched if it has no body, already has a
+/// `Content-Encoding`, has an excluded content type or the client doesn't
+/// accept any encoding supported by `backend`.
+///
+/// ```rust
+/// use rocket::{
+///     fairing::{Fairing, Info, Kind},
+///     Request, Response,
+/// };
+/// use rocket_async_compression::{compress_response, DefaultBackend, Level};
+///
+/// struct MyCompression;
+///
+/// #[rocket::async_trait]
+/// impl Fairing for MyCompression {
+///     fn info(&self) -> Info {
+///         Info {
+///             name: "My compression",
+///             kind: Kind::Response,
+///         }
+///     }
+///
+///     async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+///         compress_response(request, response, &DefaultBackend, Level::Fastest);
+///     }
+/// }
+/// ```
+pub fn compress_response<'r>(
+    request: &Request<'_>,
+    response: &mut Response<'r>,
+    backend: &dyn CompressionBackend,
+    level: Level,
+) {
+    CompressionUtils::compress_response(
+        request,
+        response,
+        &CompressionOptions {
+            backend: Some(backend),
+            ..CompressionOptions::new(&crate::EXCLUSIONS, level)
+        },
+    );
+}
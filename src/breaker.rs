@@ -0,0 +1,122 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Reads the current load of the machine, e.g. the CPU utilization between
+/// `0.0` and `1.0` or the load average.
+pub type LoadSource = Arc<dyn Fn() -> f64 + Send + Sync>;
+
+/// Stops compressing responses while the machine is overloaded, so that
+/// compression doesn't make an overload worse.
+///
+/// The breaker reads the load from a user-provided [`LoadSource`], at most
+/// once per sampling interval.  When a sample is above the threshold, the
+/// breaker opens: responses are served uncompressed until the cooldown period
+/// has elapsed since the last sample above the threshold.  Skipped
+/// responses are counted as [`SkipReason::Overloaded`](crate::SkipReason::Overloaded).
+///
+/// `LoadBreaker` is a handle to shared state, so a clone can be given to both
+/// fairings.
+///
+/// # Usage
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use rocket_async_compression::{CachedCompression, Compression, LoadBreaker};
+///
+/// # fn cpu_utilization() -> f64 { 0.0 }
+/// let breaker = LoadBreaker::new(0.9, cpu_utilization).cooldown(Duration::from_secs(30));
+///
+/// rocket::build()
+///     // ...
+///     .attach(Compression::fairing().load_breaker(breaker.clone()))
+///     .attach(
+///         CachedCompression::path_suffix_fairing(vec![".js".to_owned()]).load_breaker(breaker),
+///     )
+///     // ...
+///     # ;
+/// ```
+#[derive(Clone)]
+pub struct LoadBreaker {
+    load: LoadSource,
+    threshold: f64,
+    sample_interval: Duration,
+    cooldown: Duration,
+    state: Arc<BreakerState>,
+}
+
+/// Times are in milliseconds since `started`, so that they fit in atomics.
+struct BreakerState {
+    started: Instant,
+    next_sample: AtomicU64,
+    open_until: AtomicU64,
+}
+
+impl LoadBreaker {
+    /// Creates a breaker opening when `load` returns more than `threshold`.
+    /// The load is sampled at most once a second and the breaker stays open
+    /// for at least 10 seconds.
+    pub fn new<F>(threshold: f64, load: F) -> LoadBreaker
+    where
+        F: Fn() -> f64 + Send + Sync + 'static,
+    {
+        LoadBreaker {
+            load: Arc::new(load),
+            threshold,
+            sample_interval: Duration::from_secs(1),
+            cooldown: Duration::from_secs(10),
+            state: Arc::new(BreakerState {
+                started: Instant::now(),
+                next_sample: AtomicU64::new(0),
+                open_until: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Sets the minimum time between two samples of the load.
+    pub fn sample_every(self, sample_interval: Duration) -> LoadBreaker {
+        LoadBreaker {
+            sample_interval,
+            ..self
+        }
+    }
+
+    /// Sets how long the breaker stays open after a sample above the
+    /// threshold.
+    pub fn cooldown(self, cooldown: Duration) -> LoadBreaker {
+        LoadBreaker { cooldown, ..self }
+    }
+
+    /// Returns `true` if responses should currently be left uncompressed,
+    /// sampling the load if the sampling interval has elapsed.
+    pub fn is_open(&self) -> bool {
+        let now = self.state.started.elapsed().as_millis() as u64;
+        let next_sample = self.state.next_sample.load(Ordering::Relaxed);
+        // Only the request winning the exchange samples the load, so slow load sources are called
+        // once per interval however many requests arrive.
+        if now >= next_sample
+            && self
+                .state
+                .next_sample
+                .compare_exchange(
+                    next_sample,
+                    now + self.sample_interval.as_millis() as u64,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            && (self.load)() > self.threshold
+        {
+            self.state
+                .open_until
+                .store(now + self.cooldown.as_millis() as u64, Ordering::Relaxed);
+        }
+
+        now < self.state.open_until.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    sync::Arc,
+};
+
+/// A map of compressed bodies bounded by their total size in bytes.  When
+/// inserting an entry would exceed the bound, the oldest entries are evicted
+/// first.
+pub(crate) struct BoundedCache<K> {
+    entries: HashMap<K, Arc<[u8]>>,
+    insertion_order: VecDeque<K>,
+    size: usize,
+    max_size: usize,
+}
+
+impl<K: Clone + Eq + Hash> BoundedCache<K> {
+    pub(crate) fn new(max_size: usize) -> BoundedCache<K> {
+        BoundedCache {
+            entries: HashMap::new(),
+            insertion_order: VecDeque::new(),
+            size: 0,
+            max_size,
+        }
+    }
+
+    pub(crate) fn get(&self, key: &K) -> Option<Arc<[u8]>> {
+        self.entries.get(key).cloned()
+    }
+
+    /// Inserts `value`, evicting old entries as needed to stay within the size
+    /// bound.  Values larger than the bound itself are not inserted.  Returns
+    /// the number of evicted entries.
+    pub(crate) fn insert(&mut self, key: K, value: Arc<[u8]>) -> usize {
+        if value.len() > self.max_size {
+            return 0;
+        }
+
+        if let Some(previous) = self.entries.remove(&key) {
+            self.size -= previous.len();
+            self.insertion_order.retain(|existing| *existing != key);
+        }
+
+        let mut evicted = 0;
+        while self.size + value.len() > self.max_size {
+            let Some(oldest) = self.insertion_order.pop_front() else {
+                break;
+            };
+            if let Some(removed) = self.entries.remove(&oldest) {
+                self.size -= removed.len();
+                evicted += 1;
+            }
+        }
+
+        self.size += value.len();
+        self.insertion_order.push_back(key.clone());
+        self.entries.insert(key, value);
+        evicted
+    }
+
+    /// Changes the size bound, evicting entries if the cache no longer fits.
+    pub(crate) fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        while self.size > self.max_size {
+            let Some(oldest) = self.insertion_order.pop_front() else {
+                break;
+            };
+            if let Some(removed) = self.entries.remove(&oldest) {
+                self.size -= removed.len();
+            }
+        }
+    }
+}
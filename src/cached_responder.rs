@@ -0,0 +1,208 @@
+use std::{
+    io::Cursor,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use async_compression::Level;
+use lazy_static::lazy_static;
+use rocket::{
+    http::{hyper::header::CONTENT_ENCODING, Header},
+    response::{self, Responder},
+    tokio::io::{AsyncRead, ReadBuf},
+    Request,
+};
+
+use crate::{
+    cache::BoundedCache, CompressionStats, CompressionUtils, Encoding, NoContentTypePolicy,
+    ZstdParams, EXCLUSIONS,
+};
+
+/// Default bound on the total size of the bodies cached by [`CachedCompress`].
+const DEFAULT_MAX_CACHE_BYTES: usize = 32 * 1024 * 1024;
+
+lazy_static! {
+    static ref RESPONDER_CACHE: Mutex<BoundedCache<(String, Encoding)>> =
+        Mutex::new(BoundedCache::new(DEFAULT_MAX_CACHE_BYTES));
+}
+
+fn responder_cache() -> std::sync::MutexGuard<'static, BoundedCache<(String, Encoding)>> {
+    RESPONDER_CACHE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Sets the bound on the total size of the bodies cached by all
+/// [`CachedCompress`] responders, evicting entries if necessary.
+///
+/// ```rust
+/// rocket_async_compression::set_cached_compress_max_bytes(8 * 1024 * 1024);
+/// ```
+pub fn set_cached_compress_max_bytes(max_cache_bytes: usize) {
+    responder_cache().set_max_size(max_cache_bytes);
+}
+
+/// Compresses a responder's body and caches the result under a key chosen by
+/// the route.
+///
+/// The first time a key is served with a given encoding, the body is
+/// compressed while it is streamed to the client and stored once complete.
+/// Later responses for the same key and encoding reuse the stored bytes
+/// instead of compressing again.  The wrapped responder is still invoked on
+/// every request so that its status and headers are preserved; only its body
+/// is replaced.
+///
+/// This is useful when a handler's output depends on a small set of
+/// parameters that path-based caching with
+/// [`CachedCompression`](super::CachedCompression) can't express.  Keys are
+/// shared by every route using `CachedCompress`, so include something unique to
+/// the route in them.
+///
+/// Cached bodies are held in memory, bounded by a total size which defaults to
+/// 32 MiB and can be changed with [`set_cached_compress_max_bytes`].
+/// The oldest entries are evicted first once the bound is reached.
+///
+/// Negotiation and exclusions follow [`Compress`](super::Compress).
+///
+/// # Usage
+///
+/// ```rust
+/// use rocket_async_compression::CachedCompress;
+///
+/// # let theme = "dark";
+/// # let rendered = String::new();
+/// let response = CachedCompress::new(format!("/settings?theme={}", theme), rendered);
+/// ```
+#[derive(Debug)]
+pub struct CachedCompress<R> {
+    key: String,
+    responder: R,
+    level: Level,
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> CachedCompress<R> {
+    /// Caches the compressed body of `responder` under `key`.
+    pub fn new(key: impl Into<String>, responder: R) -> CachedCompress<R> {
+        CachedCompress {
+            key: key.into(),
+            responder,
+            level: Level::Default,
+        }
+    }
+
+    /// Sets the compression level used when the body isn't cached yet.
+    pub fn level(self, level: Level) -> CachedCompress<R> {
+        CachedCompress { level, ..self }
+    }
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for CachedCompress<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        let mut response = self.responder.respond_to(request)?;
+
+        if CompressionUtils::empty_body(&response)
+            || CompressionUtils::already_encoded(&response)
+            || CompressionUtils::skip_encoding(
+                &response.content_type(),
+                &EXCLUSIONS,
+                NoContentTypePolicy::Compress,
+            )
+        {
+            return Ok(response);
+        }
+
+        let (accepts_gzip, accepts_br) = CompressionUtils::accepted_algorithms(request, None);
+        let encoding = if accepts_br && cfg!(feature = "brotli") {
+            Encoding::Brotli
+        } else if accepts_gzip && cfg!(feature = "gzip") {
+            Encoding::Gzip
+        } else {
+            return Ok(response);
+        };
+
+        CompressionStats::global().record_encoding(&encoding);
+        response.set_header(Header::new(CONTENT_ENCODING.as_str(), encoding.to_string()));
+        CompressionUtils::add_vary(&mut response, "Accept-Encoding");
+
+        let key = (self.key, encoding);
+        let cached_body = responder_cache().get(&key);
+        match cached_body {
+            Some(cached_body) => {
+                debug!("Found cached response for key {}", key.0);
+                #[cfg(feature = "prometheus")]
+                crate::PrometheusMetrics::global().cache_hits.inc();
+                response.set_sized_body(cached_body.len(), Cursor::new(cached_body));
+            }
+            None => {
+                #[cfg(feature = "prometheus")]
+                crate::PrometheusMetrics::global().cache_misses.inc();
+                let body = response.body_mut().take();
+                let compressor = CompressionUtils::streaming_encoder(
+                    body,
+                    &key.1,
+                    self.level,
+                    false,
+                    None,
+                    &ZstdParams::default(),
+                );
+                response.set_streamed_body(CacheFiller {
+                    inner: compressor,
+                    buffer: Vec::new(),
+                    key: Some(key),
+                });
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+/// Passes compressed data through while recording it, inserting the complete
+/// body into the responder cache once the inner reader is exhausted.
+struct CacheFiller<R> {
+    inner: R,
+    buffer: Vec<u8>,
+    /// Taken once the body has been inserted, or when reading failed.
+    key: Option<(String, Encoding)>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CacheFiller<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled_before = buf.filled().len();
+        let had_capacity = buf.remaining() > 0;
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        match &poll {
+            Poll::Ready(Ok(())) => {
+                let read = &buf.filled()[filled_before..];
+                if !read.is_empty() {
+                    self.buffer.extend_from_slice(read);
+                } else if had_capacity {
+                    // Reading nothing into a non-full buffer marks the end of the body.
+                    if let Some(key) = self.key.take() {
+                        debug!("Setting cached response for key {}", key.0);
+                        let body: Arc<[u8]> = std::mem::take(&mut self.buffer).into();
+                        let evicted = responder_cache().insert(key, body);
+                        if evicted > 0 {
+                            debug!("Evicted {} cached responses", evicted);
+                        }
+                        #[cfg(feature = "prometheus")]
+                        crate::PrometheusMetrics::global()
+                            .cache_evictions
+                            .inc_by(evicted as u64);
+                    }
+                }
+            }
+            Poll::Ready(Err(_)) => {
+                self.key = None;
+                self.buffer = Vec::new();
+            }
+            Poll::Pending => {}
+        }
+        poll
+    }
+}
@@ -0,0 +1,48 @@
+use rocket::http::MediaType;
+
+/// Content types worth compressing that aren't covered by the rules in
+/// [`is_compressible`], curated from the `compressible` flags of mime-db.
+const COMPRESSIBLE_TYPES: &[(&str, &str)] = &[
+    ("application", "ecmascript"),
+    ("application", "graphql"),
+    ("application", "javascript"),
+    ("application", "json"),
+    ("application", "manifest+json"),
+    ("application", "rtf"),
+    ("application", "toml"),
+    ("application", "vnd.ms-fontobject"),
+    ("application", "wasm"),
+    ("application", "x-font-otf"),
+    ("application", "x-font-ttf"),
+    ("application", "x-javascript"),
+    ("application", "x-sh"),
+    ("application", "x-tar"),
+    ("application", "x-www-form-urlencoded"),
+    ("application", "xml"),
+    ("application", "yaml"),
+    ("font", "otf"),
+    ("font", "ttf"),
+    ("image", "bmp"),
+    ("image", "vnd.microsoft.icon"),
+    ("image", "x-icon"),
+];
+
+/// Returns `true` if `media_type` is known to compress well: every `text/*`
+/// type except `text/event-stream`, `+json`, `+xml` and `+yaml` types, and the
+/// types of [`COMPRESSIBLE_TYPES`].
+pub(crate) fn is_compressible(media_type: &MediaType) -> bool {
+    let top = media_type.top().as_str().to_ascii_lowercase();
+    let sub = media_type.sub().as_str().to_ascii_lowercase();
+    if top == "text" {
+        return sub != "event-stream";
+    }
+    if sub.ends_with("+json") || sub.ends_with("+xml") || sub.ends_with("+yaml") {
+        return true;
+    }
+
+    COMPRESSIBLE_TYPES
+        .iter()
+        .any(|(compressible_top, compressible_sub)| {
+            top == *compressible_top && sub == *compressible_sub
+        })
+}
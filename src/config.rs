@@ -0,0 +1,147 @@
+use async_compression::Level;
+use rocket::{
+    figment::{self, Figment},
+    http::MediaType,
+    serde::Deserialize,
+    Build, Rocket,
+};
+
+use crate::{CachedCompression, Encoding, EXCLUSIONS};
+
+/// Compression settings shared by the [`Compression`](crate::Compression)
+/// fairing and the [`Compress`](crate::Compress) responder, so that both
+/// follow the same application-wide policy.
+///
+/// Build the fairing from the config with
+/// [`Compression::from_config`](crate::Compression::from_config), which also
+/// places the config in managed state, and wrap responders with
+/// [`Compress::managed`](crate::Compress::managed) to have them look it up.
+/// The config can also be managed directly with `Rocket::manage`.
+///
+/// # Usage
+///
+/// ```rust
+/// use rocket_async_compression::{
+///     Compress, Compression, CompressionConfig, ConfiguredCompress, Level,
+/// };
+///
+/// #[rocket::get("/")]
+/// fn index() -> ConfiguredCompress<&'static str> {
+///     Compress::managed("Hi.")
+/// }
+///
+/// let config = CompressionConfig {
+///     level: Level::Fastest,
+///     min_size: Some(512),
+///     ..CompressionConfig::default()
+/// };
+///
+/// rocket::build()
+///     .attach(Compression::from_config(config))
+///     .mount("/", rocket::routes![index])
+///     # ;
+/// ```
+#[derive(Clone, Debug)]
+pub struct CompressionConfig {
+    /// The compression level.
+    pub level: Level,
+    /// Content types that are left uncompressed.  `*` may be used as the
+    /// subtype to exclude a whole top-level type.  Defaults to the same
+    /// exclusions as the fairing.
+    pub excluded_content_types: Vec<MediaType>,
+    /// Bodies smaller than this many bytes are left uncompressed.  Only bodies
+    /// with a known size are checked.
+    pub min_size: Option<usize>,
+    /// Encodings that may be produced, in order of preference, or `None` for
+    /// every supported encoding.
+    pub encodings: Option<Vec<Encoding>>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            level: Level::Default,
+            excluded_content_types: EXCLUSIONS.clone(),
+            min_size: None,
+            encodings: None,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Manages a clone of `self` unless a config is already managed.
+    pub(crate) fn manage(&self, rocket: Rocket<Build>) -> Rocket<Build> {
+        if rocket.state::<CompressionConfig>().is_some() {
+            return rocket;
+        }
+
+        rocket.manage(self.clone())
+    }
+}
+
+/// The key of the table [`CachedCompression::from_figment`] reads.
+pub(crate) const CACHED_COMPRESSION_KEY: &str = "cached_compression";
+
+/// The `cached_compression` table of the Rocket configuration.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+struct CachedCompressionTable {
+    #[serde(default)]
+    cached_paths: Vec<String>,
+    #[serde(default)]
+    cached_path_prefixes: Vec<String>,
+    #[serde(default)]
+    cached_path_suffixes: Vec<String>,
+    #[serde(default)]
+    excluded_path_prefixes: Vec<String>,
+    level: Option<LevelValue>,
+}
+
+/// A compression level as written in the configuration: `"default"`,
+/// `"best"`, `"fastest"` or a numeric quality.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde", untagged)]
+enum LevelValue {
+    Precise(i32),
+    Named(String),
+}
+
+impl LevelValue {
+    fn level(self) -> Result<Level, Box<figment::Error>> {
+        match self {
+            LevelValue::Precise(quality) => Ok(Level::Precise(quality)),
+            LevelValue::Named(name) => match name.as_str() {
+                "default" => Ok(Level::Default),
+                "best" => Ok(Level::Best),
+                "fastest" => Ok(Level::Fastest),
+                _ => name.parse().map(Level::Precise).map_err(|_| {
+                    Box::new(figment::Error::from(format!(
+                        "invalid `{}.level` `{}`: expected `default`, `best`, `fastest` or a \
+                         numeric quality",
+                        CACHED_COMPRESSION_KEY, name
+                    )))
+                }),
+            },
+        }
+    }
+}
+
+/// Builds the fairing configured by the `cached_compression` table of
+/// `figment`, or a fairing caching nothing if there is no such table.
+pub(crate) fn cached_compression_from_figment(
+    figment: &Figment,
+) -> Result<CachedCompression, Box<figment::Error>> {
+    if !figment.contains(CACHED_COMPRESSION_KEY) {
+        return Ok(CachedCompression::default());
+    }
+
+    let table: CachedCompressionTable = figment.extract_inner(CACHED_COMPRESSION_KEY)?;
+    Ok(CachedCompression {
+        cached_paths: table.cached_paths,
+        cached_path_prefixes: table.cached_path_prefixes,
+        cached_path_suffixes: table.cached_path_suffixes,
+        excluded_path_prefixes: table.excluded_path_prefixes,
+        level: table.level.map(LevelValue::level).transpose()?,
+        ..CachedCompression::default()
+    })
+}
@@ -0,0 +1,66 @@
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use rocket::tokio::io::{AsyncRead, ReadBuf};
+
+/// An `AsyncRead` adapter that adds the number of bytes read through it to a
+/// shared counter.
+///
+/// The counter is updated as data flows through the reader, so byte totals can
+/// be tracked for streamed bodies without buffering them.  Wrapping a source
+/// body and the encoder reading from it with two `CountingReader`s yields the
+/// original and compressed sizes respectively.
+///
+/// # Usage
+///
+/// ```rust
+/// use std::sync::{atomic::AtomicU64, Arc};
+///
+/// use rocket_async_compression::CountingReader;
+///
+/// let count = Arc::new(AtomicU64::new(0));
+/// let reader = CountingReader::new(&b"Hi."[..], count.clone());
+/// ```
+pub struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R> CountingReader<R> {
+    /// Wraps `inner`, adding every byte read from it to `count`.
+    pub fn new(inner: R, count: Arc<AtomicU64>) -> Self {
+        CountingReader { inner, count }
+    }
+
+    /// Returns the current value of the shared counter.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Consumes the adapter, returning the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled_before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            let read = buf.filled().len() - filled_before;
+            self.count.fetch_add(read as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+}
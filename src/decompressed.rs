@@ -0,0 +1,204 @@
+use std::{fmt, io, ops::Deref};
+
+use rocket::{
+    data::{self, Data, FromData, Limits},
+    http::Status,
+    tokio::io::AsyncReadExt,
+    Request,
+};
+
+use crate::{CompressedBody, RequestDecodingError};
+
+/// Types that can be read from a decoded request body by [`Decompressed`].
+///
+/// Rocket doesn't allow constructing [`Data`] from an arbitrary stream, so
+/// decoded bodies can't be handed to the inner type's [`FromData`]
+/// implementation.  Instead, this trait reads the type from a
+/// [`CompressedBody`] the way its `FromData` implementation reads it from
+/// `Data`, applying the same data limit and producing the same error type.
+///
+/// It is implemented for `String`, `Vec<u8>` and, with the `json` feature,
+/// `Json<T>`.
+#[rocket::async_trait]
+pub trait FromDecompressedData<'r>: FromData<'r> {
+    /// Reads `Self` from the decoded `body` of `req`.
+    async fn from_decompressed(
+        req: &'r Request<'_>,
+        body: CompressedBody<'r>,
+    ) -> Result<Self, (Status, Self::Error)>;
+}
+
+/// Returns the status for an error that occurred while reading a decoded body.
+fn read_error_status(err: &io::Error) -> Status {
+    let decoding_error = err
+        .get_ref()
+        .and_then(|err| err.downcast_ref::<RequestDecodingError>());
+    match decoding_error {
+        Some(err) => err.status(),
+        None => match err.kind() {
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => Status::BadRequest,
+            _ => Status::InternalServerError,
+        },
+    }
+}
+
+/// Reads the whole decoded `body` into a string, limited by the `string` data
+/// limit.
+async fn read_string<'r>(
+    req: &'r Request<'_>,
+    body: CompressedBody<'r>,
+) -> Result<String, (Status, io::Error)> {
+    let limit = req.limits().get("string").unwrap_or(Limits::STRING);
+    let mut string = String::new();
+    match body.open(limit).read_to_string(&mut string).await {
+        Ok(_) => Ok(string),
+        Err(err) => Err((read_error_status(&err), err)),
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromDecompressedData<'r> for String {
+    async fn from_decompressed(
+        req: &'r Request<'_>,
+        body: CompressedBody<'r>,
+    ) -> Result<Self, (Status, Self::Error)> {
+        read_string(req, body).await
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromDecompressedData<'r> for Vec<u8> {
+    async fn from_decompressed(
+        req: &'r Request<'_>,
+        body: CompressedBody<'r>,
+    ) -> Result<Self, (Status, Self::Error)> {
+        let limit = req.limits().get("bytes").unwrap_or(Limits::BYTES);
+        let mut bytes = Vec::new();
+        match body.open(limit).read_to_end(&mut bytes).await {
+            Ok(_) => Ok(bytes),
+            Err(err) => Err((read_error_status(&err), err)),
+        }
+    }
+}
+
+/// A decoded JSON body, cached in the request so that deserialized values can
+/// borrow from it.  Wrapped to avoid clashing with other cached `String`s.
+#[cfg(feature = "json")]
+struct DecodedJson(String);
+
+#[cfg(feature = "json")]
+#[rocket::async_trait]
+impl<'r, T: rocket::serde::Deserialize<'r>> FromDecompressedData<'r>
+    for rocket::serde::json::Json<T>
+{
+    async fn from_decompressed(
+        req: &'r Request<'_>,
+        body: CompressedBody<'r>,
+    ) -> Result<Self, (Status, Self::Error)> {
+        use rocket::serde::json::{self, Error, Json};
+
+        let limit = req.limits().get("json").unwrap_or(Limits::JSON);
+        let mut string = String::new();
+        if let Err(err) = body.open(limit).read_to_string(&mut string).await {
+            return Err((read_error_status(&err), Error::Io(err)));
+        }
+
+        let string = &req.local_cache(move || DecodedJson(string)).0;
+        match json::from_str(string) {
+            Ok(value) => Ok(Json(value)),
+            Err(err) if err.is_data() => {
+                Err((Status::UnprocessableEntity, Error::Parse(string, err)))
+            }
+            Err(err) => Err((Status::BadRequest, Error::Parse(string, err))),
+        }
+    }
+}
+
+/// The error produced by the [`Decompressed`] data guard.
+#[derive(Debug)]
+pub enum DecompressedError<E> {
+    /// The request's `Content-Encoding` can't be decoded.
+    Decoding(RequestDecodingError),
+    /// The inner guard failed.
+    Inner(E),
+}
+
+impl<E: fmt::Display> fmt::Display for DecompressedError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecompressedError::Decoding(err) => err.fmt(f),
+            DecompressedError::Inner(err) => err.fmt(f),
+        }
+    }
+}
+
+/// A data guard that decodes compressed request bodies before handing them to
+/// the inner guard `T`.
+///
+/// Requests without a `Content-Encoding` are passed to `T`'s [`FromData`]
+/// implementation untouched.  Encoded requests are decoded like
+/// [`CompressedBody`], subject to the same [`DecompressionLimits`], and read
+/// with [`FromDecompressedData`] using `T`'s usual data limit for the decoded
+/// size.  Errors of the inner guard are preserved in
+/// [`DecompressedError::Inner`].
+///
+/// [`DecompressionLimits`]: crate::DecompressionLimits
+///
+/// # Usage
+///
+/// ```rust
+/// # #[macro_use] extern crate rocket;
+/// use rocket_async_compression::Decompressed;
+///
+/// #[post("/notes", data = "<note>")]
+/// fn create(note: Decompressed<String>) -> String {
+///     format!("Received {} bytes", note.len())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Decompressed<T>(pub T);
+
+impl<T> Decompressed<T> {
+    /// Returns the value produced by the inner guard.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Decompressed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T: FromDecompressedData<'r>> FromData<'r> for Decompressed<T> {
+    type Error = DecompressedError<T::Error>;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let body = match CompressedBody::from_data(req, data).await {
+            data::Outcome::Success(body) => body,
+            data::Outcome::Error((status, err)) => {
+                return data::Outcome::Error((status, DecompressedError::Decoding(err)))
+            }
+            data::Outcome::Forward(forward) => return data::Outcome::Forward(forward),
+        };
+
+        if body.encodings().is_empty() {
+            return match T::from_data(req, body.into_data()).await {
+                data::Outcome::Success(value) => data::Outcome::Success(Decompressed(value)),
+                data::Outcome::Error((status, err)) => {
+                    data::Outcome::Error((status, DecompressedError::Inner(err)))
+                }
+                data::Outcome::Forward(forward) => data::Outcome::Forward(forward),
+            };
+        }
+
+        match T::from_decompressed(req, body).await {
+            Ok(value) => data::Outcome::Success(Decompressed(value)),
+            Err((status, err)) => data::Outcome::Error((status, DecompressedError::Inner(err))),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use sha2::{Digest, Sha256, Sha512};
+
+/// The hash algorithm of the `Content-Digest` and `Repr-Digest` headers set
+/// by [`CachedCompression::digest`](crate::CachedCompression::digest).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    /// SHA-256, registered as `sha-256`.
+    #[default]
+    Sha256,
+    /// SHA-512, registered as `sha-512`.
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    /// Returns the key of the algorithm in digest fields.
+    fn key(self) -> &'static str {
+        match self {
+            DigestAlgorithm::Sha256 => "sha-256",
+            DigestAlgorithm::Sha512 => "sha-512",
+        }
+    }
+
+    /// Returns the digest field value for `bytes`, a structured field
+    /// dictionary with a single member such as `sha-256=:<base64>:`.
+    pub(crate) fn field(self, bytes: &[u8]) -> String {
+        let digest = match self {
+            DigestAlgorithm::Sha256 => Sha256::digest(bytes).to_vec(),
+            DigestAlgorithm::Sha512 => Sha512::digest(bytes).to_vec(),
+        };
+        format!("{}=:{}:", self.key(), base64(&digest))
+    }
+}
+
+/// Encodes `bytes` in padded base64 with the standard alphabet, as used by
+/// structured field byte sequences.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let triple = chunk.iter().enumerate().fold(0u32, |triple, (i, byte)| {
+            triple | ((*byte as u32) << (16 - 8 * i))
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[((triple >> (18 - 6 * i)) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
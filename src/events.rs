@@ -0,0 +1,267 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+
+use async_compression::Level;
+use rocket::{
+    tokio::io::{AsyncRead, ReadBuf},
+    Response,
+};
+
+use crate::{Encoding, SkipReason};
+
+/// A callback invoked with a [`CompressionEvent`] whenever a fairing finishes
+/// compressing a response.
+pub type CompressionHook = Arc<dyn Fn(CompressionEvent) + Send + Sync>;
+
+/// A callback invoked with a response right after the
+/// [`Compression`](crate::Compression) fairing has compressed it.  See
+/// [`Compression::after_compression`](crate::Compression::after_compression).
+pub type ResponseHook = Arc<dyn Fn(&mut Response<'_>) + Send + Sync>;
+
+/// Describes a response compressed by one of the fairings.
+///
+/// Events are passed to the hooks registered with
+/// [`Compression::on_compressed`](crate::Compression::on_compressed) and
+/// [`CachedCompression::on_compressed`](crate::CachedCompression::on_compressed).
+#[derive(Clone, Debug)]
+pub struct CompressionEvent {
+    /// The path of the request.
+    pub path: String,
+    /// The encoding the response was compressed with.
+    pub encoding: Encoding,
+    /// The size of the body before compression, if known.  Unknown for cache
+    /// hits.
+    pub original_size: Option<u64>,
+    /// The size of the compressed body.
+    pub compressed_size: u64,
+    /// The time spent compressing the response.  For streamed responses, this
+    /// is the time between the start of the response and the end of its body,
+    /// which includes the time spent waiting for the client and the original
+    /// body.
+    pub duration: Duration,
+    /// Whether the compressed body was served from the cache.
+    pub cache_hit: bool,
+}
+
+/// Formats as ` fairing=<name>` for fairings given a name and as nothing
+/// otherwise, for appending to log lines.
+#[derive(Clone, Copy)]
+pub(crate) struct FairingLabel<'a>(pub(crate) Option<&'a str>);
+
+impl fmt::Display for FairingLabel<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(name) => write!(f, " fairing={}", name),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Logs `event` at debug level as `key=value` pairs.
+pub(crate) fn log_event(event: &CompressionEvent, level: Level, fairing: FairingLabel<'_>) {
+    let original_size = event.original_size.map(|size| size.to_string());
+    let ratio = event
+        .original_size
+        .filter(|size| *size > 0)
+        .map(|size| format!("{:.3}", event.compressed_size as f64 / size as f64));
+    debug!(
+        "compressed response path={} encoding={} level={:?} original_size={} compressed_size={} ratio={} elapsed_us={} cache_hit={}{}",
+        event.path,
+        event.encoding,
+        level,
+        original_size.as_deref().unwrap_or("unknown"),
+        event.compressed_size,
+        ratio.as_deref().unwrap_or("unknown"),
+        event.duration.as_micros(),
+        event.cache_hit,
+        fairing
+    );
+}
+
+/// Logs a response left uncompressed at debug level as `key=value` pairs.
+pub(crate) fn log_skip(path: &str, reason: SkipReason, fairing: FairingLabel<'_>) {
+    debug!(
+        "skipped response compression path={} reason={:?}{}",
+        path, reason, fairing
+    );
+}
+
+/// Returns a hook logging events before passing them on to `hook`.
+pub(crate) fn logging_hook(
+    hook: Option<CompressionHook>,
+    level: Level,
+    fairing: Option<&'static str>,
+) -> CompressionHook {
+    Arc::new(move |event| {
+        log_event(&event, level, FairingLabel(fairing));
+        if let Some(hook) = &hook {
+            hook(event);
+        }
+    })
+}
+
+/// A `debug` level span covering the compression of a single response, with
+/// fields for its path, encoding, level, sizes and whether it was a cache hit.
+///
+/// Without the `tracing` feature, this does nothing.
+#[derive(Clone)]
+pub(crate) struct CompressionSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+/// Creates a [`CompressionSpan`] named `$name`.
+#[cfg(feature = "tracing")]
+macro_rules! compression_span {
+    ($name:literal, $path:expr, $encoding:expr, $level:expr) => {
+        tracing::debug_span!(
+            $name,
+            path = %$path,
+            encoding = %$encoding,
+            level = ?$level,
+            original_size = tracing::field::Empty,
+            compressed_size = tracing::field::Empty,
+            cache_hit = tracing::field::Empty,
+        )
+    };
+}
+
+impl CompressionSpan {
+    /// Creates the `compress_response` span for a response compressed while
+    /// streaming.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn streaming(path: &str, encoding: &Encoding, level: Level) -> Self {
+        CompressionSpan {
+            #[cfg(feature = "tracing")]
+            span: compression_span!("compress_response", path, encoding, level),
+        }
+    }
+
+    /// Creates the `cached_compression` span for a response handled by
+    /// [`CachedCompression`](crate::CachedCompression).
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn cached(path: &str, encoding: &Encoding, level: Level) -> Self {
+        CompressionSpan {
+            #[cfg(feature = "tracing")]
+            span: compression_span!("cached_compression", path, encoding, level),
+        }
+    }
+
+    /// Records the sizes and cache status of `event` on the span.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn record(&self, event: &CompressionEvent) {
+        #[cfg(feature = "tracing")]
+        {
+            if let Some(original_size) = event.original_size {
+                self.span.record("original_size", original_size);
+            }
+            self.span.record("compressed_size", event.compressed_size);
+            self.span.record("cache_hit", event.cache_hit);
+        }
+    }
+
+    /// Records that the response missed the cache.
+    pub(crate) fn record_miss(&self) {
+        #[cfg(feature = "tracing")]
+        self.span.record("cache_hit", false);
+    }
+
+    /// Attaches the span to `future`, so that the time spent polling it shows
+    /// up in the span's timings.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn instrument<F: Future>(&self, future: F) -> impl Future<Output = F::Output> {
+        tracing::Instrument::instrument(future, self.span.clone())
+    }
+
+    /// Attaches the span to `future`, so that the time spent polling it shows
+    /// up in the span's timings.
+    #[cfg(not(feature = "tracing"))]
+    pub(crate) fn instrument<F: Future>(&self, future: F) -> F {
+        future
+    }
+
+    /// Returns a hook recording events on the span before passing them on to
+    /// `hook`.  The span stays open until the hook is dropped.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn hook(self, hook: Option<CompressionHook>) -> CompressionHook {
+        Arc::new(move |event| {
+            self.record(&event);
+            if let Some(hook) = &hook {
+                hook(event);
+            }
+        })
+    }
+}
+
+/// Reports a [`CompressionEvent`] once the wrapped compressed body has been
+/// read to the end.
+pub(crate) struct ReportingReader<R> {
+    inner: R,
+    hook: CompressionHook,
+    path: String,
+    encoding: Encoding,
+    original_size: Arc<AtomicU64>,
+    compressed_size: u64,
+    started: Instant,
+    reported: bool,
+}
+
+impl<R> ReportingReader<R> {
+    /// Wraps the compressed body `inner`.  `original_size` must count the bytes
+    /// read from the original body.
+    pub(crate) fn new(
+        inner: R,
+        hook: CompressionHook,
+        path: String,
+        encoding: Encoding,
+        original_size: Arc<AtomicU64>,
+    ) -> Self {
+        ReportingReader {
+            inner,
+            hook,
+            path,
+            encoding,
+            original_size,
+            compressed_size: 0,
+            started: Instant::now(),
+            reported: false,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ReportingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        let read = buf.filled().len() - filled;
+        this.compressed_size += read as u64;
+
+        if read == 0 && buf.remaining() > 0 && !this.reported {
+            this.reported = true;
+            (this.hook)(CompressionEvent {
+                path: std::mem::take(&mut this.path),
+                encoding: this.encoding.clone(),
+                original_size: Some(this.original_size.load(Ordering::Relaxed)),
+                compressed_size: this.compressed_size,
+                duration: this.started.elapsed(),
+                cache_hit: false,
+            });
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
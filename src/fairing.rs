@@ -1,37 +1,419 @@
 use async_compression::Level;
 use lazy_static::lazy_static;
 use rocket::{
-    fairing::{Fairing, Info, Kind},
-    http::{hyper::header::CONTENT_ENCODING, Header, MediaType},
-    tokio::{
-        io::{AsyncRead, ReadBuf},
-        sync::RwLock,
+    fairing::{self, Fairing, Info, Kind},
+    figment::{self, Figment},
+    http::{hyper::header::CONTENT_ENCODING, ContentType, Header, MediaType, RawStr, Status},
+    tokio::io::{AsyncRead, AsyncReadExt, ReadBuf},
+    Build, Request, Response, Rocket, Route,
+};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt::Write,
+    hash::{Hash, Hasher},
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, RwLock,
     },
-    Request, Response,
+    task::Poll,
+    time::Instant,
 };
-use std::{collections::HashMap, io::Cursor, task::Poll};
 
-use crate::{CompressionUtils, Encoding};
+use crate::{
+    events::{log_event, log_skip, CompressionSpan, FairingLabel},
+    file_server::{is_compressible, write_atomically},
+    sniff, AcceptEncoding, CompressionBackend, CompressionConfig, CompressionEvent,
+    CompressionHook, CompressionInfo, CompressionOptions, CompressionStats, CompressionUtils,
+    CustomCoding, Encoding, EventStreamCheck, IpNetwork, LoadBreaker, NoContentTypePolicy,
+    ResponseHook, SkipReason, ZstdParams, DEFAULT_MAX_BUFFERED_BODY, EXCLUSIONS,
+};
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub(crate) enum CachedEncoding {
+    #[cfg(feature = "gzip")]
     Gzip,
+    #[cfg(feature = "brotli")]
     Brotli,
 }
 
+impl CachedEncoding {
+    /// Returns the cached encoding corresponding to `encoding`, if it can be
+    /// produced by the cached fairing.
+    pub(crate) fn from_encoding(encoding: &Encoding) -> Option<CachedEncoding> {
+        match encoding {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => Some(CachedEncoding::Gzip),
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => Some(CachedEncoding::Brotli),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn encoding(self) -> Encoding {
+        match self {
+            #[cfg(feature = "gzip")]
+            CachedEncoding::Gzip => Encoding::Gzip,
+            #[cfg(feature = "brotli")]
+            CachedEncoding::Brotli => Encoding::Brotli,
+        }
+    }
+
+    /// Returns the enabled encoding for which `allowed` is `true` that the
+    /// client prefers, favoring brotli at equal quality like [`Compression`]
+    /// does.
+    pub(crate) fn preferred(
+        accept: &AcceptEncoding,
+        allowed: impl Fn(CachedEncoding) -> bool,
+    ) -> Option<CachedEncoding> {
+        let candidates: Vec<Encoding> = [
+            #[cfg(feature = "brotli")]
+            CachedEncoding::Brotli,
+            #[cfg(feature = "gzip")]
+            CachedEncoding::Gzip,
+        ]
+        .into_iter()
+        .filter(|encoding| allowed(*encoding))
+        .map(CachedEncoding::encoding)
+        .collect();
+        accept
+            .preferred(&candidates)
+            .and_then(CachedEncoding::from_encoding)
+    }
+}
+
+/// Returns `true` if `path` is equal to any of `paths`, starts with any of
+/// `prefixes` or ends with any of `suffixes`, and doesn't start with any of
+/// `excluded_prefixes`.
+fn path_matches(
+    path: &str,
+    paths: &[String],
+    prefixes: &[String],
+    suffixes: &[String],
+    excluded_prefixes: &[String],
+) -> bool {
+    !excluded_prefixes.iter().any(|s| path.starts_with(s))
+        && (paths.iter().any(|s| path.eq(s))
+            || suffixes.iter().any(|s| path.ends_with(s))
+            || prefixes.iter().any(|s| path.starts_with(s)))
+}
+
+/// The paths cached by every [`CachedCompression`] fairing attached to an
+/// application, managed as state so that [`Compression`] can leave responses
+/// to them no matter in which order the fairings were attached.
+#[derive(Default)]
+struct CachedPaths(RwLock<Vec<CachedPathSet>>);
+
+struct CachedPathSet {
+    paths: Vec<String>,
+    prefixes: Vec<String>,
+    suffixes: Vec<String>,
+    excluded_prefixes: Vec<String>,
+    rules: CachedPathRules,
+}
+
+impl CachedPaths {
+    /// Adds the paths cached by `fairing` to those of `rocket`.
+    fn register(rocket: Rocket<Build>, fairing: &CachedCompression) -> Rocket<Build> {
+        let paths = CachedPathSet {
+            paths: fairing.cached_paths.clone(),
+            prefixes: fairing.cached_path_prefixes.clone(),
+            suffixes: fairing.cached_path_suffixes.clone(),
+            excluded_prefixes: fairing.excluded_path_prefixes.clone(),
+            rules: fairing.runtime_rules.clone(),
+        };
+        if let Some(cached_paths) = rocket.state::<CachedPaths>() {
+            cached_paths
+                .0
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .push(paths);
+            return rocket;
+        }
+
+        rocket.manage(CachedPaths(RwLock::new(vec![paths])))
+    }
+
+    /// Returns `true` if responses to requests for `path` are handled by a
+    /// [`CachedCompression`] fairing.
+    fn contains(&self, path: &str) -> bool {
+        self.0
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .any(|set| {
+                path_matches(
+                    path,
+                    &set.paths,
+                    &set.prefixes,
+                    &set.suffixes,
+                    &set.excluded_prefixes,
+                ) || set.rules.matches(path, &set.excluded_prefixes)
+            })
+    }
+}
+
+/// A handle to the rules of a [`CachedCompression`] fairing that can be added
+/// and removed while the application is running, e.g. to start caching pages
+/// published at runtime.  Changes apply to the requests that follow them.
+///
+/// The handle is obtained with [`CachedCompression::rules`] and can be cloned
+/// freely; every clone changes the rules of the same fairing.  Runtime rules
+/// extend the fairing's configured paths and are subject to its excluded path
+/// prefixes.
+///
+/// ```rust
+/// use rocket_async_compression::CachedCompression;
+///
+/// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()]);
+/// let rules = fairing.rules();
+///
+/// // Later, once a landing page is published.
+/// rules.add_exact_path("/lp/spring-sale");
+/// // And once it is taken down.
+/// rules.remove_rule("/lp/spring-sale", true);
+/// ```
+#[derive(Clone, Default)]
+pub struct CachedPathRules(Arc<RwLock<RuntimeRules>>);
+
+#[derive(Default)]
+struct RuntimeRules {
+    paths: Vec<String>,
+    prefixes: Vec<String>,
+    suffixes: Vec<String>,
+    /// The fairing's soft ceiling, set once it is attached, to tell whether a
+    /// purge made room in the cache.
+    max_cache_bytes: Option<usize>,
+}
+
+impl CachedPathRules {
+    /// Caches responses to requests for exactly `path`.
+    pub fn add_exact_path(&self, path: impl Into<String>) {
+        self.write().paths.push(path.into());
+    }
+
+    /// Caches responses to requests for paths starting with `prefix`.
+    pub fn add_prefix(&self, prefix: impl Into<String>) {
+        self.write().prefixes.push(prefix.into());
+    }
+
+    /// Caches responses to requests for paths ending with `suffix`.
+    pub fn add_suffix(&self, suffix: impl Into<String>) {
+        self.write().suffixes.push(suffix.into());
+    }
+
+    /// Removes the exact path, prefix and suffix rules equal to `rule` and
+    /// returns `true` if there were any.
+    ///
+    /// If `purge` is set, the cached bodies of the paths matched by the
+    /// removed rules are dropped as well, so that they aren't served anymore
+    /// should another rule still match them, and caching resumes if that
+    /// brings the cache back below its ceiling.  Bodies cached by `ETag` and
+    /// bodies persisted to disk are kept.
+    pub fn remove_rule(&self, rule: &str, purge: bool) -> bool {
+        let mut rules = self.write();
+        let removed_path = remove_all(&mut rules.paths, rule);
+        let removed_prefix = remove_all(&mut rules.prefixes, rule);
+        let removed_suffix = remove_all(&mut rules.suffixes, rule);
+        let max_cache_bytes = rules.max_cache_bytes;
+        drop(rules);
+
+        if purge {
+            CACHED_FILES.retain(|key| match key {
+                CacheKey::Path(path) => {
+                    !((removed_path && path == rule)
+                        || (removed_prefix && path.starts_with(rule))
+                        || (removed_suffix && path.ends_with(rule)))
+                }
+                CacheKey::ETag(_) => true,
+            });
+            update_cache_full(max_cache_bytes);
+        }
+        removed_path || removed_prefix || removed_suffix
+    }
+
+    /// Returns `true` if a rule matches `path`, which doesn't start with any
+    /// of `excluded_prefixes`.
+    fn matches(&self, path: &str, excluded_prefixes: &[String]) -> bool {
+        let rules = self
+            .0
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        path_matches(
+            path,
+            &rules.paths,
+            &rules.prefixes,
+            &rules.suffixes,
+            excluded_prefixes,
+        )
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, RuntimeRules> {
+        self.0
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Returns `true` if the cached bodies have reached `max_cache_bytes`, and
+/// reports it as [`CompressionStats::cache_degraded`].
+fn update_cache_full(max_cache_bytes: Option<usize>) -> bool {
+    let cache_full =
+        max_cache_bytes.is_some_and(|max_cache_bytes| CACHED_FILES.size() >= max_cache_bytes);
+    CompressionStats::global().set_cache_degraded(cache_full);
+    cache_full
+}
+
+/// Removes every occurrence of `rule` from `rules` and returns `true` if there
+/// was one.
+fn remove_all(rules: &mut Vec<String>, rule: &str) -> bool {
+    let len = rules.len();
+    rules.retain(|existing| existing != rule);
+    rules.len() != len
+}
+
+/// Identifies a response body stored in [`CACHED_FILES`].
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum CacheKey {
+    Path(String),
+    ETag(String),
+}
+
+/// Number of independently locked shards the cache is split into, so that
+/// concurrent requests for different paths rarely wait on the same lock.
+const CACHE_SHARDS: usize = 64;
+
+/// Largest body [`CachedCompression`] buffers on a cache miss unless configured
+/// otherwise.
+const DEFAULT_MAX_BUFFER_SIZE: usize = 16 * 1024 * 1024;
+
+type CachedBodies = HashMap<(CacheKey, CachedEncoding), Arc<[u8]>>;
+
+/// The compressed bodies cached by [`CachedCompression`] along with their
+/// total size.  Bodies are spread across shards by the hash of their key.
+///
+/// The shards are guarded by blocking locks: they are only ever held for a map
+/// lookup or insertion and never across an `.await`, which makes reads much
+/// cheaper than with an async lock, whose every acquisition goes through a
+/// semaphore.
+struct CachedFiles {
+    shards: [RwLock<CachedBodies>; CACHE_SHARDS],
+    size: AtomicUsize,
+}
+
+impl CacheKey {
+    /// Returns the name of the file `self` is persisted to when compressed with
+    /// `encoding`.  See [`CachedCompression::persist_to_dir`].
+    fn file_name(&self, encoding: CachedEncoding) -> String {
+        let (prefix, key) = match self {
+            CacheKey::Path(path) => ("", path),
+            CacheKey::ETag(etag) => ("etag-", etag),
+        };
+
+        let mut name = prefix.to_owned();
+        for (i, byte) in key.bytes().enumerate() {
+            match byte {
+                b'.' if i == 0 => name.push_str("_2E"),
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' => name.push(byte as char),
+                _ => {
+                    let _ = write!(name, "_{:02X}", byte);
+                }
+            }
+        }
+        if name.is_empty() {
+            name.push('_');
+        }
+
+        name.push_str(match encoding {
+            #[cfg(feature = "gzip")]
+            CachedEncoding::Gzip => ".gz",
+            #[cfg(feature = "brotli")]
+            CachedEncoding::Brotli => ".br",
+        });
+        name
+    }
+}
+
+impl Default for CachedFiles {
+    fn default() -> Self {
+        CachedFiles {
+            shards: std::array::from_fn(|_| RwLock::default()),
+            size: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl CachedFiles {
+    fn shard(&self, key: &(CacheKey, CachedEncoding)) -> &RwLock<CachedBodies> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % CACHE_SHARDS]
+    }
+
+    fn get(&self, key: &(CacheKey, CachedEncoding)) -> Option<Arc<[u8]>> {
+        self.shard(key)
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(key)
+            .cloned()
+    }
+
+    fn contains(&self, key: &(CacheKey, CachedEncoding)) -> bool {
+        self.shard(key)
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .contains_key(key)
+    }
+
+    fn insert(&self, key: (CacheKey, CachedEncoding), body: Arc<[u8]>) {
+        // The size is updated while holding the shard's lock so that it never goes below the
+        // size of the bodies being cleared.
+        let mut bodies = self
+            .shard(&key)
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.size.fetch_add(body.len(), Ordering::Relaxed);
+        if let Some(previous) = bodies.insert(key, body) {
+            self.size.fetch_sub(previous.len(), Ordering::Relaxed);
+        }
+    }
+
+    fn clear(&self) {
+        for shard in &self.shards {
+            let mut bodies = shard
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            let freed: usize = bodies.values().map(|body| body.len()).sum();
+            bodies.clear();
+            self.size.fetch_sub(freed, Ordering::Relaxed);
+        }
+    }
+
+    /// Drops the bodies whose key `keep` returns `false` for.
+    fn retain(&self, mut keep: impl FnMut(&CacheKey) -> bool) {
+        for shard in &self.shards {
+            let mut bodies = shard
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            bodies.retain(|(key, _), body| {
+                let kept = keep(key);
+                if !kept {
+                    self.size.fetch_sub(body.len(), Ordering::Relaxed);
+                }
+                kept
+            });
+        }
+    }
+
+    /// Returns the total size of the cached bodies.
+    fn size(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+}
+
 lazy_static! {
-    static ref EXCLUSIONS: Vec<MediaType> = vec![
-        MediaType::parse_flexible("application/gzip").unwrap(),
-        MediaType::parse_flexible("application/zip").unwrap(),
-        MediaType::parse_flexible("image/*").unwrap(),
-        MediaType::parse_flexible("video/*").unwrap(),
-        MediaType::parse_flexible("application/octet-stream").unwrap(),
-        MediaType::parse_flexible("text/event-stream").unwrap(),
-    ];
-    static ref CACHED_FILES: RwLock<HashMap<(String, CachedEncoding), &'static [u8]>> = {
-        let m = HashMap::new();
-        RwLock::new(m)
-    };
+    static ref CACHED_FILES: CachedFiles = CachedFiles::default();
 }
 
 /// Compresses all responses with Brotli or Gzip compression.
@@ -48,6 +430,8 @@ lazy_static! {
 /// - `video/*`
 /// - `application/octet-stream`
 /// - `text/event-stream`
+/// - `application/x-ndjson`
+/// - `application/json-seq`
 ///
 /// # Usage
 ///
@@ -68,162 +452,2576 @@ lazy_static! {
 pub struct Compression {
     pub level: Level,
     pub excluded_content_types: Vec<MediaType>,
+    /// `User-Agent` substrings for which brotli is never used, even if the
+    /// client advertises support for it.  See
+    /// [`Compression::prefer_gzip_for_user_agents`].
+    pub prefer_gzip_for_user_agents: Vec<String>,
+    /// Whether responses without a `Content-Type` are compressed.  Defaults to
+    /// [`NoContentTypePolicy::Compress`].
+    pub default_when_no_content_type: NoContentTypePolicy,
+    /// Paths that are compressed.  See [`Compression::included_paths`].
+    pub included_paths: Vec<String>,
+    /// Path prefixes that are compressed.  See
+    /// [`Compression::included_path_prefixes`].
+    pub included_path_prefixes: Vec<String>,
+    /// Parameters of the zstd encoder.  See [`Compression::zstd_params`].
+    pub zstd: ZstdParams,
+    /// Whether the fairing compresses responses at all.  See
+    /// [`Compression::disabled`].
+    pub enabled: bool,
+    /// Called whenever a response has been compressed.  See
+    /// [`Compression::on_compressed`].
+    pub on_compressed: Option<CompressionHook>,
+    /// Header read instead of `Accept-Encoding` when present.  See
+    /// [`Compression::accept_encoding_header`].
+    pub accept_encoding_header: Option<String>,
+    /// Whether every compressed and skipped response is logged.  See
+    /// [`Compression::log_compression`].
+    pub log_compression: bool,
+    /// Whether skipped responses are marked with `Content-Encoding: identity`.
+    /// See [`Compression::mark_identity_when_skipped`].
+    pub mark_identity_when_skipped: bool,
+    /// Encodes response bodies instead of the built-in encoders.  See
+    /// [`Compression::backend`].
+    pub backend: Option<Arc<dyn CompressionBackend>>,
+    /// Codings offered in addition to the built-in ones.  See
+    /// [`Compression::register_coding`].
+    pub custom_codings: Vec<CustomCoding>,
+    /// Called with every compressed response.  See
+    /// [`Compression::after_compression`].
+    pub after_compression: Option<ResponseHook>,
+    /// Names of routes returning server-sent events.  See
+    /// [`Compression::event_stream_routes`].
+    pub event_stream_routes: Vec<String>,
+    /// What to do about event stream routes that would be compressed.  See
+    /// [`Compression::event_stream_check`].
+    pub event_stream_check: EventStreamCheck,
+    /// Encodings the fairing may produce, in order of preference, or `None`
+    /// for every supported encoding.  See [`Compression::server_encodings`].
+    pub server_encodings: Option<Vec<Encoding>>,
+    /// Whether responses are compressed with `Transfer-Encoding` instead of
+    /// `Content-Encoding`.  See [`Compression::transfer_encoding`].
+    pub transfer_encoding: bool,
+    /// Name of the fairing.  See [`Compression::name`].
+    pub name: Option<&'static str>,
+    /// Header set to the size of compressed bodies before compression.  See
+    /// [`Compression::original_length_header`].
+    pub original_length_header: Option<String>,
+    /// Content types compressed while flushing every chunk.  See
+    /// [`Compression::flush_each_chunk_for`].
+    pub flushed_content_types: Vec<MediaType>,
+    /// Number of input bytes between two flushes of the encoder.  See
+    /// [`Compression::flush_every`].
+    pub flush_every: Option<usize>,
+    /// Whether only content types known to compress well are compressed.  See
+    /// [`Compression::compressible_defaults`].
+    pub compressible_only: bool,
+    /// Settings overridden for requests to specific hosts.  See
+    /// [`Compression::host_config`].
+    pub host_configs: Vec<(String, HostConfig)>,
+    /// Paths whose responses are never compressed.  See
+    /// [`Compression::exclude_path_patterns`].
+    #[cfg(feature = "regex")]
+    pub excluded_path_patterns: Vec<regex::Regex>,
+    /// Stops compression while the machine is overloaded.  See
+    /// [`Compression::load_breaker`].
+    pub load_breaker: Option<LoadBreaker>,
+    /// Header names added to the `Vary` header of compressed responses.  See
+    /// [`Compression::vary_headers`].
+    pub vary_headers: Vec<String>,
+    /// Paths whose responses are never compressed.  See
+    /// [`Compression::always_skip_paths`].
+    pub always_skip_paths: Vec<String>,
+    /// Whether responses without a `Content-Type` are left uncompressed.  See
+    /// [`Compression::skip_untyped`].
+    pub skip_untyped: bool,
+    /// Client networks whose requests are answered uncompressed.  See
+    /// [`Compression::skip_client_networks`].
+    pub skipped_client_networks: Vec<IpNetwork>,
+    /// Whether responses to proxied requests are compressed.  See
+    /// [`Compression::proxied`].
+    pub proxied: ProxiedPolicy,
+    /// Bodies smaller than this many bytes are left uncompressed.  See
+    /// [`CompressionConfig::min_size`].
+    pub min_size: Option<usize>,
+    /// Largest sized body compressed in full to be sent with a
+    /// `Content-Length`.  See [`Compression::max_buffered_body`].
+    pub max_buffered_body: usize,
+    /// The config the fairing was built from, managed at launch.  See
+    /// [`Compression::from_config`].
+    pub config: Option<CompressionConfig>,
 }
 
 impl Compression {
     /// Returns a fairing that compresses outgoing requests.  Uses default compression level and excluded content types.
     ///
     /// ## Example
-    /// To attach this fairing, simply call `attach` on the application's
-    /// `Rocket` instance with `Compression::fairing()`:
+    /// To attach this fairing, simply call `attach` on the application's
+    /// `Rocket` instance with `Compression::fairing()`:
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// rocket::build()
+    ///     // ...
+    ///     .attach(Compression::fairing())
+    ///     // ...
+    ///     # ;
+    /// ```
+    pub fn fairing() -> Compression {
+        Compression::with_level(Level::Default)
+    }
+
+    /// Returns a fairing that compresses outgoing requests with the specified
+    /// compression level.  `Compression::from(level)` and `level.into()` are
+    /// equivalent.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{Compression, Level};
+    ///
+    /// rocket::build()
+    ///    // ...
+    ///    .attach(Compression::with_level(Level::Fastest))
+    ///    // ...
+    ///    # ;
+    /// ```
+    pub fn with_level(level: Level) -> Compression {
+        Compression {
+            level,
+            excluded_content_types: EXCLUSIONS.clone(),
+            prefer_gzip_for_user_agents: Vec::new(),
+            default_when_no_content_type: NoContentTypePolicy::Compress,
+            included_paths: Vec::new(),
+            included_path_prefixes: Vec::new(),
+            zstd: ZstdParams::default(),
+            enabled: true,
+            on_compressed: None,
+            accept_encoding_header: None,
+            log_compression: false,
+            mark_identity_when_skipped: false,
+            backend: None,
+            custom_codings: Vec::new(),
+            after_compression: None,
+            event_stream_routes: Vec::new(),
+            event_stream_check: EventStreamCheck::Warn,
+            server_encodings: None,
+            transfer_encoding: false,
+            name: None,
+            original_length_header: None,
+            flushed_content_types: Vec::new(),
+            flush_every: None,
+            compressible_only: false,
+            host_configs: Vec::new(),
+            #[cfg(feature = "regex")]
+            excluded_path_patterns: Vec::new(),
+            load_breaker: None,
+            vary_headers: Vec::new(),
+            always_skip_paths: Vec::new(),
+            skip_untyped: false,
+            skipped_client_networks: Vec::new(),
+            proxied: ProxiedPolicy::Any,
+            min_size: None,
+            max_buffered_body: DEFAULT_MAX_BUFFERED_BODY,
+            config: None,
+        }
+    }
+
+    /// Returns a fairing following the settings of `config`.
+    ///
+    /// `config` is placed in managed state at launch, unless a config is
+    /// already managed, so that responders wrapped with
+    /// [`Compress::managed`](crate::Compress::managed) follow the same
+    /// settings.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{Compression, CompressionConfig, Encoding};
+    ///
+    /// let fairing = Compression::from_config(CompressionConfig {
+    ///     encodings: Some(vec![Encoding::Gzip]),
+    ///     ..CompressionConfig::default()
+    /// });
+    /// ```
+    pub fn from_config(config: CompressionConfig) -> Compression {
+        Compression {
+            excluded_content_types: config.excluded_content_types.clone(),
+            min_size: config.min_size,
+            server_encodings: config.encodings.clone(),
+            config: Some(config.clone()),
+            ..Compression::with_level(config.level)
+        }
+    }
+
+    /// Returns a fairing that compresses outgoing requests as fast as
+    /// possible, at the expense of compression ratio.
+    ///
+    /// This is a good choice for dynamic responses, which are compressed anew
+    /// for every request while they are streamed: a slow level costs CPU time on
+    /// every response and delays the first bytes reaching the client.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fastest();
+    /// ```
+    pub fn fastest() -> Compression {
+        Compression::with_level(Level::Fastest)
+    }
+
+    /// Returns a fairing that only compresses responses whose content type is
+    /// known to compress well, instead of compressing everything that isn't
+    /// excluded.  Responses with unknown and binary content types are left
+    /// uncompressed.
+    ///
+    /// The compressible types are, following the `compressible` flags of
+    /// mime-db:
+    ///
+    /// - `text/*`, except `text/event-stream`
+    /// - types with a `+json`, `+xml` or `+yaml` suffix, such as
+    ///   `image/svg+xml`
+    /// - JavaScript, JSON, XML, YAML, TOML, WebAssembly, GraphQL, form data,
+    ///   RTF, shell scripts and tar archives
+    /// - TrueType, OpenType and Embedded OpenType fonts
+    /// - BMP images and icons
+    ///
+    /// This table replaces the default exclusions, which would otherwise skip
+    /// SVG and BMP images along with every other `image/*` type.  Content
+    /// types registered as already compressed are still never compressed, and
+    /// responses without a `Content-Type` are handled according to
+    /// [`Compression::default_when_no_content_type`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// rocket::build()
+    ///     // ...
+    ///     .attach(Compression::compressible_defaults())
+    ///     // ...
+    ///     # ;
+    /// ```
+    pub fn compressible_defaults() -> Compression {
+        Compression {
+            compressible_only: true,
+            // Only kept so that the server-sent events check at ignition knows event streams are
+            // skipped.
+            excluded_content_types: vec![MediaType::EventStream],
+            ..Compression::fairing()
+        }
+    }
+
+    /// Returns a fairing that compresses outgoing requests as small as
+    /// possible, at the expense of CPU time.
+    ///
+    /// Compressing with the best level is many times slower than the default,
+    /// especially with brotli, so it is rarely worth it for responses that are
+    /// compressed on every request.  Prefer [`CachedCompression::best`] for
+    /// static assets, whose compressed bodies are only produced once.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::best();
+    /// ```
+    pub fn best() -> Compression {
+        Compression::with_level(Level::Best)
+    }
+
+    /// Compresses responses at the precise quality `level`, whose meaning
+    /// depends on the algorithm:
+    ///
+    /// - brotli: 0 (fastest) to 11 (smallest), where the default level is 4.
+    /// - gzip: 0 (stored, uncompressed) to 9 (smallest), where the default
+    ///   level is 6.
+    ///
+    /// Qualities outside of an algorithm's range are clamped to it, so that
+    /// e.g. `11` compresses brotli at 11 and gzip at 9.  zstd ignores the
+    /// level and uses [`ZstdParams`](crate::ZstdParams) instead.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::precise(5);
+    /// ```
+    pub fn precise(level: i32) -> Compression {
+        Compression::with_level(precise_level(level))
+    }
+
+    /// Returns a fairing that leaves all responses untouched.
+    ///
+    /// This allows attaching the fairing unconditionally and toggling
+    /// compression from configuration, e.g. to disable it in development or
+    /// behind a proxy that already compresses responses.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let compress = std::env::var("COMPRESS_RESPONSES").is_ok();
+    ///
+    /// rocket::build()
+    ///     // ...
+    ///     .attach(if compress {
+    ///         Compression::fairing()
+    ///     } else {
+    ///         Compression::disabled()
+    ///     })
+    ///     // ...
+    ///     # ;
+    /// ```
+    pub fn disabled() -> Compression {
+        Compression {
+            enabled: false,
+            ..Compression::fairing()
+        }
+    }
+
+    /// Replaces the default list of excluded content types with the provided list.
+    pub fn exlude_content_types(self, excluded_content_types: Vec<MediaType>) -> Self {
+        Compression {
+            excluded_content_types,
+            ..self
+        }
+    }
+
+    /// Returns a mutable reference to the list of excluded content types.
+    pub fn excluded_content_types(&mut self) -> &mut Vec<MediaType> {
+        &mut self.excluded_content_types
+    }
+
+    /// Never uses brotli for clients whose `User-Agent` header contains any of
+    /// the provided substrings.  Matching clients are served gzip if they
+    /// accept it and an uncompressed response otherwise.
+    ///
+    /// This is an interoperability workaround for clients that advertise `br`
+    /// in `Accept-Encoding` but fail to decode it correctly; it deliberately
+    /// deviates from standard content negotiation, so keep the list as narrow
+    /// as possible.  Matching is a case-sensitive substring search.  The list
+    /// is empty by default.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing()
+    ///     .prefer_gzip_for_user_agents(vec!["LegacyClient/1.".to_owned()]);
+    /// ```
+    pub fn prefer_gzip_for_user_agents(self, user_agents: Vec<String>) -> Self {
+        Compression {
+            prefer_gzip_for_user_agents: user_agents,
+            ..self
+        }
+    }
+
+    /// Sets whether responses without a `Content-Type` header are compressed.
+    ///
+    /// Untyped responses are compressed by default.  Applications serving
+    /// binary data without declaring a content type may prefer
+    /// [`NoContentTypePolicy::Skip`] to avoid wasting CPU on incompressible
+    /// bodies, or [`NoContentTypePolicy::Sniff`] to decide from the first bytes
+    /// of each body.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{Compression, NoContentTypePolicy};
+    ///
+    /// let fairing = Compression::fairing().no_content_type(NoContentTypePolicy::Skip);
+    /// ```
+    pub fn no_content_type(self, policy: NoContentTypePolicy) -> Self {
+        Compression {
+            default_when_no_content_type: policy,
+            ..self
+        }
+    }
+
+    /// Only compresses responses to requests for exactly the provided paths, or
+    /// for paths matching [`Compression::included_path_prefixes`].
+    ///
+    /// All paths are compressed when neither list is set.  Unlike
+    /// [`CachedCompression`], compressed responses are not cached.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing()
+    ///     .included_paths(vec!["/".to_owned(), "/about".to_owned()])
+    ///     .included_path_prefixes(vec!["/api/".to_owned()]);
+    /// ```
+    pub fn included_paths(self, included_paths: Vec<String>) -> Self {
+        Compression {
+            included_paths,
+            ..self
+        }
+    }
+
+    /// Only compresses responses to requests for paths starting with any of the
+    /// provided prefixes, or matching [`Compression::included_paths`].
+    ///
+    /// All paths are compressed when neither list is set.
+    pub fn included_path_prefixes(self, included_path_prefixes: Vec<String>) -> Self {
+        Compression {
+            included_path_prefixes,
+            ..self
+        }
+    }
+
+    /// Sets the parameters of the zstd encoder, which is used for clients
+    /// preferring zstd when the `zstd` feature is enabled.  `level` is ignored
+    /// for zstd responses.
+    ///
+    /// Defaults to zstd level 3 with the window size picked by zstd, which suits
+    /// dynamic responses; see [`ZstdParams`] for how to tune it for large
+    /// assets.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{Compression, Level, ZstdParams};
+    ///
+    /// let fairing = Compression::fairing().zstd_params(ZstdParams {
+    ///     level: Level::Best,
+    ///     window_log: Some(23),
+    /// });
+    /// ```
+    pub fn zstd_params(self, zstd: ZstdParams) -> Self {
+        Compression { zstd, ..self }
+    }
+
+    /// Calls `hook` with a [`CompressionEvent`] for every compressed response,
+    /// e.g. to feed compression outcomes into an application's own telemetry.
+    ///
+    /// Responses are compressed while they are streamed to the client, so the
+    /// hook is called once the compressed body has been read to the end.
+    /// Responses whose body isn't read to the end, e.g. because the client
+    /// disconnected, are not reported.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing().on_compressed(|event| {
+    ///     println!("{} compressed to {} bytes", event.path, event.compressed_size);
+    /// });
+    /// ```
+    pub fn on_compressed<F>(self, hook: F) -> Self
+    where
+        F: Fn(CompressionEvent) + Send + Sync + 'static,
+    {
+        Compression {
+            on_compressed: Some(Arc::new(hook)),
+            ..self
+        }
+    }
+
+    /// Calls `hook` with every response this fairing compresses, right after
+    /// its body and `Content-Encoding` have been set, e.g. to add a header or
+    /// adjust `Cache-Control` for compressed responses only.  Responses left
+    /// uncompressed are not passed to `hook`.
+    ///
+    /// Rocket runs response fairings in the order they were attached, so
+    /// fairings attached after this one see the changes made by `hook`, while
+    /// fairings attached before it don't.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket::http::Header;
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing().after_compression(|response| {
+    ///     response.set_header(Header::new("X-Compressed-By", "rocket"));
+    /// });
+    /// ```
+    pub fn after_compression<F>(self, hook: F) -> Self
+    where
+        F: Fn(&mut Response<'_>) + Send + Sync + 'static,
+    {
+        Compression {
+            after_compression: Some(Arc::new(hook)),
+            ..self
+        }
+    }
+
+    /// Reads the client's accepted encodings from the header `name` when the
+    /// request has it, falling back to `Accept-Encoding` otherwise.
+    ///
+    /// This is an interoperability option for proxies that strip or normalize
+    /// `Accept-Encoding` but forward the client's original value in another
+    /// header.  Only enable it behind such a proxy, since clients can set the
+    /// header themselves.  Only `Accept-Encoding` is used by default.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing =
+    ///     Compression::fairing().accept_encoding_header("X-Original-Accept-Encoding");
+    /// ```
+    pub fn accept_encoding_header(self, name: impl Into<String>) -> Self {
+        Compression {
+            accept_encoding_header: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Logs every compressed response at debug level with its path, encoding,
+    /// level, original and compressed sizes, ratio and elapsed time, as well as
+    /// the reason every skipped response was left uncompressed.
+    ///
+    /// Compressed responses are logged once their body has been read to the
+    /// end.  Disabled by default.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing().log_compression(true);
+    /// ```
+    pub fn log_compression(self, log_compression: bool) -> Self {
+        Compression {
+            log_compression,
+            ..self
+        }
+    }
+
+    /// Sets `Content-Encoding: identity` on responses left uncompressed because
+    /// they are too small, their content type is excluded or the client doesn't
+    /// accept any available encoding, for clients that distinguish an explicit
+    /// "not compressed" from an absent header.
+    ///
+    /// Responses that already have a `Content-Encoding`, responses without a
+    /// body and responses to requests for paths that aren't compressed are left
+    /// untouched.  Disabled by default.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing().mark_identity_when_skipped(true);
+    /// ```
+    pub fn mark_identity_when_skipped(self, mark_identity_when_skipped: bool) -> Self {
+        Compression {
+            mark_identity_when_skipped,
+            ..self
+        }
+    }
+
+    /// Encodes response bodies with `backend` instead of the built-in
+    /// encoders.  Only encodings supported by `backend` are negotiated; the
+    /// rest of the fairing's behavior is unchanged.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{Compression, DefaultBackend};
+    ///
+    /// let fairing = Compression::fairing().backend(DefaultBackend);
+    /// ```
+    pub fn backend(self, backend: impl CompressionBackend + 'static) -> Self {
+        Compression {
+            backend: Some(Arc::new(backend)),
+            ..self
+        }
+    }
+
+    /// Offers `coding` to clients in addition to the built-in codings.  See
+    /// [`CustomCoding`] for how it is negotiated.
+    ///
+    /// Custom codings are only used by this fairing, not by
+    /// [`CachedCompression`], which caches brotli and gzip bodies.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{Compression, CustomCoding};
+    ///
+    /// # use std::pin::Pin;
+    /// # use rocket::tokio::io::AsyncRead;
+    /// # use rocket_async_compression::Level;
+    /// # fn lz4_frame_encoder<'r>(
+    /// #     body: Pin<Box<dyn AsyncRead + Send + 'r>>,
+    /// #     _level: Level,
+    /// # ) -> Pin<Box<dyn AsyncRead + Send + 'r>> {
+    /// #     body
+    /// # }
+    /// let fairing = Compression::fairing().register_coding(CustomCoding::new("lz4f", lz4_frame_encoder));
+    /// ```
+    pub fn register_coding(mut self, coding: CustomCoding) -> Self {
+        self.custom_codings.push(coding);
+        self
+    }
+
+    /// Treats the routes named `names` as returning server-sent events when
+    /// checking routes at launch, in addition to routes declaring a
+    /// `text/event-stream` format.  See [`Compression::event_stream_check`].
+    ///
+    /// Route names default to the name of the handler function.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing().event_stream_routes(vec!["events".to_owned()]);
+    /// ```
+    pub fn event_stream_routes(self, names: Vec<String>) -> Self {
+        Compression {
+            event_stream_routes: names,
+            ..self
+        }
+    }
+
+    /// Sets what the fairing does when, at launch, it finds routes returning
+    /// server-sent events whose responses it would compress because
+    /// `text/event-stream` isn't excluded.  Defaults to
+    /// [`EventStreamCheck::Warn`].
+    ///
+    /// Event stream routes are routes declaring a `text/event-stream` format
+    /// and routes named with [`Compression::event_stream_routes`].
+    /// Nothing is reported when event streams are compressed with
+    /// [`Compression::flush_each_chunk_for`], which doesn't buffer them.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{Compression, EventStreamCheck};
+    ///
+    /// let fairing = Compression::fairing().event_stream_check(EventStreamCheck::Abort);
+    /// ```
+    pub fn event_stream_check(self, event_stream_check: EventStreamCheck) -> Self {
+        Compression {
+            event_stream_check,
+            ..self
+        }
+    }
+
+    /// Only produces the encodings in `encodings`, preferring them in the
+    /// order given when the client accepts several with the same quality.
+    /// This overrides the default preference order and the priorities of
+    /// [custom codings](Compression::register_coding).
+    ///
+    /// Encodings that aren't compiled in or supported by the
+    /// [backend](Compression::backend) are ignored.  By default every
+    /// supported encoding is produced.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{Compression, Encoding};
+    ///
+    /// // Serve gzip only, even though brotli is compiled in.
+    /// let fairing = Compression::fairing().server_encodings(vec![Encoding::Gzip]);
+    /// ```
+    pub fn server_encodings(self, encodings: Vec<Encoding>) -> Self {
+        Compression {
+            server_encodings: Some(encodings),
+            ..self
+        }
+    }
+
+    /// Compresses responses hop-by-hop with `Transfer-Encoding: gzip` instead
+    /// of `Content-Encoding`, for proxies that require the representation,
+    /// including its `Content-Encoding` and `ETag`, to be left untouched.
+    /// Compressed bodies are always streamed without a `Content-Length`, and
+    /// the server appends the `chunked` coding that frames them.  Disabled by
+    /// default.
+    ///
+    /// In this mode gzip is negotiated with the request's `TE` header instead
+    /// of `Accept-Encoding`, custom codings are not offered and skipped
+    /// responses are never marked with `Content-Encoding: identity`.  Responses
+    /// that already have a `Content-Encoding` or `Transfer-Encoding` are left
+    /// uncompressed.
+    ///
+    /// Transfer codings only exist in HTTP/1.1; HTTP/2 drops the
+    /// `Transfer-Encoding` header, leaving clients with an undecodable body.
+    /// Only enable this mode when the server is exclusively reached over
+    /// HTTP/1.1, e.g. behind such a proxy.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing().transfer_encoding(true);
+    /// ```
+    pub fn transfer_encoding(self, transfer_encoding: bool) -> Self {
+        Compression {
+            transfer_encoding,
+            ..self
+        }
+    }
+
+    /// Names the fairing, to tell several instances apart.  The name replaces
+    /// `Response compression` in Rocket's list of fairings and is added to the
+    /// fairing's log lines as `fairing=<name>`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing().name("api compression");
+    /// ```
+    pub fn name(self, name: &'static str) -> Self {
+        Compression {
+            name: Some(name),
+            ..self
+        }
+    }
+
+    /// Sets the header `name` on compressed responses to the size of their
+    /// body before compression, e.g. for CDNs or billing.  The header is only
+    /// set for bodies whose size is known up front; streamed bodies don't get
+    /// it.  Disabled by default.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{Compression, ORIGINAL_CONTENT_LENGTH};
+    ///
+    /// let fairing = Compression::fairing().original_length_header(ORIGINAL_CONTENT_LENGTH);
+    /// ```
+    pub fn original_length_header(self, name: impl Into<String>) -> Self {
+        Compression {
+            original_length_header: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Compresses responses whose `Content-Type` matches any of
+    /// `content_types` while flushing the encoder every time the body yields
+    /// data, like [`Compress::streaming`](crate::Compress::streaming).  `*` may
+    /// be used as the subtype to match a whole top-level type.
+    ///
+    /// This lets clients decode each chunk of a long-lived stream, such as a
+    /// server-sent event, as soon as it is sent instead of waiting for the
+    /// encoder to fill its buffer.  Matching responses are compressed even if
+    /// their content type is excluded, which is how `text/event-stream` is
+    /// opted into compression.  Flushing lowers the compression ratio, so
+    /// only list streaming content types.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket::http::MediaType;
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing().flush_each_chunk_for(vec![MediaType::EventStream]);
+    /// ```
+    pub fn flush_each_chunk_for(self, content_types: Vec<MediaType>) -> Self {
+        Compression {
+            flushed_content_types: content_types,
+            ..self
+        }
+    }
+
+    /// Flushes the encoder every `bytes` bytes of uncompressed input, so that
+    /// the compressed stream only depends on the response body and not on how
+    /// the handler splits it into chunks.  Identical bodies are then
+    /// compressed identically across runs, which makes golden-file testing of
+    /// streamed responses possible.
+    ///
+    /// Every flush ends a compressed block early, which slightly lowers the
+    /// compression ratio, the more so the smaller `bytes` is.  Content types
+    /// set with [`Compression::flush_each_chunk_for`] still flush after every
+    /// chunk.  Custom codings and backends ignore this setting.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing().flush_every(64 * 1024);
+    /// ```
+    pub fn flush_every(self, bytes: usize) -> Self {
+        Compression {
+            flush_every: Some(bytes),
+            ..self
+        }
+    }
+
+    /// Sets the largest sized body, in bytes, that is compressed in full
+    /// before being sent so that it keeps a `Content-Length`.  Larger bodies
+    /// are compressed while they are sent, with chunked transfer encoding.
+    /// Defaults to 64 KiB; `0` streams every body.
+    ///
+    /// Buffered bodies are held in memory in compressed form until they are
+    /// sent, so raising this limit raises the memory used by every concurrent
+    /// response.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing().max_buffered_body(256 * 1024);
+    /// ```
+    pub fn max_buffered_body(self, bytes: usize) -> Self {
+        Compression {
+            max_buffered_body: bytes,
+            ..self
+        }
+    }
+
+    /// Overrides whether responses are compressed, their level and the
+    /// excluded content types for requests whose `Host` matches `pattern`,
+    /// e.g. to serve several domains with different settings from a single
+    /// application.  Requests to other hosts use the fairing's own settings.
+    ///
+    /// `pattern` is either a host name, such as `api.example.com`, or a
+    /// wildcard matching its subdomains, such as `*.example.com`.  The port of
+    /// the request's host is ignored unless `pattern` includes one, as in
+    /// `localhost:8000`.  Host names are compared case-insensitively.  When
+    /// several patterns match, the first one registered is used.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{Compression, HostConfig, Level};
+    ///
+    /// let fairing = Compression::fairing()
+    ///     .host_config("www.example.com", HostConfig::with_level(Level::Best))
+    ///     .host_config("api.example.com", HostConfig::disabled());
+    /// ```
+    pub fn host_config(mut self, pattern: impl Into<String>, config: HostConfig) -> Self {
+        self.host_configs.push((pattern.into(), config));
+        self
+    }
+
+    /// Leaves responses to requests for paths matching any of `patterns`
+    /// uncompressed, e.g. `^/stream/` for streaming endpoints.  Patterns are
+    /// matched against the whole path, without the query, so anchor them to
+    /// match prefixes or suffixes only.  Exclusions take precedence over
+    /// [included paths](Compression::included_paths).
+    ///
+    /// Requires the `regex` feature.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use regex::Regex;
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing =
+    ///     Compression::fairing().exclude_path_patterns(vec![Regex::new("^/stream/").unwrap()]);
+    /// ```
+    #[cfg(feature = "regex")]
+    pub fn exclude_path_patterns(self, patterns: Vec<regex::Regex>) -> Self {
+        Compression {
+            excluded_path_patterns: patterns,
+            ..self
+        }
+    }
+
+    /// Leaves responses uncompressed while `breaker` is open, i.e. while the
+    /// machine is overloaded.  See [`LoadBreaker`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{Compression, LoadBreaker};
+    ///
+    /// # fn cpu_utilization() -> f64 { 0.0 }
+    /// let fairing = Compression::fairing().load_breaker(LoadBreaker::new(0.9, cpu_utilization));
+    /// ```
+    pub fn load_breaker(self, breaker: LoadBreaker) -> Self {
+        Compression {
+            load_breaker: Some(breaker),
+            ..self
+        }
+    }
+
+    /// Adds `names` to the `Vary` header of compressed responses, after
+    /// `Accept-Encoding`, for responses that also depend on other request
+    /// headers.  Names already listed in the response's `Vary` header aren't
+    /// repeated.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing().vary_headers(vec!["Accept-Language".to_owned()]);
+    /// ```
+    pub fn vary_headers(self, names: Vec<String>) -> Self {
+        Compression {
+            vary_headers: names,
+            ..self
+        }
+    }
+
+    /// Never compresses responses to requests for exactly one of `paths`,
+    /// whatever the other settings, e.g. for health checks and metrics
+    /// endpoints that are scraped often and return tiny bodies.  The paths are
+    /// checked before anything else.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing()
+    ///     .always_skip_paths(vec!["/healthz".to_owned(), "/metrics".to_owned()]);
+    /// ```
+    pub fn always_skip_paths(self, paths: Vec<String>) -> Self {
+        Compression {
+            always_skip_paths: paths,
+            ..self
+        }
+    }
+
+    /// Leaves responses without a `Content-Type` header uncompressed if
+    /// `skip_untyped` is `true`, whatever the
+    /// [`no_content_type`](Compression::no_content_type) policy.  Handlers
+    /// that don't declare a content type mostly pass binary data through.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing().skip_untyped(true);
+    /// ```
+    pub fn skip_untyped(self, skip_untyped: bool) -> Self {
+        Compression {
+            skip_untyped,
+            ..self
+        }
+    }
+
+    /// Leaves responses to clients in any of `networks` uncompressed, e.g.
+    /// health checks from a load balancer or services in the same datacenter,
+    /// which gain nothing from compression.
+    ///
+    /// The client's address is the one returned by
+    /// [`Request::client_ip`], which honors Rocket's `ip_header`
+    /// configuration, so clients behind a proxy are identified by the
+    /// forwarded address.  Responses to clients whose address is unknown are
+    /// compressed as usual.
+    ///
+    /// Rocket doesn't expose the HTTP version of requests, so compression can't
+    /// be gated on it.  Legacy HTTP/1.0 clients, or middleboxes, that mishandle
+    /// compressed responses can be served uncompressed by listing their
+    /// networks here instead.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compression;
+    ///
+    /// let fairing = Compression::fairing().skip_client_networks(vec![
+    ///     "10.0.0.0/8".parse().unwrap(),
+    ///     "127.0.0.1".parse().unwrap(),
+    /// ]);
+    /// ```
+    pub fn skip_client_networks(self, networks: Vec<IpNetwork>) -> Self {
+        Compression {
+            skipped_client_networks: networks,
+            ..self
+        }
+    }
+
+    /// Sets whether responses to requests that went through a proxy, i.e.
+    /// carrying a `Via` header, are compressed, like nginx's `gzip_proxied`.
+    /// Defaults to [`ProxiedPolicy::Any`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{Compression, ProxiedPolicy};
+    ///
+    /// let fairing = Compression::fairing().proxied(ProxiedPolicy::Uncacheable);
+    /// ```
+    pub fn proxied(self, proxied: ProxiedPolicy) -> Self {
+        Compression { proxied, ..self }
+    }
+
+    /// Returns `true` if `response` is left uncompressed because `request`
+    /// went through a proxy.
+    fn skips_proxied(&self, request: &Request<'_>, response: &Response<'_>) -> bool {
+        if !request.headers().contains("Via") {
+            return false;
+        }
+
+        match self.proxied {
+            ProxiedPolicy::Any => false,
+            ProxiedPolicy::Off => true,
+            ProxiedPolicy::Uncacheable => !uncacheable(response),
+        }
+    }
+
+    /// Returns `true` if the client sending `request` is in a skipped network.
+    fn skips_client(&self, request: &Request<'_>) -> bool {
+        match request.client_ip() {
+            Some(ip) => self
+                .skipped_client_networks
+                .iter()
+                .any(|network| network.contains(ip)),
+            None => false,
+        }
+    }
+
+    /// Returns the settings overridden for the host `request` was sent to, if
+    /// any.
+    fn host_config_for(&self, request: &Request<'_>) -> Option<&HostConfig> {
+        let host = request.host()?;
+        self.host_configs
+            .iter()
+            .find(|(pattern, _)| host_matches(pattern, host.domain().as_str(), host.port()))
+            .map(|(_, config)| config)
+    }
+
+    /// Returns `true` if responses with `content_type` are compressed while
+    /// flushing every chunk.
+    fn flushes(&self, content_type: &MediaType) -> bool {
+        self.flushed_content_types
+            .iter()
+            .any(|flushed| CompressionUtils::media_type_matches(flushed, content_type))
+    }
+
+    /// Returns the routes of `rocket` returning server-sent events.
+    fn event_stream_routes_of<'a>(&'a self, rocket: &'a Rocket<Build>) -> Vec<&'a Route> {
+        rocket
+            .routes()
+            .filter(|route| {
+                let declares_event_stream = route
+                    .format
+                    .as_ref()
+                    .is_some_and(|format| format.top() == "text" && format.sub() == "event-stream");
+                let named = route.name.as_ref().is_some_and(|name| {
+                    self.event_stream_routes.iter().any(|listed| listed == name)
+                });
+                declares_event_stream || named
+            })
+            .collect()
+    }
+
+    /// Returns `true` if responses to requests for `path` are compressed.
+    fn includes_path(&self, path: &str) -> bool {
+        #[cfg(feature = "regex")]
+        if self
+            .excluded_path_patterns
+            .iter()
+            .any(|pattern| pattern.is_match(path))
+        {
+            return false;
+        }
+
+        (self.included_paths.is_empty() && self.included_path_prefixes.is_empty())
+            || path_matches(
+                path,
+                &self.included_paths,
+                &self.included_path_prefixes,
+                &[],
+                &[],
+            )
+    }
+}
+
+/// Adds the URL, under `url_prefix`, of every compressible file in `dir` to
+/// `paths`.  See [`CachedCompression::from_static_dir`].
+fn static_dir_paths(url_prefix: &str, dir: &Path, paths: &mut Vec<String>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        // Request paths are percent-encoded, so file names are too.
+        let Some(name) = entry
+            .file_name()
+            .to_str()
+            .map(|name| RawStr::new(name).percent_encode().as_str().to_owned())
+        else {
+            continue;
+        };
+        let url = format!("{}/{}", url_prefix, name);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            static_dir_paths(&url, &path, paths)?;
+        } else if file_type.is_file() && is_compressible(&path) {
+            paths.push(url);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the precise `level`, warning if it is outside of the range of every
+/// algorithm.
+fn precise_level(level: i32) -> Level {
+    if !(0..=11).contains(&level) {
+        warn!(
+            "Compression level {} is outside of 0..=11 and will be clamped",
+            level
+        );
+    }
+    Level::Precise(level)
+}
+
+/// Settings of the [`Compression`] fairing overridden for requests to some
+/// hosts.  See [`Compression::host_config`].
+#[derive(Clone, Debug)]
+pub struct HostConfig {
+    pub enabled: bool,
+    pub level: Level,
+    pub excluded_content_types: Vec<MediaType>,
+}
+
+impl HostConfig {
+    /// Compresses responses at `level`, excluding the default content types.
+    pub fn with_level(level: Level) -> HostConfig {
+        HostConfig {
+            enabled: true,
+            level,
+            excluded_content_types: EXCLUSIONS.clone(),
+        }
+    }
+
+    /// Leaves all responses uncompressed.
+    pub fn disabled() -> HostConfig {
+        HostConfig {
+            enabled: false,
+            ..HostConfig::default()
+        }
+    }
+
+    /// Replaces the default list of excluded content types with the provided
+    /// list.
+    pub fn exclude_content_types(self, excluded_content_types: Vec<MediaType>) -> HostConfig {
+        HostConfig {
+            excluded_content_types,
+            ..self
+        }
+    }
+}
+
+impl Default for HostConfig {
+    fn default() -> Self {
+        HostConfig::with_level(Level::Default)
+    }
+}
+
+/// Whether [`Compression`] compresses responses to requests that went through
+/// a proxy, modeled after nginx's `gzip_proxied`.  Some intermediaries
+/// mishandle compressed responses, e.g. by caching one encoding and serving it
+/// to every client.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ProxiedPolicy {
+    /// Compress proxied responses like any other.
+    #[default]
+    Any,
+    /// Leave proxied responses uncompressed.
+    Off,
+    /// Only compress proxied responses that intermediaries can't cache: those
+    /// whose `Cache-Control` includes `no-cache`, `no-store` or `private`, or
+    /// whose `Expires` header is already in the past (`0` or `-1`).
+    Uncacheable,
+}
+
+/// Returns `true` if `response` can't be stored by shared caches.  See
+/// [`ProxiedPolicy::Uncacheable`].
+fn uncacheable(response: &Response<'_>) -> bool {
+    let cache_control = response
+        .headers()
+        .get("Cache-Control")
+        .flat_map(|value| value.split(','))
+        .map(|directive| directive.trim())
+        .any(|directive| {
+            let name = directive.split('=').next().unwrap_or_default().trim();
+            ["no-cache", "no-store", "private"]
+                .iter()
+                .any(|marker| name.eq_ignore_ascii_case(marker))
+        });
+    let expired = response
+        .headers()
+        .get("Expires")
+        .any(|expires| matches!(expires.trim(), "0" | "-1"));
+    cache_control || expired
+}
+
+/// Returns `true` if a request to `domain`, on `port` if specified, matches the
+/// host `pattern`.  See [`Compression::host_config`].
+fn host_matches(pattern: &str, domain: &str, port: Option<u16>) -> bool {
+    let (pattern, pattern_port) = match pattern.rsplit_once(':') {
+        Some((pattern, pattern_port)) => match pattern_port.parse::<u16>() {
+            Ok(pattern_port) => (pattern, Some(pattern_port)),
+            Err(_) => (pattern, None),
+        },
+        None => (pattern, None),
+    };
+    if pattern_port.is_some() && pattern_port != port {
+        return false;
+    }
+
+    let domain = domain.trim_end_matches('.');
+    match pattern.strip_prefix("*.") {
+        Some(parent) => domain
+            .len()
+            .checked_sub(parent.len() + 1)
+            .is_some_and(|dot| {
+                domain.as_bytes()[dot] == b'.' && domain[dot + 1..].eq_ignore_ascii_case(parent)
+            }),
+        None => domain.eq_ignore_ascii_case(pattern),
+    }
+}
+
+/// Same as [`Compression::fairing`], for code generic over `Default`
+/// fairings.
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::fairing()
+    }
+}
+
+/// Same as [`Compression::with_level`].
+///
+/// ```rust
+/// use rocket_async_compression::{Compression, Level};
+///
+/// let fairing: Compression = Level::Best.into();
+/// ```
+impl From<Level> for Compression {
+    fn from(level: Level) -> Self {
+        Compression::with_level(level)
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Compression {
+    fn info(&self) -> Info {
+        Info {
+            name: self.name.unwrap_or("Response compression"),
+            kind: Kind::Ignite | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let rocket = CompressionStats::manage(rocket);
+        let rocket = match &self.config {
+            Some(config) => config.manage(rocket),
+            None => rocket,
+        };
+        if !self.enabled
+            || self.event_stream_check == EventStreamCheck::Ignore
+            || self.flushes(&MediaType::EventStream)
+            || CompressionUtils::skip_encoding(
+                &Some(ContentType::EventStream),
+                &self.excluded_content_types,
+                self.default_when_no_content_type,
+            )
+        {
+            return Ok(rocket);
+        }
+
+        let routes: Vec<String> = self
+            .event_stream_routes_of(&rocket)
+            .into_iter()
+            .map(|route| match &route.name {
+                Some(name) => format!("{} ({})", route.uri, name),
+                None => route.uri.to_string(),
+            })
+            .collect();
+        if routes.is_empty() {
+            return Ok(rocket);
+        }
+
+        let routes = routes.join(", ");
+        if self.event_stream_check == EventStreamCheck::Abort {
+            error!(
+                "Compression would buffer the server-sent events of {}; exclude `text/event-stream` from compression",
+                routes
+            );
+            return Err(rocket);
+        }
+
+        warn!(
+            "Compression will buffer the server-sent events of {}; exclude `text/event-stream` from compression",
+            routes
+        );
+        Ok(rocket)
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let path = request.uri().path();
+        let host_config = self.host_config_for(request);
+        let enabled = host_config.map_or(self.enabled, |config| config.enabled);
+        if !enabled || !self.includes_path(path.as_str()) || self.skips_client(request) {
+            return;
+        }
+
+        // Responses for cached paths are left to `CachedCompression`, even if it runs after this
+        // fairing, so that they are served from its cache instead of being compressed every time.
+        if let Some(cached_paths) = request.rocket().state::<CachedPaths>() {
+            if cached_paths.contains(path.as_str()) {
+                return;
+            }
+        }
+
+        if self.skips_proxied(request, response) {
+            return;
+        }
+
+        // Streams opted into flushing are compressed regardless of the exclusions.
+        let flush = response
+            .content_type()
+            .is_some_and(|content_type| self.flushes(content_type.media_type()));
+        let exclusions: &[MediaType] = match host_config {
+            _ if flush => &[],
+            Some(config) => &config.excluded_content_types,
+            None => &self.excluded_content_types,
+        };
+        let level = host_config.map_or(self.level, |config| config.level);
+        let options = CompressionOptions {
+            prefer_gzip_for_user_agents: &self.prefer_gzip_for_user_agents,
+            max_buffered_body: self.max_buffered_body,
+            min_size: self.min_size,
+            no_content_type: if self.skip_untyped {
+                NoContentTypePolicy::Skip
+            } else {
+                self.default_when_no_content_type
+            },
+            flush_each_chunk: flush,
+            flush_every: self.flush_every,
+            compressible_only: self.compressible_only && !flush,
+            zstd: self.zstd,
+            on_compressed: self.on_compressed.as_ref(),
+            accept_encoding_header: self.accept_encoding_header.as_deref(),
+            log: self.log_compression,
+            mark_identity: self.mark_identity_when_skipped,
+            backend: self.backend.as_deref(),
+            custom_codings: &self.custom_codings,
+            after_compression: self.after_compression.as_ref(),
+            server_encodings: self.server_encodings.as_deref(),
+            fairing_name: self.name,
+            original_length_header: self.original_length_header.as_deref(),
+            load_breaker: self.load_breaker.as_ref(),
+            vary_headers: &self.vary_headers,
+            always_skip_paths: &self.always_skip_paths,
+            ..CompressionOptions::new(exclusions, level)
+        };
+        let options = if self.transfer_encoding {
+            CompressionOptions {
+                mark_identity: false,
+                custom_codings: &[],
+                server_encodings: Some(&[Encoding::Gzip]),
+                transfer_coding: true,
+                ..options
+            }
+        } else {
+            options
+        };
+        // File-backed bodies, such as those of `NamedFile`, are sized but their size is only known
+        // once computed by seeking, which is needed to skip empty and small files.  Seeking is left
+        // for responses that the cheaper checks would compress.
+        let compresses = matches!(
+            CompressionUtils::decide(request, response, &options),
+            Some(Ok(_))
+        );
+        if compresses {
+            response.body_mut().size().await;
+        }
+        // Peeking consumes the body, so untyped bodies are only sniffed once nothing else leaves
+        // them uncompressed.
+        let options = if options.no_content_type == NoContentTypePolicy::Sniff
+            && matches!(
+                CompressionUtils::decide(request, response, &options),
+                Some(Ok(_))
+            ) {
+            CompressionOptions {
+                no_content_type: sniff::resolve(response).await,
+                ..options
+            }
+        } else {
+            options
+        };
+        super::CompressionUtils::compress_response(request, response, &options);
+    }
+}
+
+/// Compresses all responses with Brotli or Gzip compression. Caches compressed
+/// response bodies in memory for selected file types/path suffixes, useful for
+/// compressing large compiled JS/CSS files, OTF font packs, etc.  Note that all
+/// cached files are held in memory indefinitely.
+///
+/// Compression is done in the same manner as the [`Compression`](Compression)
+/// fairing.
+///
+/// `CachedCompression` can be attached alongside [`Compression`], e.g. to
+/// cache static assets while compressing everything else on the fly.  In that
+/// case [`Compression`] leaves responses for cached paths alone, whichever
+/// fairing is attached first, so they are compressed once and served from the
+/// cache.
+///
+/// The two fairings' levels are independent.  Since cached bodies are only
+/// compressed once, a common layering is to cache static assets at the best
+/// level while compressing dynamic responses at a faster one:
+///
+/// ```rust
+/// use rocket_async_compression::{CachedCompression, Compression, Level};
+///
+/// rocket::build()
+///     // Brotli quality 11 and gzip level 9, paid once per asset.
+///     .attach(
+///         CachedCompression::path_suffix_fairing(vec![".js".to_owned(), ".css".to_owned()])
+///             .best(),
+///     )
+///     // Brotli and gzip level 5 for everything else.
+///     .attach(Compression::with_level(Level::Precise(5)));
+/// ```
+///
+/// The cache is shared by every `CachedCompression` fairing and isn't keyed by
+/// level, so fairings with different levels should cache disjoint paths.
+///
+/// # Usage
+///
+/// Attach the compression [fairing](/rocket/fairing/) to your Rocket
+/// application:
+///
+/// ```rust
+/// use rocket_async_compression::CachedCompression;
+///
+/// rocket::build()
+///     // ...
+///     .attach(CachedCompression {
+///         cached_paths: vec![
+///             "".to_owned(),
+///             "/".to_owned(),
+///             "/about".to_owned(),
+///             "/people".to_owned(),
+///             "/posts".to_owned(),
+///             "/events".to_owned(),
+///             "/groups".to_owned(),
+///         ],
+///         cached_path_prefixes: vec!["/user/".to_owned(), "/g/".to_owned(), "/p/".to_owned()],
+///         cached_path_suffixes: vec![".otf".to_owned(), "main.dart.js".to_owned()],
+///         ..Default::default()
+///     })
+///     // ...
+///     # ;
+/// ```
+///
+///
+#[derive(Default)]
+pub struct CachedCompression {
+    pub cached_paths: Vec<String>,
+    pub cached_path_prefixes: Vec<String>,
+    pub cached_path_suffixes: Vec<String>,
+    pub excluded_path_prefixes: Vec<String>,
+    pub level: Option<Level>,
+    /// Level of gzip bodies, overriding `level`.  See
+    /// [`CachedCompression::gzip_level`].
+    pub gzip_level: Option<Level>,
+    /// Level of brotli bodies, overriding `level`.  See
+    /// [`CachedCompression::brotli_level`].
+    pub brotli_level: Option<Level>,
+    /// Encodings that are all compressed and cached together the first time a
+    /// cached path is requested, in addition to the one the client negotiated.
+    /// See [`CachedCompression::eager_encodings`].
+    pub eager_encodings: Vec<Encoding>,
+    /// Whether cached bodies are keyed by the response's strong `ETag` rather
+    /// than by path.  See [`CachedCompression::key_by_etag`].
+    pub key_by_etag: bool,
+    /// Called whenever a response has been compressed or served from the
+    /// cache.  See [`CachedCompression::on_compressed`].
+    pub on_compressed: Option<CompressionHook>,
+    /// Header read instead of `Accept-Encoding` when present.  See
+    /// [`CachedCompression::accept_encoding_header`].
+    pub accept_encoding_header: Option<String>,
+    /// Whether every compressed and skipped response is logged.  See
+    /// [`CachedCompression::log_compression`].
+    pub log_compression: bool,
+    /// Whether compressed bodies are checked against the original in debug
+    /// builds.  See [`CachedCompression::debug_verify`].
+    pub debug_verify: bool,
+    /// Soft ceiling on the total size of the cached bodies.  See
+    /// [`CachedCompression::max_cache_bytes`].
+    pub max_cache_bytes: Option<usize>,
+    /// Largest compressed body cached.  See
+    /// [`CachedCompression::max_cache_entry_bytes`].
+    pub max_cache_entry_bytes: Option<usize>,
+    /// Directory compressed bodies are persisted to.  See
+    /// [`CachedCompression::persist_to_dir`].
+    pub cache_dir: Option<PathBuf>,
+    /// Largest compressed to original size ratio of cached bodies.  See
+    /// [`CachedCompression::min_cache_ratio`].
+    pub min_cache_ratio: Option<f32>,
+    /// Bodies smaller than this many bytes are neither compressed nor cached.
+    /// See [`CachedCompression::min_size`].
+    pub min_size: Option<usize>,
+    /// Largest body buffered on cache misses.  See
+    /// [`CachedCompression::max_buffer_size`].
+    pub max_buffer_size: Option<usize>,
+    /// What to serve when a response fails to compress.  See
+    /// [`CachedCompression::failure_policy`].
+    pub failure_policy: FailurePolicy,
+    /// Name of the fairing.  See [`CachedCompression::name`].
+    pub name: Option<&'static str>,
+    /// Header set to the size of compressed bodies before compression.  See
+    /// [`CachedCompression::original_length_header`].
+    pub original_length_header: Option<String>,
+    /// Stops compressing cache misses while the machine is overloaded.  See
+    /// [`CachedCompression::load_breaker`].
+    pub load_breaker: Option<LoadBreaker>,
+    /// Header names added to the `Vary` header of compressed responses.  See
+    /// [`CachedCompression::vary_headers`].
+    pub vary_headers: Vec<String>,
+    /// Paths whose responses are never compressed.  See
+    /// [`CachedCompression::always_skip_paths`].
+    pub always_skip_paths: Vec<String>,
+    /// Whether responses without a `Content-Type` are neither compressed nor
+    /// cached.  See [`CachedCompression::skip_untyped`].
+    pub skip_untyped: bool,
+    /// Content types that are neither compressed nor cached, or `None` for the
+    /// default exclusions.  See [`CachedCompression::exclude_content_types`].
+    pub excluded_content_types: Option<Vec<MediaType>>,
+    /// Rules added and removed while the application is running.  See
+    /// [`CachedCompression::rules`].
+    pub runtime_rules: CachedPathRules,
+    /// Algorithm of the digest headers set on compressed responses.  See
+    /// [`CachedCompression::digest`].
+    #[cfg(feature = "digest")]
+    pub digest: Option<crate::DigestAlgorithm>,
+    /// Size gzip bodies are padded to a multiple of.  See
+    /// [`CachedCompression::pad_to_bucket`].
+    pub pad_to_bucket: Option<usize>,
+}
+
+/// What [`CachedCompression`] serves when reading or compressing a response
+/// body fails.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Serve the original, uncompressed body, which is buffered before being
+    /// compressed.  If reading the
+    /// original body is what failed, the error is served as with
+    /// [`FailurePolicy::ServeError`].
+    #[default]
+    ServeIdentity,
+    /// Serve a body that fails with the error once read, which clients see as
+    /// an interrupted response.
+    ServeError,
+    /// Replace the response with an empty `500 Internal Server Error`.
+    Status500,
+}
+
+/// Returns a fairing compressing cached responses at `level`.  No path is
+/// cached until one is configured, e.g. by setting
+/// [`CachedCompression::cached_path_suffixes`].
+///
+/// ```rust
+/// use rocket_async_compression::{CachedCompression, Level};
+///
+/// let fairing = CachedCompression {
+///     cached_path_suffixes: vec![".js".to_owned()],
+///     ..Level::Best.into()
+/// };
+/// ```
+impl From<Level> for CachedCompression {
+    fn from(level: Level) -> Self {
+        CachedCompression {
+            level: Some(level),
+            ..Default::default()
+        }
+    }
+}
+
+impl CachedCompression {
+    /// Caches only the specific paths provided.
+    pub fn exact_path_fairing(cached_paths: Vec<String>) -> CachedCompression {
+        CachedCompression {
+            cached_paths,
+            ..Default::default()
+        }
+    }
+
+    /// Caches all paths with the provided suffixes.
+    pub fn path_suffix_fairing(cached_path_suffixes: Vec<String>) -> CachedCompression {
+        CachedCompression {
+            cached_path_suffixes,
+            ..Default::default()
+        }
+    }
+
+    /// Caches all paths with the provided suffixes.
+    pub fn path_prefix_fairing(cached_path_prefixes: Vec<String>) -> CachedCompression {
+        CachedCompression {
+            cached_path_prefixes,
+            ..Default::default()
+        }
+    }
+
+    /// Caches compressed responses for all paths except those with the excluded prefixes.
+    pub fn excluded_path_prefix_fairing(excluded_path_prefixes: Vec<String>) -> CachedCompression {
+        CachedCompression {
+            cached_path_prefixes: vec!["".to_string()],
+            excluded_path_prefixes,
+            ..Default::default()
+        }
+    }
+
+    /// Caches the URL of every compressible file in `dir`, recursively, as
+    /// served under `url_prefix`, e.g. by a `FileServer` mounted there.
+    ///
+    /// The directory is only listed, not compressed: responses are compressed
+    /// and cached as they are requested.  Files are compressible if their
+    /// extension maps to a content type outside of the default exclusions, as
+    /// with [`precompress_dir`](crate::precompress_dir).  Files added later
+    /// aren't cached until the fairing is built again.  Other rules can be
+    /// added with the struct update syntax.
+    ///
+    /// Fails if `dir` can't be listed, e.g. because it doesn't exist.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use rocket::fs::FileServer;
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// # fn build() -> std::io::Result<rocket::Rocket<rocket::Build>> {
+    /// let fairing = CachedCompression {
+    ///     cached_path_prefixes: vec!["/docs/".to_owned()],
+    ///     ..CachedCompression::from_static_dir("/static", "static")?
+    /// };
+    ///
+    /// Ok(rocket::build()
+    ///     .mount("/static", FileServer::from("static"))
+    ///     .attach(fairing))
+    /// # }
+    /// ```
+    pub fn from_static_dir(
+        url_prefix: &str,
+        dir: impl AsRef<Path>,
+    ) -> std::io::Result<CachedCompression> {
+        let dir = dir.as_ref();
+        let mut cached_paths = Vec::new();
+        static_dir_paths(url_prefix.trim_end_matches('/'), dir, &mut cached_paths).map_err(
+            |err| {
+                std::io::Error::new(
+                    err.kind(),
+                    format!(
+                        "failed to list static directory `{}`: {}",
+                        dir.display(),
+                        err
+                    ),
+                )
+            },
+        )?;
+        Ok(CachedCompression::exact_path_fairing(cached_paths))
+    }
+
+    /// Compresses cached responses as fast as possible, at the expense of
+    /// compression ratio.
+    ///
+    /// Since cached bodies are only compressed once, this is only worth it if
+    /// the cached responses change often or the first response for each path
+    /// must be served quickly.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()]).fastest();
+    /// ```
+    pub fn fastest(self) -> CachedCompression {
+        CachedCompression {
+            level: Some(Level::Fastest),
+            ..self
+        }
+    }
+
+    /// Compresses cached responses as small as possible, at the expense of CPU
+    /// time.
+    ///
+    /// This is a sensible choice for static assets: the slow compression is
+    /// paid once per path and encoding, while every later response is served
+    /// from the cache at the smallest size.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()]).best();
+    /// ```
+    pub fn best(self) -> CachedCompression {
+        CachedCompression {
+            level: Some(Level::Best),
+            ..self
+        }
+    }
+
+    /// Compresses cached responses at the precise quality `level`, whose meaning
+    /// depends on the algorithm:
+    ///
+    /// - brotli: 0 (fastest) to 11 (smallest), where the default level is 4.
+    /// - gzip: 0 (stored, uncompressed) to 9 (smallest), where the default
+    ///   level is 6.
+    ///
+    /// Qualities outside of an algorithm's range are clamped to it, so that
+    /// e.g. `11` compresses brotli at 11 and gzip at 9.  zstd ignores the
+    /// level and uses [`ZstdParams`](crate::ZstdParams) instead.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()]).precise(9);
+    /// ```
+    pub fn precise(self, level: i32) -> CachedCompression {
+        CachedCompression {
+            level: Some(precise_level(level)),
+            ..self
+        }
+    }
+
+    /// Compresses cached gzip bodies at `level`, regardless of the level set
+    /// with [`CachedCompression::fastest`] or [`CachedCompression::best`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{CachedCompression, Level};
+    ///
+    /// // Brotli quality 11 and gzip level 6.
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()])
+    ///     .best()
+    ///     .gzip_level(Level::Precise(6));
+    /// ```
+    pub fn gzip_level(self, level: Level) -> CachedCompression {
+        CachedCompression {
+            gzip_level: Some(level),
+            ..self
+        }
+    }
+
+    /// Compresses cached brotli bodies at `level`, regardless of the level set
+    /// with [`CachedCompression::fastest`] or [`CachedCompression::best`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{CachedCompression, Level};
+    ///
+    /// // Brotli quality 11 and gzip at the default level.
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()])
+    ///     .brotli_level(Level::Best);
+    /// ```
+    pub fn brotli_level(self, level: Level) -> CachedCompression {
+        CachedCompression {
+            brotli_level: Some(level),
+            ..self
+        }
+    }
+
+    /// Returns the level bodies compressed with `encoding` are compressed at.
+    fn level_for(&self, encoding: CachedEncoding) -> Level {
+        let level = match encoding {
+            #[cfg(feature = "gzip")]
+            CachedEncoding::Gzip => self.gzip_level,
+            #[cfg(feature = "brotli")]
+            CachedEncoding::Brotli => self.brotli_level,
+        };
+        level.or(self.level).unwrap_or(Level::Default)
+    }
+
+    /// Builds the fairing from the `cached_compression` table of a Rocket
+    /// configuration, so that the cached paths can differ between profiles.
+    ///
+    /// The table accepts the keys `cached_paths`, `cached_path_prefixes`,
+    /// `cached_path_suffixes` and `excluded_path_prefixes`, lists of strings
+    /// matching the fields of the same names, and `level`, one of
+    /// `"default"`, `"best"`, `"fastest"` or a numeric quality.  All keys are
+    /// optional; unknown keys are rejected so that typos are reported.  If the
+    /// table is missing, the fairing caches nothing.  Errors are boxed, as
+    /// `figment::Error` is large.
+    ///
+    /// ```toml
+    /// [default.cached_compression]
+    /// cached_path_suffixes = [".js", ".css"]
+    /// excluded_path_prefixes = ["/api/"]
+    /// level = "best"
+    /// ```
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let rocket = rocket::build();
+    /// let fairing = CachedCompression::from_figment(rocket.figment())
+    ///     .expect("invalid `cached_compression` configuration");
+    /// rocket.attach(fairing)
+    ///     // ...
+    ///     # ;
+    /// ```
+    pub fn from_figment(figment: &Figment) -> Result<CachedCompression, Box<figment::Error>> {
+        crate::config::cached_compression_from_figment(figment)
+    }
+
+    /// Sets `Content-Digest` and `Repr-Digest` headers (RFC 9530) with the
+    /// `algorithm` digest of the bodies served compressed from the cache or
+    /// compressed on a cache miss.
+    ///
+    /// Both digests cover the compressed bytes: a representation's content
+    /// coding is part of its data, and cached responses are always complete.
+    /// Responses compressed while streaming, because the cache is full or
+    /// their body is too large to buffer, get no digest since their bytes are
+    /// never all in hand.  Digests are computed for every response, which
+    /// costs a hash of the compressed body.
+    ///
+    /// Only available with the `digest` feature.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{CachedCompression, DigestAlgorithm};
+    ///
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()])
+    ///     .digest(DigestAlgorithm::Sha256);
+    /// ```
+    #[cfg(feature = "digest")]
+    pub fn digest(self, algorithm: crate::DigestAlgorithm) -> CachedCompression {
+        CachedCompression {
+            digest: Some(algorithm),
+            ..self
+        }
+    }
+
+    /// Sets the digest headers of `response`, whose body is `body`.
+    #[cfg(feature = "digest")]
+    fn set_digest(&self, response: &mut Response<'_>, body: &[u8]) {
+        if let Some(algorithm) = self.digest {
+            let field = algorithm.field(body);
+            response.set_header(Header::new("Repr-Digest", field.clone()));
+            response.set_header(Header::new("Content-Digest", field));
+        }
+    }
+
+    /// Pads compressed gzip bodies so that their length is a multiple of
+    /// `size` bytes, e.g. 256, as a deterministic mitigation of BREACH-style
+    /// attacks: response sizes only reveal which bucket they fall in.
+    ///
+    /// The padding is an extra field in the gzip header, which decoders skip,
+    /// so the decompressed body is unchanged whatever its content type.
+    ///
+    /// **Only gzip is served while padding is enabled.**  Brotli has no such
+    /// field, so clients that don't accept gzip are served uncompressed bodies,
+    /// which reveal nothing through compression.  Padding is applied to bodies
+    /// compressed on a cache miss, and thus to those served from the cache,
+    /// but **not** to bodies too large to buffer or that don't fit in a full
+    /// cache, which are compressed while streaming: keep such bodies off the
+    /// paths where the mitigation matters.  Buckets larger than 64 KiB can't
+    /// always be reached and leave bodies unpadded.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let fairing = CachedCompression::path_prefix_fairing(vec!["/account/".to_owned()])
+    ///     .pad_to_bucket(256);
+    /// ```
+    pub fn pad_to_bucket(self, size: usize) -> CachedCompression {
+        CachedCompression {
+            pad_to_bucket: Some(size),
+            ..self
+        }
+    }
+
+    /// Pads `body`, compressed with `encoding`, to the bucket size, if any.
+    fn pad(&self, encoding: CachedEncoding, body: Vec<u8>) -> Vec<u8> {
+        match (self.pad_to_bucket, encoding) {
+            #[cfg(feature = "gzip")]
+            (Some(bucket), CachedEncoding::Gzip) => crate::padding::pad_gzip(body, bucket),
+            _ => body,
+        }
+    }
+
+    /// Returns `true` if bodies compressed with `encoding` may be served, which
+    /// only gzip bodies may while padding is enabled.
+    fn serves(&self, encoding: CachedEncoding) -> bool {
+        match encoding {
+            #[cfg(feature = "gzip")]
+            CachedEncoding::Gzip => true,
+            #[allow(unreachable_patterns)]
+            _ => self.pad_to_bucket.is_none(),
+        }
+    }
+
+    /// Returns a handle to add and remove cached path rules while the
+    /// application is running.  See [`CachedPathRules`].
+    pub fn rules(&self) -> CachedPathRules {
+        self.runtime_rules.clone()
+    }
+
+    /// Caches `Vec<&str>` to `Vec<String>`.
+    pub fn static_paths(paths: Vec<&str>) -> Vec<String> {
+        paths.into_iter().map(Into::into).collect()
+    }
+
+    /// Compresses and caches cached paths with all of the provided encodings as
+    /// soon as they are first requested, rather than only with the encoding
+    /// negotiated by the first client.
+    ///
+    /// Later clients preferring a different encoding are then served from the
+    /// cache as well, at the cost of some extra CPU time on the first request.
+    /// Encodings other than gzip and brotli are ignored.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{CachedCompression, Encoding};
+    ///
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()])
+    ///     .eager_encodings(vec![Encoding::Brotli, Encoding::Gzip]);
+    /// ```
+    pub fn eager_encodings(self, eager_encodings: Vec<Encoding>) -> CachedCompression {
+        CachedCompression {
+            eager_encodings,
+            ..self
+        }
+    }
+
+    /// Keys cached bodies by the response's `ETag` instead of the request path
+    /// when the response has a strong `ETag`.
+    ///
+    /// Content-addressed responses often serve identical bytes at several URLs,
+    /// e.g. the same hashed asset under versioned paths or aliases.  Keyed by
+    /// path, each URL holds its own copy of the compressed body; keyed by
+    /// `ETag`, they all share a single copy, which can noticeably reduce the
+    /// memory used by asset-heavy sites.  Responses without an `ETag`, or with
+    /// a weak one, are keyed by path.
+    ///
+    /// Only enable this if every strong `ETag` produced by the application
+    /// uniquely identifies its body, as required by HTTP.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let fairing = CachedCompression::path_prefix_fairing(vec!["/assets/".to_owned()])
+    ///     .key_by_etag(true);
+    /// ```
+    pub fn key_by_etag(self, key_by_etag: bool) -> CachedCompression {
+        CachedCompression {
+            key_by_etag,
+            ..self
+        }
+    }
+
+    /// Calls `hook` with a [`CompressionEvent`] for every response compressed
+    /// or served from the cache.  Events for cache hits don't carry the
+    /// original size of the body.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()])
+    ///     .on_compressed(|event| println!("{:?}", event));
+    /// ```
+    pub fn on_compressed<F>(self, hook: F) -> CachedCompression
+    where
+        F: Fn(CompressionEvent) + Send + Sync + 'static,
+    {
+        CachedCompression {
+            on_compressed: Some(Arc::new(hook)),
+            ..self
+        }
+    }
+
+    /// Reads the client's accepted encodings from the header `name` when the
+    /// request has it, like [`Compression::accept_encoding_header`].
+    pub fn accept_encoding_header(self, name: impl Into<String>) -> CachedCompression {
+        CachedCompression {
+            accept_encoding_header: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Names the fairing, to tell several instances apart.  The name replaces
+    /// `Cached response compression` in Rocket's list of fairings and is added
+    /// to the fairing's log lines as `fairing=<name>`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let fairing =
+    ///     CachedCompression::path_suffix_fairing(vec![".otf".to_owned()]).name("fonts");
+    /// ```
+    pub fn name(self, name: &'static str) -> CachedCompression {
+        CachedCompression {
+            name: Some(name),
+            ..self
+        }
+    }
+
+    /// Sets the header `name` on compressed responses to the size of their
+    /// body before compression, e.g. for CDNs or billing.  Responses
+    /// compressed while streaming because the cache is full only get the
+    /// header if their body's size is known up front.  Disabled by default.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{CachedCompression, ORIGINAL_CONTENT_LENGTH};
+    ///
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()])
+    ///     .original_length_header(ORIGINAL_CONTENT_LENGTH);
+    /// ```
+    pub fn original_length_header(self, name: impl Into<String>) -> CachedCompression {
+        CachedCompression {
+            original_length_header: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Leaves cache misses uncompressed, and uncached, while `breaker` is
+    /// open, i.e. while the machine is overloaded.  Cached responses are still
+    /// served compressed since that costs no compression.  See
+    /// [`LoadBreaker`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{CachedCompression, LoadBreaker};
+    ///
+    /// # fn cpu_utilization() -> f64 { 0.0 }
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()])
+    ///     .load_breaker(LoadBreaker::new(0.9, cpu_utilization));
+    /// ```
+    pub fn load_breaker(self, breaker: LoadBreaker) -> CachedCompression {
+        CachedCompression {
+            load_breaker: Some(breaker),
+            ..self
+        }
+    }
+
+    /// Adds `names` to the `Vary` header of compressed responses, after
+    /// `Accept-Encoding`, for responses that also depend on other request
+    /// headers.  Names already listed in the response's `Vary` header aren't
+    /// repeated.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()])
+    ///     .vary_headers(vec!["Accept-Language".to_owned()]);
+    /// ```
+    pub fn vary_headers(self, names: Vec<String>) -> CachedCompression {
+        CachedCompression {
+            vary_headers: names,
+            ..self
+        }
+    }
+
+    /// Never compresses responses to requests for exactly one of `paths`,
+    /// whatever the other settings, e.g. for health checks and metrics
+    /// endpoints that are scraped often and return tiny bodies.  The paths are
+    /// checked before anything else.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let fairing = CachedCompression::path_prefix_fairing(vec!["/".to_owned()])
+    ///     .always_skip_paths(vec!["/healthz".to_owned(), "/metrics".to_owned()]);
+    /// ```
+    pub fn always_skip_paths(self, paths: Vec<String>) -> CachedCompression {
+        CachedCompression {
+            always_skip_paths: paths,
+            ..self
+        }
+    }
+
+    /// Neither compresses nor caches responses without a `Content-Type` header
+    /// if `skip_untyped` is `true`.  Untyped responses are compressed by
+    /// default.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let fairing = CachedCompression::path_prefix_fairing(vec!["/".to_owned()]).skip_untyped(true);
+    /// ```
+    pub fn skip_untyped(self, skip_untyped: bool) -> CachedCompression {
+        CachedCompression {
+            skip_untyped,
+            ..self
+        }
+    }
+
+    /// Replaces the default list of excluded content types with the provided
+    /// list for this fairing only.  `*` may be used as the subtype to exclude
+    /// a whole top-level type.  Content types whose bodies are already
+    /// compressed are never compressed, whatever the list.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket::http::MediaType;
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// // Cache compressed exports, which are served as `application/octet-stream`.
+    /// let fairing = CachedCompression::path_prefix_fairing(vec!["/exports/".to_owned()])
+    ///     .exclude_content_types(vec![MediaType::new("image", "*"), MediaType::new("video", "*")]);
+    /// ```
+    pub fn exclude_content_types(
+        self,
+        excluded_content_types: Vec<MediaType>,
+    ) -> CachedCompression {
+        CachedCompression {
+            excluded_content_types: Some(excluded_content_types),
+            ..self
+        }
+    }
+
+    /// Sets what is served when reading or compressing a response body fails.
+    /// Defaults to [`FailurePolicy::ServeIdentity`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{CachedCompression, FailurePolicy};
+    ///
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()])
+    ///     .failure_policy(FailurePolicy::Status500);
+    /// ```
+    pub fn failure_policy(self, failure_policy: FailurePolicy) -> CachedCompression {
+        CachedCompression {
+            failure_policy,
+            ..self
+        }
+    }
+
+    /// Only caches responses whose compressed size is at most `ratio` times
+    /// their original size, e.g. `0.9` to require a 10% reduction.
+    ///
+    /// Responses that compress worse than that are served uncompressed and
+    /// aren't cached, so that near-incompressible data doesn't take up cache
+    /// memory for little bandwidth benefit.  They are compressed again on
+    /// every request.
+    ///
+    /// ## Example
     ///
     /// ```rust
-    /// use rocket_async_compression::Compression;
+    /// use rocket_async_compression::CachedCompression;
     ///
-    /// rocket::build()
-    ///     // ...
-    ///     .attach(Compression::fairing())
-    ///     // ...
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()])
+    ///     .min_cache_ratio(0.9);
     /// ```
-    pub fn fairing() -> Compression {
-        Compression::with_level(Level::Default)
+    pub fn min_cache_ratio(self, ratio: f32) -> CachedCompression {
+        CachedCompression {
+            min_cache_ratio: Some(ratio),
+            ..self
+        }
     }
 
-    /// Returns a fairing that compresses outgoing requests with the specified
-    /// compression level.
+    /// Leaves responses whose body is smaller than `min_size` bytes
+    /// uncompressed, without caching them, since tiny bodies gain little from
+    /// compression and can even grow.
+    ///
+    /// Bodies with a known size are checked before anything is read.  Other
+    /// bodies are buffered on cache misses in order to check their size.
     ///
     /// ## Example
     ///
     /// ```rust
-    /// use rocket_async_compression::{Compression, Level};
+    /// use rocket_async_compression::CachedCompression;
     ///
-    /// rocket::build()
-    ///    // ...
-    ///    .attach(Compression::with_level(Level::Fastest))
-    ///    // ...
+    /// let fairing = CachedCompression::path_prefix_fairing(vec!["/".to_owned()]).min_size(1024);
     /// ```
-    pub fn with_level(level: Level) -> Compression {
-        Compression {
-            level,
-            excluded_content_types: EXCLUSIONS.clone(),
+    pub fn min_size(self, min_size: usize) -> CachedCompression {
+        CachedCompression {
+            min_size: Some(min_size),
+            ..self
         }
     }
 
-    /// Replaces the default list of excluded content types with the provided list.
-    pub fn exlude_content_types(self, excluded_content_types: Vec<MediaType>) -> Self {
-        Compression {
-            excluded_content_types,
+    /// Caps the size of the bodies buffered on cache misses, 16 MiB by
+    /// default.
+    ///
+    /// Cache misses are buffered in order to compress and cache them.  Bodies
+    /// known to be larger than `max_buffer_size`, and bodies of unknown size
+    /// growing past it such as event streams or long downloads, are instead
+    /// compressed while they are streamed and aren't cached.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let fairing = CachedCompression::path_prefix_fairing(vec!["/".to_owned()])
+    ///     .max_buffer_size(4 * 1024 * 1024);
+    /// ```
+    pub fn max_buffer_size(self, max_buffer_size: usize) -> CachedCompression {
+        CachedCompression {
+            max_buffer_size: Some(max_buffer_size),
             ..self
         }
     }
 
-    /// Returns a mutable reference to the list of excluded content types.
-    pub fn excluded_content_types(&mut self) -> &mut Vec<MediaType> {
-        &mut self.excluded_content_types
+    /// Compresses `body` into `response` while it is streamed, without
+    /// caching it.  `original_size` is the size of `body`, if known.
+    fn stream_uncached<'r>(
+        &self,
+        request: &Request<'_>,
+        response: &mut Response<'r>,
+        body: impl AsyncRead + Send + 'r,
+        desired_encoding: CachedEncoding,
+        level: Level,
+        original_size: Option<u64>,
+    ) {
+        let encoding = desired_encoding.encoding();
+        CompressionStats::global().record_encoding(&encoding);
+        CompressionInfo {
+            original_size,
+            cache_hit: Some(false),
+            ..CompressionInfo::compressed(encoding.clone(), level)
+        }
+        .record(request);
+        CompressionUtils::set_original_length(
+            response,
+            self.original_length_header.as_deref(),
+            original_size,
+        );
+        let compressor = CompressionUtils::streaming_encoder(
+            body,
+            &encoding,
+            CompressionUtils::effective_level(desired_encoding, level),
+            false,
+            None,
+            &ZstdParams::default(),
+        );
+        // A `Content-Length` declared by the handler is the length of the uncompressed body.
+        response.remove_header("Content-Length");
+        CompressionUtils::set_body_and_encoding(response, compressor, encoding);
+        CompressionUtils::set_vary(response, &self.vary_headers);
     }
-}
 
-#[rocket::async_trait]
-impl Fairing for Compression {
-    fn info(&self) -> Info {
-        Info {
-            name: "Response compression",
-            kind: Kind::Response,
+    /// Returns `true` if a body of `size` bytes is below the minimum size.
+    fn too_small(&self, size: usize) -> bool {
+        self.min_size.is_some_and(|min_size| size < min_size)
+    }
+
+    /// Stops caching new responses once the cached bodies add up to
+    /// `max_cache_bytes`.
+    ///
+    /// Cached bodies are never evicted, so without a ceiling the cache grows
+    /// with every distinct cached path.  Once the ceiling is reached, further
+    /// cache misses are compressed while streaming, like the [`Compression`]
+    /// fairing does, rather than being buffered and cached.  Responses that
+    /// are already cached are still served from the cache.  The ceiling is
+    /// soft: the body that reaches it is still cached.
+    ///
+    /// Caching resumes once space is freed with
+    /// [`CachedCompression::clear_cache`] or by removing a rule with
+    /// [`CachedPathRules::remove_rule`] and purging its bodies.  Whether the
+    /// cache is currently full is reported by
+    /// [`CompressionStats::cache_degraded`], which is updated whenever the
+    /// cache changes and on every response handled by the fairing.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()])
+    ///     .max_cache_bytes(256 * 1024 * 1024);
+    /// ```
+    pub fn max_cache_bytes(self, max_cache_bytes: usize) -> CachedCompression {
+        CachedCompression {
+            max_cache_bytes: Some(max_cache_bytes),
+            ..self
         }
     }
 
-    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
-        super::CompressionUtils::compress_response(
-            request,
-            response,
-            &self.excluded_content_types,
-            self.level,
-        );
+    /// Doesn't cache compressed bodies larger than `max_cache_entry_bytes`, so
+    /// that the cache holds many small and medium assets rather than a few
+    /// large ones.
+    ///
+    /// Larger bodies are still compressed and served, but they are compressed
+    /// anew on every request.  The limit applies to each body on its own,
+    /// independently of [`CachedCompression::max_cache_bytes`], which only
+    /// counts the bodies that were cached.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let fairing = CachedCompression::path_prefix_fairing(vec!["/".to_owned()])
+    ///     .max_cache_bytes(256 * 1024 * 1024)
+    ///     .max_cache_entry_bytes(5 * 1024 * 1024);
+    /// ```
+    pub fn max_cache_entry_bytes(self, max_cache_entry_bytes: usize) -> CachedCompression {
+        CachedCompression {
+            max_cache_entry_bytes: Some(max_cache_entry_bytes),
+            ..self
+        }
     }
-}
 
-/// Compresses all responses with Brotli or Gzip compression. Caches compressed
-/// response bodies in memory for selected file types/path suffixes, useful for
-/// compressing large compiled JS/CSS files, OTF font packs, etc.  Note that all
-/// cached files are held in memory indefinitely.
-///
-/// Compression is done in the same manner as the [`Compression`](Compression)
-/// fairing.
-///
-/// # Usage
-///
-/// Attach the compression [fairing](/rocket/fairing/) to your Rocket
-/// application:
-///
-/// ```rust
-/// use rocket_async_compression::CachedCompression;
-///
-/// rocket::build()
-///     // ...
-///     .attach(CachedCompression {
-///         cached_paths: vec![
-///             "".to_owned(),
-///             "/".to_owned(),
-///             "/about".to_owned(),
-///             "/people".to_owned(),
-///             "/posts".to_owned(),
-///             "/events".to_owned(),
-///             "/groups".to_owned(),
-///         ],
-///         cached_path_prefixes: vec!["/user/".to_owned(), "/g/".to_owned(), "/p/".to_owned()],
-///         cached_path_suffixes: vec![".otf".to_owned(), "main.dart.js".to_owned()],
-///         ..Default::default()
-///     })
-///     // ...
-/// ```
-///
-///
-#[derive(Default)]
-pub struct CachedCompression {
-    pub cached_paths: Vec<String>,
-    pub cached_path_prefixes: Vec<String>,
-    pub cached_path_suffixes: Vec<String>,
-    pub excluded_path_prefixes: Vec<String>,
-    pub level: Option<Level>,
-}
+    /// Returns `true` if a body of `original_size` bytes compressed to
+    /// `compressed_size` bytes meets the minimum cache ratio, if any.
+    fn compresses_well(&self, original_size: usize, compressed_size: usize) -> bool {
+        self.min_cache_ratio.is_none_or(|min_cache_ratio| {
+            compressed_size as f32 <= original_size as f32 * min_cache_ratio
+        })
+    }
 
-impl CachedCompression {
-    /// Caches only the specific paths provided.
-    pub fn exact_path_fairing(cached_paths: Vec<String>) -> CachedCompression {
+    /// Returns `true` if a compressed body of `size` bytes may be cached.
+    fn fits_cache_entry(&self, size: usize) -> bool {
+        self.max_cache_entry_bytes
+            .is_none_or(|max_cache_entry_bytes| size <= max_cache_entry_bytes)
+    }
+
+    /// Persists compressed bodies to `dir` in addition to caching them in
+    /// memory, and loads them from `dir` on cache misses, so that they survive
+    /// restarts.
+    ///
+    /// Each body is stored in its own file, named after the request path (or
+    /// the `ETag`, prefixed with `etag-`, see
+    /// [`CachedCompression::key_by_etag`]) followed by `.br` or `.gz`
+    /// depending on its encoding.  The files are plain compressed data, so
+    /// they can be inspected with standard tools or handed to a CDN as-is.
+    ///
+    /// Names are made filesystem-safe by keeping ASCII letters, digits, `.` and
+    /// `-` and replacing every other byte, as well as a leading `.`, with `_`
+    /// followed by its value in two hexadecimal digits.  For example,
+    /// `/assets/app.js` compressed with brotli is stored as
+    /// `_2Fassets_2Fapp.js.br`.  This escaping is reversible, so two different
+    /// keys never share a file.  Keys producing names too long for the
+    /// filesystem are only cached in memory.
+    ///
+    /// Files are written atomically, but are never removed or invalidated by
+    /// the fairing: clear the directory whenever cached responses or the
+    /// compression level change.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()])
+    ///     .persist_to_dir("compression-cache");
+    /// ```
+    pub fn persist_to_dir(self, dir: impl Into<PathBuf>) -> CachedCompression {
         CachedCompression {
-            cached_paths,
-            ..Default::default()
+            cache_dir: Some(dir.into()),
+            ..self
         }
     }
 
-    /// Caches all paths with the provided suffixes.
-    pub fn path_suffix_fairing(cached_path_suffixes: Vec<String>) -> CachedCompression {
-        CachedCompression {
-            cached_path_suffixes,
-            ..Default::default()
+    /// Writes `body` to the cache directory, if any.
+    async fn persist(&self, key: &CacheKey, encoding: CachedEncoding, body: &[u8]) {
+        let Some(dir) = &self.cache_dir else {
+            return;
+        };
+
+        let path = dir.join(key.file_name(encoding));
+        let written = match rocket::tokio::fs::create_dir_all(dir).await {
+            Ok(()) => write_atomically(&path, body).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = written {
+            warn!(
+                "Failed to persist cached response to {}: {}",
+                path.display(),
+                err
+            );
         }
     }
 
-    /// Caches all paths with the provided suffixes.
-    pub fn path_prefix_fairing(cached_path_prefixes: Vec<String>) -> CachedCompression {
+    /// Loads a body persisted to the cache directory, if any, into the memory
+    /// cache.
+    async fn load_persisted(&self, key: &CacheKey, encoding: CachedEncoding) -> Option<Arc<[u8]>> {
+        let path = self.cache_dir.as_ref()?.join(key.file_name(encoding));
+        let body: Arc<[u8]> = rocket::tokio::fs::read(&path).await.ok()?.into();
+        debug!("Loaded persisted cached response from {}", path.display());
+        CACHED_FILES.insert((key.clone(), encoding), body.clone());
+        Some(body)
+    }
+
+    /// Removes all cached bodies, freeing their memory unless they are still
+    /// being sent.
+    pub fn clear_cache() {
+        CACHED_FILES.clear();
+        CompressionStats::global().set_cache_degraded(false);
+    }
+
+    /// Returns `true` if the cache has reached the soft ceiling, and reports
+    /// it in the stats.
+    fn cache_full(&self) -> bool {
+        update_cache_full(self.max_cache_bytes)
+    }
+
+    /// Decompresses every body compressed on a cache miss and checks that it
+    /// matches the original before serving and caching it, in order to catch
+    /// misconfigured encoders during development.
+    ///
+    /// Verification only happens in builds with debug assertions; the flag is
+    /// ignored in release builds.  Bodies failing it are logged as errors,
+    /// served uncompressed and left uncached.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use rocket_async_compression::CachedCompression;
+    ///
+    /// let fairing = CachedCompression::path_suffix_fairing(vec![".js".to_owned()]).debug_verify(true);
+    /// ```
+    pub fn debug_verify(self, debug_verify: bool) -> CachedCompression {
         CachedCompression {
-            cached_path_prefixes,
-            ..Default::default()
+            debug_verify,
+            ..self
         }
     }
 
-    /// Caches compressed responses for all paths except those with the excluded prefixes.
-    pub fn excluded_path_prefix_fairing(excluded_path_prefixes: Vec<String>) -> CachedCompression {
+    /// Returns `false` if verification is enabled and `compressed_body`
+    /// doesn't decompress to `original`.  See
+    /// [`CachedCompression::debug_verify`].
+    async fn verify(
+        &self,
+        encoding: CachedEncoding,
+        original: &[u8],
+        compressed_body: &[u8],
+    ) -> bool {
+        if !cfg!(debug_assertions) || !self.debug_verify {
+            return true;
+        }
+        matches!(
+            crate::util::decompress(compressed_body, encoding.encoding()).await,
+            Ok(decompressed) if decompressed[..] == original[..]
+        )
+    }
+
+    /// Logs every compressed or cached response and every skipped response at
+    /// debug level, like [`Compression::log_compression`].
+    pub fn log_compression(self, log_compression: bool) -> CachedCompression {
         CachedCompression {
-            cached_path_prefixes: vec!["".to_string()],
-            excluded_path_prefixes,
-            ..Default::default()
+            log_compression,
+            ..self
         }
     }
 
-    /// Caches `Vec<&str>` to `Vec<String>`.
-    pub fn static_paths(paths: Vec<&str>) -> Vec<String> {
-        paths.into_iter().map(Into::into).collect()
+    /// Records a response left uncompressed for `reason`.
+    fn skip(&self, request: &Request<'_>, path: &str, reason: SkipReason) {
+        CompressionStats::global().record_skipped(reason);
+        CompressionInfo::skipped(reason).record(request);
+        if self.log_compression {
+            log_skip(path, reason, FairingLabel(self.name));
+        }
+    }
+
+    /// Passes `event` to the metrics, the log and the registered hook.
+    fn report(&self, event: CompressionEvent, level: Level, span: &CompressionSpan) {
+        span.record(&event);
+        if self.log_compression {
+            log_event(&event, level, FairingLabel(self.name));
+        }
+        #[cfg(feature = "prometheus")]
+        crate::PrometheusMetrics::global().record_event(&event);
+        if let Some(hook) = &self.on_compressed {
+            hook(event);
+        }
+    }
+
+    /// Returns the key under which the compressed body of `response` to a
+    /// request for `path` is cached.
+    fn cache_key(&self, path: &str, response: &Response<'_>) -> CacheKey {
+        match response.headers().get_one("ETag") {
+            Some(etag) if self.key_by_etag && !etag.starts_with("W/") => {
+                CacheKey::ETag(etag.to_owned())
+            }
+            _ => CacheKey::Path(path.to_owned()),
+        }
+    }
+
+    /// Compresses `original` with each eager encoding other than `negotiated`
+    /// and caches the results that pass the same checks as the negotiated
+    /// body.
+    async fn compress_eagerly(
+        &self,
+        path: &str,
+        cache_key: &CacheKey,
+        original: &[u8],
+        negotiated: CachedEncoding,
+    ) {
+        for encoding in self
+            .eager_encodings
+            .iter()
+            .filter_map(CachedEncoding::from_encoding)
+            .filter(|encoding| *encoding != negotiated && self.serves(*encoding))
+        {
+            let key = (cache_key.clone(), encoding);
+            if self.cache_full() || CACHED_FILES.contains(&key) {
+                continue;
+            }
+
+            match CompressionUtils::compress_body(original, encoding, self.level_for(encoding))
+                .await
+            {
+                Ok(compressed_body) => {
+                    if !self.verify(encoding, original, &compressed_body).await {
+                        error!(
+                            "Eagerly compressed {} response for {} doesn't decompress to the original body; not caching it{}",
+                            encoding.encoding(),
+                            path,
+                            FairingLabel(self.name)
+                        );
+                        continue;
+                    }
+                    if !self.compresses_well(original.len(), compressed_body.len()) {
+                        debug!(
+                            "Not eagerly caching {} response for {}: compressed from {} to {} bytes",
+                            encoding.encoding(),
+                            path,
+                            original.len(),
+                            compressed_body.len()
+                        );
+                        continue;
+                    }
+                    let compressed_body = self.pad(encoding, compressed_body);
+                    if !self.fits_cache_entry(compressed_body.len()) {
+                        continue;
+                    }
+                    debug!("Eagerly setting cached response for {}", path);
+                    self.persist(cache_key, encoding, &compressed_body).await;
+                    CACHED_FILES.insert(key, compressed_body.into());
+                }
+                Err(err) => {
+                    warn!(
+                        "Failed to eagerly compress response body for {}: {}",
+                        path, err
+                    );
+                }
+            }
+        }
     }
 }
 
@@ -239,7 +3037,7 @@ impl AsyncRead for ErrorBody {
     ) -> Poll<Result<(), std::io::Error>> {
         let err = match self.0.take() {
             Some(err) => err,
-            None => std::io::Error::new(std::io::ErrorKind::Other, "ErrorBody already read"),
+            None => std::io::Error::other("ErrorBody already read"),
         };
         Poll::Ready(Err(err))
     }
@@ -249,96 +3047,378 @@ impl AsyncRead for ErrorBody {
 impl Fairing for CachedCompression {
     fn info(&self) -> Info {
         Info {
-            name: "Cached response compression",
-            kind: Kind::Response,
+            name: self.name.unwrap_or("Cached response compression"),
+            kind: Kind::Ignite | Kind::Response,
         }
     }
 
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let rocket = CompressionStats::manage(rocket);
+        self.runtime_rules.write().max_cache_bytes = self.max_cache_bytes;
+        Ok(CachedPaths::register(rocket, self))
+    }
+
     async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
         let path = request.uri().path().to_string();
-        let excluded_from_cache = self
-            .excluded_path_prefixes
-            .iter()
-            .any(|s| path.starts_with(s));
-        let cache_compressed_responses = !excluded_from_cache
-            && (self.cached_paths.iter().any(|s| path.eq(s))
-                || self.cached_path_suffixes.iter().any(|s| path.ends_with(s))
-                || self
-                    .cached_path_prefixes
-                    .iter()
-                    .any(|s| path.starts_with(s)));
-        if !cache_compressed_responses {
+        if self.always_skip_paths.contains(&path) {
             return;
         }
 
-        let (accepts_gzip, accepts_br) = CompressionUtils::accepted_algorithms(request);
-        if !accepts_gzip && !accepts_br {
+        let cache_compressed_responses = path_matches(
+            &path,
+            &self.cached_paths,
+            &self.cached_path_prefixes,
+            &self.cached_path_suffixes,
+            &self.excluded_path_prefixes,
+        ) || self
+            .runtime_rules
+            .matches(&path, &self.excluded_path_prefixes);
+        if !cache_compressed_responses {
             return;
         }
 
-        if CompressionUtils::already_encoded(response) {
+        if CompressionUtils::empty_body(response) || CompressionUtils::is_redirection(response) {
             return;
         }
 
+        let stats = CompressionStats::global();
+        let accept =
+            AcceptEncoding::from_request_header(request, self.accept_encoding_header.as_deref());
         let content_type = response.content_type();
-        if CompressionUtils::skip_encoding(&content_type, &EXCLUSIONS) {
+        // Brotli bodies can't be padded, so they would reveal their exact size.
+        let decision = match CachedEncoding::preferred(&accept, |encoding| self.serves(encoding)) {
+            None => Err(SkipReason::NotAccepted),
+            Some(_) if CompressionUtils::already_encoded(response) => {
+                Err(SkipReason::AlreadyEncoded)
+            }
+            Some(_)
+                if CompressionUtils::skip_encoding(
+                    &content_type,
+                    self.excluded_content_types
+                        .as_deref()
+                        .unwrap_or(&EXCLUSIONS),
+                    if self.skip_untyped {
+                        NoContentTypePolicy::Skip
+                    } else {
+                        NoContentTypePolicy::Compress
+                    },
+                ) =>
+            {
+                Err(SkipReason::ExcludedType)
+            }
+            Some(encoding) => Ok(encoding),
+        };
+        let desired_encoding = match decision {
+            Ok(encoding) => encoding,
+            Err(reason) => {
+                stats.record_considered();
+                self.skip(request, &path, reason);
+                return;
+            }
+        };
+
+        // File-backed bodies, such as those of `NamedFile`, are sized but their size is only known
+        // once computed by seeking, so it is left until the cheaper checks have passed.
+        response.body_mut().size().await;
+        if CompressionUtils::empty_body(response) {
             return;
         }
 
-        let desired_encoding = if accepts_br {
-            CachedEncoding::Brotli
-        } else {
-            CachedEncoding::Gzip
-        };
-        let encoding = match desired_encoding {
-            CachedEncoding::Gzip => Encoding::Gzip,
-            CachedEncoding::Brotli => Encoding::Brotli,
-        };
+        stats.record_considered();
+        if CompressionUtils::known_size(response).is_some_and(|size| self.too_small(size)) {
+            self.skip(request, &path, SkipReason::TooSmall);
+            return;
+        }
 
-        if cache_compressed_responses && (accepts_gzip || accepts_br) {
-            let cached_body = {
-                let guard = CACHED_FILES.read().await;
-                let body = guard.get(&(path.clone(), desired_encoding)).copied();
-                drop(guard);
-                body
+        let started = Instant::now();
+        let cache_key = self.cache_key(&path, response);
+        let encoding = desired_encoding.encoding();
+        let level = self.level_for(desired_encoding);
+        let span = CompressionSpan::cached(&path, &encoding, level);
+
+        // Checked on hits too, so that the stats notice space freed since the last miss.
+        let cache_full = self.cache_full();
+        if cache_compressed_responses {
+            let cached_body = CACHED_FILES.get(&(cache_key.clone(), desired_encoding));
+            let cached_body = match cached_body {
+                Some(cached_body) => Some(cached_body),
+                None => self.load_persisted(&cache_key, desired_encoding).await,
             };
 
             if let Some(cached_body) = cached_body {
-                debug!("Found cached response for {}", path);
+                debug!(
+                    "Found cached response for {}{}",
+                    path,
+                    FairingLabel(self.name)
+                );
+                stats.record_encoding(&encoding);
                 response.set_header(Header::new(
                     CONTENT_ENCODING.as_str(),
                     format!("{}", encoding),
                 ));
+                CompressionUtils::set_vary(response, &self.vary_headers);
+                CompressionInfo {
+                    compressed_size: Some(cached_body.len() as u64),
+                    cache_hit: Some(true),
+                    ..CompressionInfo::compressed(encoding.clone(), level)
+                }
+                .record(request);
+                self.report(
+                    CompressionEvent {
+                        path,
+                        encoding,
+                        original_size: None,
+                        compressed_size: cached_body.len() as u64,
+                        duration: started.elapsed(),
+                        cache_hit: true,
+                    },
+                    level,
+                    &span,
+                );
+                let original_length = response.body().preset_size().map(|size| size as u64);
+                CompressionUtils::set_original_length(
+                    response,
+                    self.original_length_header.as_deref(),
+                    original_length,
+                );
+                #[cfg(feature = "digest")]
+                self.set_digest(response, &cached_body);
+                // Cloning the `Arc` is all it takes to serve the body; `Cursor` reads and seeks
+                // directly from the shared bytes.
                 response.set_sized_body(cached_body.len(), Cursor::new(cached_body));
                 return;
             }
         }
 
-        let body = response.body_mut().take();
-        let compressed_body: Vec<u8> = match CompressionUtils::compress_body(
-            body,
-            desired_encoding,
-            self.level.unwrap_or(Level::Default),
-        )
-        .await
+        #[cfg(feature = "prometheus")]
+        crate::PrometheusMetrics::global().cache_misses.inc();
+        span.record_miss();
+
+        if self.load_breaker.as_ref().is_some_and(LoadBreaker::is_open) {
+            self.skip(request, &path, SkipReason::Overloaded);
+            return;
+        }
+
+        // Past the soft ceiling, misses are compressed while streaming instead of being buffered
+        // and cached, until space is freed.
+        if cache_full {
+            debug!(
+                "Cache is full; streaming compressed response for {}{}",
+                path,
+                FairingLabel(self.name)
+            );
+            let original_size = CompressionUtils::known_size(response).map(|size| size as u64);
+            let body = response.body_mut().take();
+            self.stream_uncached(
+                request,
+                response,
+                body,
+                desired_encoding,
+                level,
+                original_size,
+            );
+            return;
+        }
+
+        // Bodies too large to buffer, such as long downloads or never-ending streams, are
+        // compressed while streaming and left uncached.
+        let max_buffer_size = self.max_buffer_size.unwrap_or(DEFAULT_MAX_BUFFER_SIZE);
+        if let Some(size) =
+            CompressionUtils::known_size(response).filter(|size| *size > max_buffer_size)
         {
+            debug!(
+                "Body too large to buffer; streaming compressed response for {}{}",
+                path,
+                FairingLabel(self.name)
+            );
+            let body = response.body_mut().take();
+            self.stream_uncached(
+                request,
+                response,
+                body,
+                desired_encoding,
+                level,
+                Some(size as u64),
+            );
+            return;
+        }
+
+        let original_size = Arc::new(AtomicU64::new(0));
+        let mut body = response.body_mut().take();
+        let mut buffered = None;
+        // The body can only be read once, so it is buffered in order to compress it with several
+        // encodings or to serve it uncompressed.  Reading stops past the cap so that bodies of
+        // unknown size can't grow the buffer without bound.
+        let mut original = Vec::new();
+        // `Body::take` shadows `AsyncReadExt::take`.
+        let read = AsyncReadExt::take(&mut body, max_buffer_size as u64 + 1)
+            .read_to_end(&mut original)
+            .await;
+        let compressed = match read {
+            Ok(_) if original.len() > max_buffer_size => {
+                debug!(
+                    "Body too large to buffer; streaming compressed response for {}{}",
+                    path,
+                    FairingLabel(self.name)
+                );
+                let body = Cursor::new(original).chain(body);
+                self.stream_uncached(request, response, body, desired_encoding, level, None);
+                return;
+            }
+            Ok(_) if self.too_small(original.len()) => {
+                self.skip(request, &path, SkipReason::TooSmall);
+                response.set_sized_body(original.len(), Cursor::new(original));
+                return;
+            }
+            Ok(_) => {
+                original_size.store(original.len() as u64, Ordering::Relaxed);
+                let compressed = span
+                    .instrument(CompressionUtils::compress_body(
+                        &original[..],
+                        desired_encoding,
+                        level,
+                    ))
+                    .await;
+                buffered = Some(original);
+                compressed
+            }
+            Err(err) => Err(err),
+        };
+        let compressed_body: Vec<u8> = match compressed {
             Ok(compressed_body) => compressed_body,
             Err(err) => {
-                error!("Failed to compress response body for {}; underlying `AsyncRead` likely failed: {}", path, err);
-                response.set_streamed_body(ErrorBody(Some(err)));
+                error!("Failed to compress response body for {}; underlying `AsyncRead` likely failed: {}{}", path, err, FairingLabel(self.name));
+                match (self.failure_policy, buffered) {
+                    (FailurePolicy::ServeIdentity, Some(original)) => {
+                        response.set_sized_body(original.len(), Cursor::new(original));
+                    }
+                    (FailurePolicy::Status500, _) => {
+                        *response = Response::build()
+                            .status(Status::InternalServerError)
+                            .finalize();
+                    }
+                    _ => response.set_streamed_body(ErrorBody(Some(err))),
+                }
                 return;
             }
         };
+        let verified = match &buffered {
+            Some(original) => {
+                self.verify(desired_encoding, original, &compressed_body)
+                    .await
+            }
+            None => true,
+        };
+        if !verified {
+            error!(
+                "Compressed response for {} doesn't decompress to the original body; serving it uncompressed{}",
+                path,
+                FairingLabel(self.name)
+            );
+            if let Some(original) = buffered {
+                response.set_sized_body(original.len(), Cursor::new(original));
+            }
+            return;
+        }
+        if let Some(min_cache_ratio) = self.min_cache_ratio {
+            let original = buffered
+                .take_if(|original| !self.compresses_well(original.len(), compressed_body.len()));
+            if let Some(original) = original {
+                info!(
+                    "Not caching response for {}: compressed from {} to {} bytes, above the {} ratio{}",
+                    path,
+                    original.len(),
+                    compressed_body.len(),
+                    min_cache_ratio,
+                    FairingLabel(self.name)
+                );
+                CompressionInfo {
+                    original_size: Some(original.len() as u64),
+                    cache_hit: Some(false),
+                    ..CompressionInfo::uncompressed()
+                }
+                .record(request);
+                response.set_sized_body(original.len(), Cursor::new(original));
+                return;
+            }
+        }
+        // Other encodings are only compressed once the negotiated body has been accepted, so that
+        // a body failing to compress or rejected by the checks above isn't cached for them.
+        if let Some(original) = &buffered {
+            span.instrument(self.compress_eagerly(&path, &cache_key, original, desired_encoding))
+                .await;
+        }
+        let compressed_body = self.pad(desired_encoding, compressed_body);
+        stats.record_encoding(&encoding);
+        stats.record_saved(
+            original_size.load(Ordering::Relaxed),
+            compressed_body.len() as u64,
+        );
+        #[cfg(feature = "metrics")]
+        stats.record_content_type_sizes(
+            content_type.as_ref(),
+            original_size.load(Ordering::Relaxed),
+            compressed_body.len() as u64,
+        );
+        debug!(
+            "Compressed response for {} from {} to {} bytes{}",
+            path,
+            original_size.load(Ordering::Relaxed),
+            compressed_body.len(),
+            FairingLabel(self.name)
+        );
         response.set_header(Header::new(
             CONTENT_ENCODING.as_str(),
             format!("{}", encoding),
         ));
+        CompressionUtils::set_vary(response, &self.vary_headers);
+        CompressionInfo {
+            original_size: Some(original_size.load(Ordering::Relaxed)),
+            compressed_size: Some(compressed_body.len() as u64),
+            cache_hit: Some(false),
+            ..CompressionInfo::compressed(encoding.clone(), level)
+        }
+        .record(request);
+        self.report(
+            CompressionEvent {
+                path: path.clone(),
+                encoding,
+                original_size: Some(original_size.load(Ordering::Relaxed)),
+                compressed_size: compressed_body.len() as u64,
+                duration: started.elapsed(),
+                cache_hit: false,
+            },
+            level,
+            &span,
+        );
+        CompressionUtils::set_original_length(
+            response,
+            self.original_length_header.as_deref(),
+            Some(original_size.load(Ordering::Relaxed)),
+        );
+        let compressed_body: Arc<[u8]> = compressed_body.into();
+        #[cfg(feature = "digest")]
+        self.set_digest(response, &compressed_body);
         response.set_sized_body(compressed_body.len(), Cursor::new(compressed_body.clone()));
+        if !self.fits_cache_entry(compressed_body.len()) {
+            debug!(
+                "Not caching response for {}: {} compressed bytes exceed the entry limit{}",
+                path,
+                compressed_body.len(),
+                FairingLabel(self.name)
+            );
+            return;
+        }
 
-        debug!("Setting cached response for {}", path);
-        CACHED_FILES
-            .write()
-            .await
-            .insert((path, desired_encoding), Vec::leak(compressed_body));
+        debug!(
+            "Setting cached response for {}{}",
+            path,
+            FairingLabel(self.name)
+        );
+        self.persist(&cache_key, desired_encoding, &compressed_body)
+            .await;
+        CACHED_FILES.insert((cache_key, desired_encoding), compressed_body);
+        // The body reaching the ceiling degrades the misses that follow it.
+        self.cache_full();
     }
 }
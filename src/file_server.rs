@@ -0,0 +1,323 @@
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use async_compression::Level;
+use rocket::{
+    http::{hyper::header::CONTENT_ENCODING, uri::Segments, ContentType, Header, Method, Status},
+    response::{Responder, Response},
+    route::{Handler, Outcome, Route},
+    tokio::fs,
+    Data, Request,
+};
+
+use crate::{
+    cache::BoundedCache, fairing::CachedEncoding, AcceptEncoding, CompressionUtils, Encoding,
+    NamedCompressedFile, NoContentTypePolicy, EXCLUSIONS,
+};
+
+/// The default rank of the routes generated by [`CompressedFileServer`],
+/// matching `rocket::fs::FileServer`.
+const DEFAULT_RANK: isize = 10;
+
+/// What [`CompressedFileServer`] does when the compressed sibling preferred by
+/// a client doesn't exist.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MissingSiblings {
+    /// Serve the best existing sibling, or the original file.
+    Ignore,
+    /// Compress the original file and write the sibling next to it, so later
+    /// requests are served from disk.
+    WriteToDisk,
+    /// Compress the original file and keep the result in memory, bounded by the
+    /// provided total size in bytes.
+    CacheInMemory { max_bytes: usize },
+}
+
+type SiblingCache = Arc<Mutex<BoundedCache<(PathBuf, Encoding)>>>;
+
+/// A drop-in replacement for `rocket::fs::FileServer` that serves
+/// precompressed `.br` and `.gz` siblings of files.
+///
+/// Each file is served like [`NamedCompressedFile`]: if `app.js` is requested
+/// and the client accepts brotli, `app.js.br` is served in its place when it
+/// exists.  Optionally, missing siblings can be generated on first request and
+/// written to disk or cached in memory; see [`MissingSiblings`].  Only files
+/// whose extension maps to a compressible content type are compressed.
+///
+/// Like `FileServer`, directories are served through their `index.html`,
+/// hidden files are not served and paths can't escape the root directory.
+/// Requests that don't match a file are forwarded.
+///
+/// # Usage
+///
+/// ```rust
+/// # #[macro_use] extern crate rocket;
+/// use rocket::fs::relative;
+/// use rocket_async_compression::{CompressedFileServer, MissingSiblings};
+///
+/// #[launch]
+/// fn rocket() -> _ {
+///     rocket::build().mount(
+///         "/",
+///         CompressedFileServer::from(relative!("static"))
+///             .missing_siblings(MissingSiblings::WriteToDisk),
+///     )
+/// }
+/// ```
+#[derive(Clone)]
+pub struct CompressedFileServer {
+    root: PathBuf,
+    rank: isize,
+    index: bool,
+    missing_siblings: MissingSiblings,
+    level: Level,
+    cache: Option<SiblingCache>,
+}
+
+impl CompressedFileServer {
+    /// Serves files from `path`.
+    pub fn from<P: AsRef<Path>>(path: P) -> CompressedFileServer {
+        CompressedFileServer {
+            root: path.as_ref().to_path_buf(),
+            rank: DEFAULT_RANK,
+            index: true,
+            missing_siblings: MissingSiblings::Ignore,
+            level: Level::Best,
+            cache: None,
+        }
+    }
+
+    /// Sets the rank of the generated route.  Defaults to `10`.
+    pub fn rank(self, rank: isize) -> CompressedFileServer {
+        CompressedFileServer { rank, ..self }
+    }
+
+    /// Sets whether requests for directories are served with the directory's
+    /// `index.html`.  Enabled by default.
+    pub fn index(self, index: bool) -> CompressedFileServer {
+        CompressedFileServer { index, ..self }
+    }
+
+    /// Sets what happens when a compressed sibling doesn't exist.  Defaults to
+    /// [`MissingSiblings::Ignore`].
+    pub fn missing_siblings(self, missing_siblings: MissingSiblings) -> CompressedFileServer {
+        let cache = match missing_siblings {
+            MissingSiblings::CacheInMemory { max_bytes } => {
+                Some(Arc::new(Mutex::new(BoundedCache::new(max_bytes))))
+            }
+            _ => None,
+        };
+
+        CompressedFileServer {
+            missing_siblings,
+            cache,
+            ..self
+        }
+    }
+
+    /// Sets the compression level used to generate missing siblings.  Defaults
+    /// to [`Level::Best`] since each file is only compressed once.
+    pub fn level(self, level: Level) -> CompressedFileServer {
+        CompressedFileServer { level, ..self }
+    }
+
+    /// Compresses the file at `path` with `encoding`.
+    async fn compress_file(&self, path: &Path, encoding: &Encoding) -> std::io::Result<Vec<u8>> {
+        let Some(cached_encoding) = CachedEncoding::from_encoding(encoding) else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("`{}` compression is disabled", encoding),
+            ));
+        };
+        let original = fs::read(path).await?;
+        CompressionUtils::compress_body(&original[..], cached_encoding, self.level).await
+    }
+
+    /// Writes the `encoding` sibling of `path` if it doesn't exist yet or is
+    /// older than `path`.  The sibling is written to a temporary file first so
+    /// that concurrent requests never observe a partially written sibling.
+    async fn write_sibling(&self, path: &Path, encoding: &Encoding) -> std::io::Result<()> {
+        let sibling = sibling_path(path, encoding);
+        let modified = fs::metadata(path).await?.modified()?;
+        let fresh = match fs::metadata(&sibling).await {
+            Ok(metadata) => metadata.modified()? >= modified,
+            Err(_) => false,
+        };
+        if fresh {
+            return Ok(());
+        }
+
+        let compressed = self.compress_file(path, encoding).await?;
+        write_atomically(&sibling, &compressed).await
+    }
+
+    /// Returns the in-memory compressed copy of `path`, compressing it if it
+    /// isn't cached yet.
+    async fn cached_sibling(
+        &self,
+        cache: &SiblingCache,
+        path: &Path,
+        encoding: &Encoding,
+    ) -> std::io::Result<Arc<[u8]>> {
+        let key = (path.to_path_buf(), encoding.clone());
+        let cached = cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(&key);
+        if let Some(cached) = cached {
+            return Ok(cached);
+        }
+
+        let compressed: Arc<[u8]> = self.compress_file(path, encoding).await?.into();
+        cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(key, compressed.clone());
+        Ok(compressed)
+    }
+}
+
+/// Returns `true` if files with the extension of `path` should be compressed.
+pub(crate) fn is_compressible(path: &Path) -> bool {
+    let content_type = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(ContentType::from_extension);
+    content_type.is_some()
+        && !CompressionUtils::skip_encoding(&content_type, &EXCLUSIONS, NoContentTypePolicy::Skip)
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it into
+/// place.  The temporary name is unique to this process and call, so
+/// concurrent writers of the same file never share a temporary file.
+pub(crate) async fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    if let Err(err) = fs::write(&temporary, contents).await {
+        let _ = fs::remove_file(&temporary).await;
+        return Err(err);
+    }
+    fs::rename(&temporary, path).await
+}
+
+/// Returns the path of the `encoding` sibling of `path`.
+pub(crate) fn sibling_path(path: &Path, encoding: &Encoding) -> PathBuf {
+    let extension = match encoding {
+        Encoding::Brotli => "br",
+        _ => "gz",
+    };
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(".");
+    sibling.push(extension);
+    sibling.into()
+}
+
+#[rocket::async_trait]
+impl Handler for CompressedFileServer {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        let path = req
+            .segments::<Segments<'_, rocket::http::uri::fmt::Path>>(0..)
+            .ok()
+            .and_then(|segments| segments.to_path_buf(false).ok())
+            .map(|path| self.root.join(path));
+        let Some(mut path) = path else {
+            return Outcome::forward(data, Status::NotFound);
+        };
+
+        let is_dir = fs::metadata(&path)
+            .await
+            .map(|metadata| metadata.is_dir())
+            .unwrap_or(false);
+        if is_dir {
+            if !self.index {
+                return Outcome::forward(data, Status::NotFound);
+            }
+            path.push("index.html");
+        }
+
+        let accept = AcceptEncoding::from_request(req);
+        let preferred = accept
+            .preferred(&[Encoding::Brotli, Encoding::Gzip])
+            .cloned();
+        if let Some(encoding) = preferred.filter(|_| is_compressible(&path)) {
+            match (self.missing_siblings, &self.cache) {
+                (MissingSiblings::WriteToDisk, _) => {
+                    if let Err(err) = self.write_sibling(&path, &encoding).await {
+                        // A read-only root fails on every request, so only the first failure is
+                        // worth a warning.
+                        static WARNED: AtomicBool = AtomicBool::new(false);
+                        if !WARNED.swap(true, Ordering::Relaxed) {
+                            warn!(
+                                "Failed to generate compressed sibling for {}: {}",
+                                path.display(),
+                                err
+                            );
+                        } else {
+                            debug!(
+                                "Failed to generate compressed sibling for {}: {}",
+                                path.display(),
+                                err
+                            );
+                        }
+                    }
+                }
+                (MissingSiblings::CacheInMemory { .. }, Some(cache))
+                    if fs::metadata(sibling_path(&path, &encoding)).await.is_err() =>
+                {
+                    match self.cached_sibling(cache, &path, &encoding).await {
+                        Ok(compressed) => {
+                            let mut response = Response::new();
+                            if let Some(content_type) = path
+                                .extension()
+                                .and_then(|extension| extension.to_str())
+                                .and_then(ContentType::from_extension)
+                            {
+                                response.set_header(content_type);
+                            }
+                            response.set_header(Header::new(
+                                CONTENT_ENCODING.as_str(),
+                                encoding.to_string(),
+                            ));
+                            CompressionUtils::add_vary(&mut response, "Accept-Encoding");
+                            response.set_sized_body(compressed.len(), Cursor::new(compressed));
+                            return Outcome::Success(response);
+                        }
+                        Err(err) => {
+                            debug!("Failed to compress {}: {}", path.display(), err);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        match NamedCompressedFile::open(&path).await {
+            Ok(file) => match file.respond_to(req) {
+                Ok(response) => Outcome::Success(response),
+                Err(status) => Outcome::Error(status),
+            },
+            Err(_) => Outcome::forward(data, Status::NotFound),
+        }
+    }
+}
+
+impl From<CompressedFileServer> for Vec<Route> {
+    fn from(server: CompressedFileServer) -> Self {
+        let name = format!("CompressedFileServer: {}", server.root.display());
+        let mut route = Route::ranked(server.rank, Method::Get, "/<path..>", server);
+        route.name = Some(name.into());
+        vec![route]
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::Mutex;
+
+use async_compression::Level;
+use rocket::Request;
+
+use crate::{Encoding, SkipReason};
+
+/// Describes what the fairings and responders did to the response to a
+/// request, for fairings running after them and the application's own
+/// instrumentation.
+///
+/// It is stored in the request's local cache and read with
+/// [`CompressionInfo::of`].  Nothing is stored for responses that weren't
+/// considered for compression, such as responses without a body or to paths
+/// that aren't compressed.
+///
+/// # Usage
+///
+/// ```rust
+/// use rocket::{
+///     fairing::{Fairing, Info, Kind},
+///     Request, Response,
+/// };
+/// use rocket_async_compression::CompressionInfo;
+///
+/// struct AccessLog;
+///
+/// #[rocket::async_trait]
+/// impl Fairing for AccessLog {
+///     fn info(&self) -> Info {
+///         Info {
+///             name: "Access log",
+///             kind: Kind::Response,
+///         }
+///     }
+///
+///     async fn on_response<'r>(&self, request: &'r Request<'_>, _: &mut Response<'r>) {
+///         if let Some(info) = CompressionInfo::of(request) {
+///             println!("{} {:?}", request.uri(), info.encoding);
+///         }
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CompressionInfo {
+    /// The encoding the response was compressed with, or `None` if it was
+    /// left uncompressed.
+    pub encoding: Option<Encoding>,
+    /// The compression level, or `None` if the response was left uncompressed.
+    pub level: Option<Level>,
+    /// The size of the body before compression, if it was known when the
+    /// response was processed.
+    pub original_size: Option<u64>,
+    /// The size of the compressed body, if it was known when the response was
+    /// processed.  Streamed responses are compressed while they are sent, so
+    /// their compressed size is unknown.
+    pub compressed_size: Option<u64>,
+    /// Whether the response was served from the
+    /// [`CachedCompression`](crate::CachedCompression) cache, or `None` if it
+    /// wasn't handled by a caching fairing.
+    pub cache_hit: Option<bool>,
+    /// Why the response was left uncompressed, if it was.
+    pub skip_reason: Option<SkipReason>,
+}
+
+/// Holds the [`CompressionInfo`] of a request in its local cache, which can
+/// only be set once.
+#[derive(Default)]
+struct InfoSlot(Mutex<Option<CompressionInfo>>);
+
+impl CompressionInfo {
+    /// Returns what was done to the response to `request`, or `None` if it
+    /// hasn't been considered for compression.
+    pub fn of(request: &Request<'_>) -> Option<CompressionInfo> {
+        request
+            .local_cache(InfoSlot::default)
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Describes a response compressed with `encoding` at `level`.
+    pub(crate) fn compressed(encoding: Encoding, level: Level) -> CompressionInfo {
+        CompressionInfo {
+            encoding: Some(encoding),
+            level: Some(level),
+            original_size: None,
+            compressed_size: None,
+            cache_hit: None,
+            skip_reason: None,
+        }
+    }
+
+    /// Describes a response left uncompressed for `reason`.
+    pub(crate) fn skipped(reason: SkipReason) -> CompressionInfo {
+        CompressionInfo {
+            skip_reason: Some(reason),
+            ..CompressionInfo::uncompressed()
+        }
+    }
+
+    /// Describes a response served uncompressed after being considered, such
+    /// as one compressing too poorly to be cached.
+    pub(crate) fn uncompressed() -> CompressionInfo {
+        CompressionInfo {
+            encoding: None,
+            level: None,
+            original_size: None,
+            compressed_size: None,
+            cache_hit: None,
+            skip_reason: None,
+        }
+    }
+
+    /// Stores `self` as the info of the response to `request`.
+    pub(crate) fn record(self, request: &Request<'_>) {
+        *request
+            .local_cache(InfoSlot::default)
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(self);
+    }
+}
@@ -13,7 +13,7 @@
 //! #[launch]
 //! async fn rocket() -> _ {
 //!     let server = rocket::build()
-//!         .mount("/", routes![...]);
+//!         .mount("/", routes![/* ... */]);
 //!
 //!     if cfg!(debug_assertions) {
 //!         server
@@ -23,31 +23,292 @@
 //! }
 //! ```
 //!
+//! Individual routes can also be compressed with the [`compress`] attribute,
+//! which wraps the route's responder in [`Compress`]:
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate rocket;
+//!
+//! use rocket_async_compression::compress;
+//!
+//! #[get("/")]
+//! #[compress(level = "best")]
+//! fn index() -> &'static str {
+//!     "Hello, world!"
+//! }
+//! ```
+//!
+//! ## Supported Response Bodies
+//!
+//! Compression operates on Rocket's [`Body`](rocket::response::Body) as a plain `AsyncRead`, so any
+//! body kind can be compressed: sized bodies (`String`, `Vec<u8>`, `NamedFile`,
+//! etc.) as well as streamed bodies produced by `ReaderStream`, `ByteStream`,
+//! `TextStream` or custom responders calling `set_streamed_body`.  Rocket has
+//! no other way of setting a body: every body is either sized or streamed.
+//!
+//! The two kinds are compressed differently.  Sized bodies of up to 64 KiB,
+//! or the size set with [`Compression::max_buffered_body`], are compressed in
+//! full before being sent, so that the compressed body is sent with a
+//! `Content-Length`, which proxies and clients handle better than a chunked
+//! body and which lets them show progress.  Compressing such a body takes
+//! little time, so this barely delays its first byte.  Larger sized
+//! bodies and streamed bodies are compressed while they are sent, with chunked
+//! transfer encoding, so that their first bytes are sent early and they are
+//! never held in memory.  Bodies whose encoder is flushed, as described below,
+//! and bodies compressed as a transfer coding are always streamed.
+//!
+//! Responses without a body, or with an empty sized body, are left untouched,
+//! since compressing them would add encoding framing to an otherwise empty
+//! response.
+//!
+//! The compressed body is produced lazily while Rocket writes the response.
+//! When the wrapped stream has no data ready, the encoder returns
+//! `Poll::Pending` and resumes once it is woken, so slow or backpressured
+//! streams are neither busy-polled nor buffered in memory.  Note however that
+//! the encoders buffer output to achieve a good compression ratio, so data from
+//! a slow stream may be delayed until enough of it has been produced or the
+//! stream ends.  This is why `text/event-stream` responses, as well as
+//! newline-delimited and sequenced JSON streams (`application/x-ndjson` and
+//! `application/json-seq`), are excluded by default.
+//!
+//! Such streams can still be compressed without delaying their data by
+//! flushing the encoder whenever the stream yields, which costs some
+//! compression ratio.  For a streaming JSON API, opt the stream types back in
+//! on the fairing with [`Compression::flush_each_chunk_for`], or wrap
+//! individual streams in [`Compress::streaming`]:
+//!
+//! ```rust
+//! use rocket::http::MediaType;
+//! use rocket_async_compression::Compression;
+//!
+//! rocket::build()
+//!     // ...
+//!     .attach(Compression::fairing().flush_each_chunk_for(vec![
+//!         MediaType::new("application", "x-ndjson"),
+//!         MediaType::new("application", "json-seq"),
+//!     ]))
+//!     // ...
+//!     # ;
+//! ```
+//!
+//! [`CachedCompression`] never caches these streams: it buffers whole bodies,
+//! so it leaves every excluded type alone.
+//!
 //! ## Security Implications
 //!
 //! In some cases, HTTP compression on a site served over HTTPS can make a web
 //! application vulnerable to attacks including BREACH. These risks should be
 //! evaluated in the context of your application before enabling compression.
+//! [`CachedCompression::pad_to_bucket`] reduces what gzip response sizes
+//! reveal by padding them to fixed buckets.
 
 #[macro_use]
 extern crate log;
 
+#[cfg(not(any(feature = "gzip", feature = "brotli")))]
+compile_error!("at least one of the `gzip` and `brotli` features must be enabled");
+
+mod accept;
+mod backend;
+mod breaker;
+mod cache;
+mod cached_responder;
+mod compressible;
+mod config;
+mod counting;
+mod decompressed;
+#[cfg(feature = "digest")]
+mod digest;
+mod events;
 mod fairing;
+mod file_server;
+mod info;
+mod named_file;
+mod network;
+#[cfg(feature = "gzip")]
+mod padding;
+mod precompress;
+mod precompressed;
+#[cfg(feature = "prometheus")]
+mod prometheus_metrics;
+mod request_body;
 mod responder;
+mod sniff;
+mod stats;
+mod streaming;
+pub mod util;
+mod zstd_params;
 
 pub use self::{
-    fairing::{CachedCompression, Compression},
-    responder::Compress,
+    backend::{
+        compress_response, register_encoder, CodingEncoder, CompressionBackend, CustomCoding,
+        DefaultBackend,
+    },
+    breaker::{LoadBreaker, LoadSource},
+    cached_responder::{set_cached_compress_max_bytes, CachedCompress},
+    config::CompressionConfig,
+    counting::CountingReader,
+    decompressed::{Decompressed, DecompressedError, FromDecompressedData},
+    events::{CompressionEvent, CompressionHook, ResponseHook},
+    fairing::{
+        CachedCompression, CachedPathRules, Compression, FailurePolicy, HostConfig, ProxiedPolicy,
+    },
+    file_server::{CompressedFileServer, MissingSiblings},
+    info::CompressionInfo,
+    named_file::NamedCompressedFile,
+    network::{InvalidNetwork, IpNetwork},
+    precompress::{precompress_dir, PrecompressOptions, PrecompressSummary},
+    precompressed::PrecompressedBytes,
+    request_body::{CompressedBody, DecompressionLimits, RequestDecodingError},
+    responder::{Compress, Compressed, ConfiguredCompress, UnacceptedEncodingPolicy},
+    stats::{CompressionStats, SkipReason},
+    zstd_params::ZstdParams,
 };
 
+#[cfg(feature = "metrics")]
+pub use self::stats::SizeTotals;
+
+#[cfg(feature = "prometheus")]
+pub use self::prometheus_metrics::PrometheusMetrics;
+
+#[cfg(feature = "digest")]
+pub use self::digest::DigestAlgorithm;
+
+pub(crate) use accept::AcceptEncoding;
 pub use async_compression::Level;
+use events::{log_skip, logging_hook, FairingLabel, ReportingReader};
 use fairing::CachedEncoding;
+use lazy_static::lazy_static;
 use rocket::{
-    http::{hyper::header::CONTENT_ENCODING, MediaType},
-    response::Body,
+    http::{
+        hyper::header::{CONTENT_ENCODING, TRANSFER_ENCODING},
+        MediaType,
+    },
+    tokio::io::AsyncRead,
     Request, Response,
 };
+pub use rocket_async_compression_codegen::compress;
+use std::{
+    pin::Pin,
+    sync::{atomic::AtomicU64, Arc, RwLock},
+};
+use streaming::{BufferedBody, FlushingEncoder};
 
+// Default media types are built from their components rather than parsed so that they can't fail
+// at runtime.
+lazy_static! {
+    /// Content types that are not compressed unless explicitly requested.
+    pub(crate) static ref EXCLUSIONS: Vec<MediaType> = vec![
+        MediaType::new("application", "gzip"),
+        MediaType::new("application", "zip"),
+        MediaType::new("image", "*"),
+        MediaType::new("video", "*"),
+        MediaType::new("application", "octet-stream"),
+        MediaType::new("text", "event-stream"),
+        MediaType::new("application", "x-ndjson"),
+        MediaType::new("application", "json-seq"),
+    ];
+    /// Content types whose bodies are themselves compressed data.  These are never compressed,
+    /// regardless of any exclusion lists.
+    static ref COMPRESSED_CONTENT_TYPES: RwLock<Vec<MediaType>> = RwLock::new(vec![
+        MediaType::new("application", "gzip"),
+        MediaType::new("application", "zip"),
+        MediaType::new("application", "zstd"),
+        MediaType::new("application", "x-bzip2"),
+        MediaType::new("application", "x-xz"),
+        MediaType::new("application", "x-7z-compressed"),
+        MediaType::new("application", "vnd.rar"),
+    ]);
+}
+
+/// Registers `media_type` as a content type whose bodies are already
+/// compressed.
+///
+/// Unlike the exclusion lists configured on the fairings and responders, which
+/// only control whether this crate *should* compress a response, registered
+/// types describe the data itself: responses with these types are never
+/// compressed by any fairing or responder, even if they are not excluded.  Use
+/// this for formats that are compressed internally, such as
+/// `application/x-protobuf` payloads produced by a compressing serializer.
+///
+/// `*` may be used as the subtype to register a whole top-level type.  Common
+/// archive formats such as `application/gzip` and `application/zip` are
+/// registered by default.
+///
+/// ```rust
+/// use rocket::http::MediaType;
+///
+/// rocket_async_compression::register_compressed_content_type(MediaType::new(
+///     "application",
+///     "x-protobuf",
+/// ));
+/// ```
+pub fn register_compressed_content_type(media_type: MediaType) {
+    let mut types = COMPRESSED_CONTENT_TYPES
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if !types.contains(&media_type) {
+        types.push(media_type);
+    }
+}
+
+/// Returns `true` if `media_type` has been registered as already compressed
+/// with [`register_compressed_content_type`].
+pub fn is_compressed_content_type(media_type: &MediaType) -> bool {
+    COMPRESSED_CONTENT_TYPES
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .iter()
+        .any(|compressed| CompressionUtils::media_type_matches(compressed, media_type))
+}
+
+/// The conventional name of the header carrying the size of a response body
+/// before compression, for use with
+/// [`Compression::original_length_header`] and
+/// [`CachedCompression::original_length_header`].
+pub const ORIGINAL_CONTENT_LENGTH: &str = "x-original-content-length";
+
+/// Largest sized body whose compressed form is buffered to be sent with a
+/// `Content-Length` unless configured otherwise.  See the crate documentation.
+pub(crate) const DEFAULT_MAX_BUFFERED_BODY: usize = 64 * 1024;
+
+/// Returns the outermost content coding applied to `response`'s body
+/// according to its `Content-Encoding` headers, or `None` if it has none.
+///
+/// When several codings are listed, they are applied in order, so the last
+/// one is returned: it is the one that must be decoded first.
+///
+/// ```rust
+/// use rocket::{http::Header, Response};
+/// use rocket_async_compression::{content_encoding, Encoding};
+///
+/// let mut response = Response::new();
+/// response.set_header(Header::new("Content-Encoding", "gzip"));
+/// assert_eq!(content_encoding(&response), Some(Encoding::Gzip));
+/// ```
+pub fn content_encoding(response: &Response<'_>) -> Option<Encoding> {
+    let coding = response
+        .headers()
+        .get("Content-Encoding")
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|coding| !coding.is_empty())
+        .last()?;
+    match coding.parse() {
+        Ok(encoding) => Some(encoding),
+        Err(never) => match never {},
+    }
+}
+
+/// A content coding, as found in `Accept-Encoding` and `Content-Encoding`
+/// headers.
+///
+/// Encodings are parsed case-insensitively with [`str::parse`] and formatted as
+/// their header value with [`Display`](std::fmt::Display), and can be used as
+/// map keys.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum Encoding {
     /// The `chunked` encoding.
     Chunked,
@@ -55,6 +316,9 @@ pub enum Encoding {
     Brotli,
     /// The `gzip` encoding.
     Gzip,
+    /// The `zstd` encoding.  Responses are only compressed with it when the
+    /// `zstd` feature is enabled.
+    Zstd,
     /// The `deflate` encoding.
     Deflate,
     /// The `compress` encoding.
@@ -67,34 +331,195 @@ pub enum Encoding {
     EncodingExt(String),
 }
 
-impl std::fmt::Display for Encoding {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(match *self {
+impl Encoding {
+    /// Returns the encoding's name as it appears in headers.
+    pub fn header_value(&self) -> &str {
+        match *self {
             Encoding::Chunked => "chunked",
             Encoding::Brotli => "br",
             Encoding::Gzip => "gzip",
+            Encoding::Zstd => "zstd",
             Encoding::Deflate => "deflate",
             Encoding::Compress => "compress",
             Encoding::Identity => "identity",
             Encoding::Trailers => "trailers",
             Encoding::EncodingExt(ref s) => s.as_ref(),
-        })
+        }
+    }
+
+    /// Returns `true` if the encoding is a known compression format, as opposed
+    /// to `identity`, a transfer coding or an unknown coding.
+    pub fn is_compression(&self) -> bool {
+        matches!(
+            self,
+            Encoding::Brotli
+                | Encoding::Gzip
+                | Encoding::Zstd
+                | Encoding::Deflate
+                | Encoding::Compress
+        )
     }
 }
 
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.header_value())
+    }
+}
+
+/// Codings are case-insensitive, so they are lowercased before being parsed and
+/// unknown codings are wrapped in [`Encoding::EncodingExt`] in lowercase.  This
+/// keeps equal codings equal as map keys and in comparisons.  The legacy
+/// `x-gzip` and `x-compress` aliases parse as [`Encoding::Gzip`] and
+/// [`Encoding::Compress`].
 impl std::str::FromStr for Encoding {
     type Err = std::convert::Infallible;
 
     fn from_str(s: &str) -> Result<Encoding, std::convert::Infallible> {
-        match s {
+        let s = s.to_ascii_lowercase();
+        match s.as_str() {
             "chunked" => Ok(Encoding::Chunked),
             "br" => Ok(Encoding::Brotli),
             "deflate" => Ok(Encoding::Deflate),
-            "gzip" => Ok(Encoding::Gzip),
-            "compress" => Ok(Encoding::Compress),
+            "gzip" | "x-gzip" => Ok(Encoding::Gzip),
+            "zstd" => Ok(Encoding::Zstd),
+            "compress" | "x-compress" => Ok(Encoding::Compress),
             "identity" => Ok(Encoding::Identity),
             "trailers" => Ok(Encoding::Trailers),
-            _ => Ok(Encoding::EncodingExt(s.to_owned())),
+            _ => Ok(Encoding::EncodingExt(s)),
+        }
+    }
+}
+
+/// What to do with responses that don't have a `Content-Type` header.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum NoContentTypePolicy {
+    /// Compress untyped responses like any other response.
+    #[default]
+    Compress,
+    /// Leave untyped responses uncompressed.
+    Skip,
+    /// Peek at the first bytes of untyped bodies with a known size and leave
+    /// them uncompressed if they start with the signature of a compressed
+    /// format, such as gzip, zip or PNG, or look like random binary data.
+    /// Text and other bodies are compressed.
+    ///
+    /// Only the [`Compression`] fairing sniffs bodies; elsewhere this is the
+    /// same as [`NoContentTypePolicy::Compress`].
+    Sniff,
+}
+
+/// What the [`Compression`] fairing does at launch when it finds event stream
+/// routes whose responses it would compress.
+///
+/// Compressing server-sent events buffers them in the encoder, so clients
+/// receive them late or not at all.  `text/event-stream` is excluded by
+/// default, but replacing the exclusion list can silently drop it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum EventStreamCheck {
+    /// Don't inspect routes.
+    Ignore,
+    /// Log a warning naming the affected routes.
+    #[default]
+    Warn,
+    /// Log an error naming the affected routes and abort the launch.
+    Abort,
+}
+
+/// Settings that control how a single response is compressed.
+pub(crate) struct CompressionOptions<'a> {
+    pub exclusions: &'a [MediaType],
+    pub level: Level,
+    pub prefer_gzip_for_user_agents: &'a [String],
+    /// When set, only this encoding is used; the response is left uncompressed
+    /// if the client doesn't accept it.
+    pub forced_encoding: Option<&'a Encoding>,
+    /// Compress regardless of the `Content-Type`, even if it is registered as
+    /// already compressed.
+    pub forced: bool,
+    /// Largest sized body compressed in full to be sent with a
+    /// `Content-Length`.
+    pub max_buffered_body: usize,
+    /// Sized bodies smaller than this many bytes are left uncompressed.
+    pub min_size: Option<usize>,
+    pub no_content_type: NoContentTypePolicy,
+    /// Flush the encoder whenever the body yields data instead of buffering
+    /// for a better compression ratio.
+    pub flush_each_chunk: bool,
+    /// Flush the encoder every this many input bytes, for output that doesn't
+    /// depend on how the body is split into chunks.
+    pub flush_every: Option<usize>,
+    pub zstd: ZstdParams,
+    /// Reported to once the compressed body has been read to the end.
+    pub on_compressed: Option<&'a CompressionHook>,
+    /// Header read instead of `Accept-Encoding` when present.
+    pub accept_encoding_header: Option<&'a str>,
+    /// Log every compressed and skipped response at debug level.
+    pub log: bool,
+    /// Set `Content-Encoding: identity` on responses that are considered but
+    /// left uncompressed, unless they already have a `Content-Encoding`.
+    pub mark_identity: bool,
+    /// Encodes bodies instead of the built-in encoders when set.  Only
+    /// encodings it supports are negotiated.
+    pub backend: Option<&'a dyn CompressionBackend>,
+    /// Codings negotiated alongside, or replacing, the built-in ones.
+    pub custom_codings: &'a [CustomCoding],
+    /// Called with the response once its body has been replaced with the
+    /// compressed one.
+    pub after_compression: Option<&'a ResponseHook>,
+    /// Encodings that may be produced, in order of preference, or `None` for
+    /// every supported encoding.
+    pub server_encodings: Option<&'a [Encoding]>,
+    /// Compresses with `Transfer-Encoding` instead of `Content-Encoding`.
+    pub transfer_coding: bool,
+    /// The name of the fairing compressing the response, added to log lines.
+    pub fairing_name: Option<&'static str>,
+    /// Header set to the size of compressed bodies before compression, when
+    /// it is known.
+    pub original_length_header: Option<&'a str>,
+    /// Only compresses content types known to compress well.
+    pub compressible_only: bool,
+    /// Leaves responses uncompressed while open.
+    pub load_breaker: Option<&'a LoadBreaker>,
+    /// Header names added to `Vary` after `Accept-Encoding` when compressing.
+    pub vary_headers: &'a [String],
+    /// Paths whose responses are never compressed, checked first.
+    pub always_skip_paths: &'a [String],
+}
+
+impl<'a> CompressionOptions<'a> {
+    /// Returns options compressing at `level` every response whose content
+    /// type isn't in `exclusions`, with every other setting at its default.
+    /// Entry points override the settings they support with the struct update
+    /// syntax, so that new settings get the same default everywhere.
+    pub(crate) fn new(exclusions: &'a [MediaType], level: Level) -> CompressionOptions<'a> {
+        CompressionOptions {
+            exclusions,
+            level,
+            prefer_gzip_for_user_agents: &[],
+            forced_encoding: None,
+            forced: false,
+            max_buffered_body: DEFAULT_MAX_BUFFERED_BODY,
+            min_size: None,
+            no_content_type: NoContentTypePolicy::Compress,
+            flush_each_chunk: false,
+            flush_every: None,
+            zstd: ZstdParams::default(),
+            on_compressed: None,
+            accept_encoding_header: None,
+            log: false,
+            mark_identity: false,
+            backend: None,
+            custom_codings: &[],
+            after_compression: None,
+            server_encodings: None,
+            transfer_coding: false,
+            fairing_name: None,
+            original_length_header: None,
+            compressible_only: false,
+            load_breaker: None,
+            vary_headers: &[],
+            always_skip_paths: &[],
         }
     }
 }
@@ -106,6 +531,18 @@ impl CompressionUtils {
         response.headers().get("Content-Encoding").next().is_some()
     }
 
+    /// Returns `true` if the response has no body or an empty sized body.
+    fn empty_body(response: &Response<'_>) -> bool {
+        response.body().is_none() || response.body().preset_size() == Some(0)
+    }
+
+    /// Returns `true` if `response` is a `3xx` redirection, whose body, if
+    /// any, is a tiny fallback for clients that don't follow `Location` and
+    /// isn't worth compressing.
+    fn is_redirection(response: &Response<'_>) -> bool {
+        response.status().class() == rocket::http::StatusClass::Redirection
+    }
+
     fn set_body_and_encoding<'r, B: rocket::tokio::io::AsyncRead + Send + 'r>(
         response: &'_ mut Response<'r>,
         body: B,
@@ -118,112 +555,537 @@ impl CompressionUtils {
         response.set_streamed_body(body);
     }
 
+    /// Returns the size of the response body, either preset or declared by the
+    /// handler with a `Content-Length` header for a streamed body.
+    fn known_size(response: &Response<'_>) -> Option<usize> {
+        response.body().preset_size().or_else(|| {
+            response
+                .headers()
+                .get_one("Content-Length")
+                .and_then(|length| length.trim().parse().ok())
+        })
+    }
+
+    /// Sets the header `name`, if any, to `size`, the size of the response
+    /// body before compression.  Nothing is set if the size is unknown.
+    fn set_original_length(response: &mut Response<'_>, name: Option<&str>, size: Option<u64>) {
+        if let (Some(name), Some(size)) = (name, size) {
+            response.set_header(::rocket::http::Header::new(
+                name.to_owned(),
+                size.to_string(),
+            ));
+        }
+    }
+
+    /// Adds `name` to the response's `Vary` header, preserving any names that
+    /// are already listed.
+    fn add_vary(response: &mut Response<'_>, name: &str) {
+        let mut names: Vec<String> = response
+            .headers()
+            .get("Vary")
+            .flat_map(|vary| vary.split(','))
+            .map(|vary| vary.trim())
+            .filter(|vary| !vary.is_empty())
+            .map(|vary| vary.to_owned())
+            .collect();
+        if names
+            .iter()
+            .any(|existing| existing == "*" || existing.eq_ignore_ascii_case(name))
+        {
+            return;
+        }
+
+        names.push(name.to_owned());
+        response.set_header(::rocket::http::Header::new("Vary", names.join(", ")));
+    }
+
+    /// Adds `Accept-Encoding`, then `extra`, to the `Vary` header of a
+    /// compressed response.
+    fn set_vary(response: &mut Response<'_>, extra: &[String]) {
+        Self::add_vary(response, "Accept-Encoding");
+        for name in extra {
+            Self::add_vary(response, name);
+        }
+    }
+
     fn skip_encoding(
         content_type: &Option<rocket::http::ContentType>,
         exclusions: &[MediaType],
+        no_content_type: NoContentTypePolicy,
     ) -> bool {
         match content_type {
-            Some(content_type) => exclusions.iter().any(|exc_media_type| {
-                if exc_media_type.sub() == "*" {
-                    *exc_media_type.top() == *content_type.top()
-                } else {
-                    *exc_media_type == *content_type.media_type()
-                }
-            }),
-            None => false,
+            Some(content_type) => {
+                let media_type = content_type.media_type();
+                is_compressed_content_type(media_type)
+                    || exclusions
+                        .iter()
+                        .any(|exc_media_type| Self::media_type_matches(exc_media_type, media_type))
+            }
+            None => no_content_type == NoContentTypePolicy::Skip,
         }
     }
 
-    /// Returns a tuple of the form (accepts_gzip, accepts_br).
-    fn accepted_algorithms(request: &Request<'_>) -> (bool, bool) {
-        request
-            .headers()
-            .get("Accept-Encoding")
-            .flat_map(|accept| accept.split(','))
-            .map(|accept| accept.trim())
-            .fold((false, false), |(accepts_gzip, accepts_br), encoding| {
-                (
-                    accepts_gzip || encoding == "gzip",
-                    accepts_br || encoding == "br",
-                )
-            })
-    }
-
-    async fn compress_body<'r>(
-        body: Body<'r>,
+    /// Returns `true` if `media_type` matches `pattern`, which may use `*` as
+    /// its subtype to match any subtype.
+    ///
+    /// Only the type and subtype are compared, case-insensitively, so that
+    /// `application/json`, `Application/JSON` and
+    /// `application/json; charset=utf-8` all match the same patterns regardless
+    /// of their parameters or their order.
+    fn media_type_matches(pattern: &MediaType, media_type: &MediaType) -> bool {
+        // `top` and `sub` are `UncasedStr`s, which compare case-insensitively.
+        pattern.top() == media_type.top()
+            && (pattern.sub() == "*" || pattern.sub() == media_type.sub())
+    }
+
+    /// Returns a tuple of the form (accepts_gzip, accepts_br).  The client's
+    /// preferences are read from `accept_header` instead of `Accept-Encoding`
+    /// when it is set and present.
+    fn accepted_algorithms(request: &Request<'_>, accept_header: Option<&str>) -> (bool, bool) {
+        let accept = AcceptEncoding::from_request_header(request, accept_header);
+        (
+            accept.accepts(&Encoding::Gzip),
+            accept.accepts(&Encoding::Brotli),
+        )
+    }
+
+    /// Returns `true` if the request's `Accept-Encoding` allows `encoding`.
+    fn accepts_encoding(request: &Request<'_>, encoding: &Encoding) -> bool {
+        AcceptEncoding::from_request(request).accepts(encoding)
+    }
+
+    /// Returns the level used by [`CompressionUtils::compress_body`] for
+    /// `encoding` when `level` is requested.
+    fn effective_level(
+        encoding: CachedEncoding,
+        level: async_compression::Level,
+    ) -> async_compression::Level {
+        match (encoding, level) {
+            // The broli library used internally by `async-compression` has a default compression level of "best", or 11.  This
+            // is unsuitable for dynamic data and makes compression extremely slow.
+            //
+            // We set a compression level of 4 if the user requests default which matches the behavior of Nginx.
+            #[cfg(feature = "brotli")]
+            (CachedEncoding::Brotli, async_compression::Level::Default) => {
+                async_compression::Level::Precise(4)
+            }
+            (_, level) => level,
+        }
+    }
+
+    /// Clamps a [`Level::Precise`] quality to the range of `encoding`: 0 to 11
+    /// for brotli and 0 to 9 for gzip.  Other levels are returned unchanged.
+    fn clamp_precise(
+        encoding: &Encoding,
+        level: async_compression::Level,
+    ) -> async_compression::Level {
+        match (encoding, level) {
+            (Encoding::Brotli, async_compression::Level::Precise(quality)) => {
+                async_compression::Level::Precise(quality.clamp(0, 11))
+            }
+            (Encoding::Gzip, async_compression::Level::Precise(quality)) => {
+                async_compression::Level::Precise(quality.clamp(0, 9))
+            }
+            (_, level) => level,
+        }
+    }
+
+    async fn compress_body<R: AsyncRead + Unpin>(
+        body: R,
         encoding: CachedEncoding,
         level: async_compression::Level,
     ) -> std::io::Result<Vec<u8>> {
+        util::compress_reader(body, &encoding.encoding(), level).await
+    }
+
+    /// Wraps `body` in an encoder that compresses it with `encoding` as it is
+    /// read.  If `flush_each_chunk` is set, the encoder flushes its output
+    /// every time `body` yields data, otherwise every `flush_every` input
+    /// bytes if set.  Only gzip, brotli and, with the `zstd`
+    /// feature, zstd are supported.  `level` is ignored for zstd, which uses
+    /// `zstd` instead.
+    fn streaming_encoder<'r, R: AsyncRead + Send + 'r>(
+        body: R,
+        encoding: &Encoding,
+        level: async_compression::Level,
+        flush_each_chunk: bool,
+        flush_every: Option<usize>,
+        zstd: &ZstdParams,
+    ) -> Pin<Box<dyn AsyncRead + Send + 'r>> {
+        let level = match encoding {
+            Encoding::Zstd => zstd.quality(),
+            _ => Self::clamp_precise(encoding, level),
+        };
+        if flush_each_chunk || flush_every.is_some() {
+            let flush_every = if flush_each_chunk { None } else { flush_every };
+            return Box::pin(FlushingEncoder::new(
+                Box::pin(body),
+                encoding,
+                level,
+                zstd,
+                flush_every,
+            ));
+        }
+
+        let body = rocket::tokio::io::BufReader::new(body);
         match encoding {
-            CachedEncoding::Brotli => {
-                // The broli library used internally by `async-compression` has a default compression level of "best", or 11.  This
-                // is unsuitable for dynamic data and makes compression extremely slow.
-                //
-                // We set a compression level of 4 if the user requests default which matches the behavior of Nginx.
-                let level = match level {
-                    async_compression::Level::Default => async_compression::Level::Precise(4),
-                    other => other,
-                };
-
-                let mut compressor = async_compression::tokio::bufread::BrotliEncoder::with_quality(
-                    rocket::tokio::io::BufReader::new(body),
-                    level,
-                );
-                let mut out = Vec::new();
-                rocket::tokio::io::copy(&mut compressor, &mut out).await?;
-                Ok(out)
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => Box::pin(
+                async_compression::tokio::bufread::BrotliEncoder::with_quality(body, level),
+            ),
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => {
+                Box::pin(async_compression::tokio::bufread::GzipEncoder::with_quality(body, level))
             }
-            CachedEncoding::Gzip => {
-                let mut compressor = async_compression::tokio::bufread::GzipEncoder::with_quality(
-                    rocket::tokio::io::BufReader::new(body),
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => Box::pin(
+                async_compression::tokio::bufread::ZstdEncoder::with_quality_and_params(
+                    body,
                     level,
-                );
-                let mut out = Vec::new();
-                rocket::tokio::io::copy(&mut compressor, &mut out).await?;
-                Ok(out)
+                    &zstd.parameters(),
+                ),
+            ),
+            other => unreachable!("no streaming encoder for {}", other),
+        }
+    }
+
+    /// Returns `true` if the request's `User-Agent` contains any of the
+    /// provided substrings.
+    fn user_agent_matches(request: &Request<'_>, user_agents: &[String]) -> bool {
+        if user_agents.is_empty() {
+            return false;
+        }
+
+        request.headers().get("User-Agent").any(|ua| {
+            user_agents
+                .iter()
+                .any(|needle| ua.contains(needle.as_str()))
+        })
+    }
+
+    /// Picks the encoding to use for the response, or `None` if the response
+    /// should be left uncompressed.
+    fn negotiate_encoding(
+        request: &Request<'_>,
+        options: &CompressionOptions<'_>,
+    ) -> Option<Encoding> {
+        // Transfer codings are negotiated with `TE` alone; falling back to `Accept-Encoding` would
+        // send them to clients that only accept content codings.
+        let accept = if options.transfer_coding {
+            AcceptEncoding::parse(request.headers().get("TE"))
+        } else {
+            AcceptEncoding::from_request_header(request, options.accept_encoding_header)
+        };
+
+        // Some clients advertise brotli support but decode it incorrectly.  Brotli is treated as
+        // unacceptable for them so they either get gzip or an uncompressed response.
+        //
+        // zstd comes last so that it is only picked over brotli and gzip if the client prefers it.
+        // Both lists are the same when only gzip is enabled.
+        #[allow(clippy::if_same_then_else)]
+        let candidates: &[Encoding] =
+            if Self::user_agent_matches(request, options.prefer_gzip_for_user_agents) {
+                &[
+                    #[cfg(feature = "gzip")]
+                    Encoding::Gzip,
+                ]
+            } else {
+                &[
+                    #[cfg(feature = "brotli")]
+                    Encoding::Brotli,
+                    #[cfg(feature = "gzip")]
+                    Encoding::Gzip,
+                    #[cfg(feature = "zstd")]
+                    Encoding::Zstd,
+                ]
+            };
+
+        let registered = backend::registered_encodings();
+        let supported: Vec<Encoding>;
+        let candidates = if options.backend.is_none()
+            && options.custom_codings.is_empty()
+            && registered.is_empty()
+        {
+            candidates
+        } else {
+            let mut prioritized: Vec<(i32, Encoding)> = candidates
+                .iter()
+                .filter(|encoding| {
+                    options
+                        .backend
+                        .is_none_or(|backend| backend.supports(encoding))
+                })
+                .map(|encoding| (0, encoding.clone()))
+                .collect();
+            for coding in options.custom_codings {
+                prioritized.retain(|(_, encoding)| *encoding != coding.encoding);
+                prioritized.push((coding.priority, coding.encoding.clone()));
+            }
+            // The sort is stable, so codings with the same priority keep their order.
+            prioritized.sort_by_key(|(priority, _)| std::cmp::Reverse(*priority));
+            let mut prioritized: Vec<Encoding> = prioritized
+                .into_iter()
+                .map(|(_, encoding)| encoding)
+                .collect();
+            // Globally registered codings come last so that the others take precedence.
+            for encoding in registered {
+                if !prioritized.contains(&encoding) {
+                    prioritized.push(encoding);
+                }
+            }
+            supported = prioritized;
+            &supported[..]
+        };
+
+        // The server's list decides the preference order, but only encodings that can actually be
+        // produced are kept.
+        let allowed: Vec<Encoding>;
+        let candidates = match options.server_encodings {
+            Some(server_encodings) => {
+                allowed = server_encodings
+                    .iter()
+                    .filter(|encoding| candidates.contains(encoding))
+                    .cloned()
+                    .collect();
+                &allowed[..]
+            }
+            None => candidates,
+        };
+
+        match options.forced_encoding {
+            Some(forced) if candidates.contains(forced) && accept.accepts(forced) => {
+                Some(forced.clone())
             }
+            Some(_) => None,
+            None => accept.preferred(candidates).cloned(),
         }
     }
 
-    fn compress_response<'r>(
+    /// Returns `true` if `request` is for one of the paths whose responses are
+    /// never compressed.
+    fn always_skips(request: &Request<'_>, options: &CompressionOptions<'_>) -> bool {
+        let path = request.uri().path();
+        options
+            .always_skip_paths
+            .iter()
+            .any(|skipped| path.as_str() == skipped)
+    }
+
+    /// Returns `false` if no encoding can ever be negotiated with `options`, so that responses can
+    /// be passed through without looking at the request or the response.
+    fn can_encode(options: &CompressionOptions<'_>) -> bool {
+        if options.server_encodings.is_some_and(<[Encoding]>::is_empty) {
+            return false;
+        }
+
+        cfg!(any(feature = "brotli", feature = "gzip", feature = "zstd"))
+            || options.backend.is_some()
+            || !options.custom_codings.is_empty()
+            || !backend::registered_encodings().is_empty()
+    }
+
+    /// Decides what [`CompressionUtils::compress_response`] does with
+    /// `response`: `None` if it is left alone without being counted, the
+    /// reason it is left uncompressed, or the encoding it is compressed with.
+    fn decide(
         request: &Request<'_>,
-        response: &'_ mut Response<'r>,
-        exclusions: &[MediaType],
-        level: async_compression::Level,
-    ) {
-        if CompressionUtils::already_encoded(response) {
-            return;
+        response: &Response<'_>,
+        options: &CompressionOptions<'_>,
+    ) -> Option<Result<Encoding, SkipReason>> {
+        if Self::always_skips(request, options)
+            || !Self::can_encode(options)
+            || CompressionUtils::empty_body(response)
+            || CompressionUtils::is_redirection(response)
+        {
+            return None;
+        }
+
+        if CompressionUtils::already_encoded(response)
+            || (options.transfer_coding && response.headers().contains(TRANSFER_ENCODING.as_str()))
+        {
+            return Some(Err(SkipReason::AlreadyEncoded));
         }
 
         let content_type = response.content_type();
 
-        if CompressionUtils::skip_encoding(&content_type, exclusions) {
-            return;
+        // An explicit `force()` wins over every content type check, including the registry of
+        // compressed types.
+        if !options.forced
+            && CompressionUtils::skip_encoding(
+                &content_type,
+                options.exclusions,
+                options.no_content_type,
+            )
+        {
+            return Some(Err(SkipReason::ExcludedType));
+        }
+        if let (false, true, Some(content_type)) =
+            (options.forced, options.compressible_only, &content_type)
+        {
+            if !compressible::is_compressible(content_type.media_type()) {
+                return Some(Err(SkipReason::ExcludedType));
+            }
         }
 
-        let (accepts_gzip, accepts_br) = Self::accepted_algorithms(request);
+        // Compressing very small bodies isn't worth it and can even make them larger.  Bodies with an
+        // unknown size are always compressed.
+        if let (Some(min_size), Some(size)) = (options.min_size, Self::known_size(response)) {
+            if size < min_size {
+                return Some(Err(SkipReason::TooSmall));
+            }
+        }
 
-        if !accepts_gzip && !accepts_br {
-            return;
+        // The load is only sampled for responses that would otherwise be compressed.
+        if options.load_breaker.is_some_and(LoadBreaker::is_open) {
+            return Some(Err(SkipReason::Overloaded));
         }
 
-        let body = response.body_mut().take();
+        Some(Self::negotiate_encoding(request, options).ok_or(SkipReason::NotAccepted))
+    }
 
-        // Compression is done when the request accepts brotli or gzip encoding
-        if accepts_br {
-            let compressor = async_compression::tokio::bufread::BrotliEncoder::with_quality(
-                rocket::tokio::io::BufReader::new(body),
-                level,
-            );
+    fn compress_response<'r>(
+        request: &Request<'_>,
+        response: &'_ mut Response<'r>,
+        options: &CompressionOptions<'_>,
+    ) {
+        let decision = match Self::decide(request, response, options) {
+            Some(decision) => decision,
+            None => return,
+        };
 
-            CompressionUtils::set_body_and_encoding(response, compressor, Encoding::Brotli);
-        } else if accepts_gzip {
-            let compressor = async_compression::tokio::bufread::GzipEncoder::with_quality(
-                rocket::tokio::io::BufReader::new(body),
-                level,
-            );
+        let stats = CompressionStats::global();
+        stats.record_considered();
+        let skip = |response: &mut Response<'r>, reason| {
+            stats.record_skipped(reason);
+            CompressionInfo::skipped(reason).record(request);
+            if options.log {
+                log_skip(
+                    request.uri().path().as_str(),
+                    reason,
+                    FairingLabel(options.fairing_name),
+                );
+            }
+            if options.mark_identity && reason != SkipReason::AlreadyEncoded {
+                response.set_header(::rocket::http::Header::new(
+                    CONTENT_ENCODING.as_str(),
+                    Encoding::Identity.to_string(),
+                ));
+            }
+        };
+        let encoding = match decision {
+            Ok(encoding) => encoding,
+            Err(reason) => {
+                skip(response, reason);
+                return;
+            }
+        };
+        #[cfg(feature = "metrics")]
+        let content_type = response.content_type();
+        let known_size = Self::known_size(response);
+
+        // Small sized bodies are compressed in full so that they keep a `Content-Length`, while
+        // large and chunked bodies are streamed so that their first bytes are sent early.
+        let buffer = !options.transfer_coding
+            && !options.flush_each_chunk
+            && options.flush_every.is_none()
+            && response
+                .body()
+                .preset_size()
+                .is_some_and(|size| size <= options.max_buffered_body);
+
+        stats.record_encoding(&encoding);
+        let original_size = Arc::new(AtomicU64::new(0));
+        let body = CountingReader::new(response.body_mut().take(), original_size.clone());
+        let body = CountingReader::new(body, stats.bytes_in.clone());
+        #[cfg(feature = "metrics")]
+        let counters = stats.content_type_counters(content_type.as_ref());
+        #[cfg(feature = "metrics")]
+        let body = CountingReader::new(body, counters.original.clone());
+
+        let custom_encoder = options
+            .custom_codings
+            .iter()
+            .find(|coding| coding.encoding == encoding)
+            .map(|coding| coding.encoder.clone())
+            .or_else(|| backend::registered_encoder(&encoding));
+        let compressor = match (custom_encoder, options.backend) {
+            (Some(encoder), _) => encoder(Box::pin(body), options.level),
+            (None, Some(backend)) => backend.encode(Box::pin(body), &encoding, options.level),
+            (None, None) => Self::streaming_encoder(
+                body,
+                &encoding,
+                options.level,
+                options.flush_each_chunk,
+                options.flush_every,
+                &options.zstd,
+            ),
+        };
+        let compressor = CountingReader::new(compressor, stats.bytes_out.clone());
+        #[cfg(feature = "metrics")]
+        let compressor = CountingReader::new(compressor, counters.compressed.clone());
+
+        let mut hook = options.on_compressed.cloned();
+        if options.log {
+            hook = Some(logging_hook(hook, options.level, options.fairing_name));
+        }
+        #[cfg(feature = "prometheus")]
+        let hook = Some(PrometheusMetrics::hook(hook));
+        #[cfg(feature = "tracing")]
+        let hook = Some(
+            events::CompressionSpan::streaming(
+                request.uri().path().as_str(),
+                &encoding,
+                options.level,
+            )
+            .hook(hook),
+        );
+        CompressionInfo {
+            original_size: known_size.map(|size| size as u64),
+            ..CompressionInfo::compressed(encoding.clone(), options.level)
+        }
+        .record(request);
+        let compressor: Pin<Box<dyn AsyncRead + Send + 'r>> = match hook {
+            Some(hook) => Box::pin(ReportingReader::new(
+                compressor,
+                hook,
+                request.uri().path().to_string(),
+                encoding.clone(),
+                original_size,
+            )),
+            None => Box::pin(compressor),
+        };
+        // A `Content-Length` declared by the handler is the length of the uncompressed body.
+        response.remove_header("Content-Length");
+        if options.transfer_coding {
+            // Only the message is compressed, so the representation's headers such as
+            // `Content-Encoding` and `ETag` are left untouched.  The body is always streamed and
+            // framing is left to the server, which appends `chunked` to the transfer codings.
+            response.set_header(::rocket::http::Header::new(
+                TRANSFER_ENCODING.as_str(),
+                encoding.to_string(),
+            ));
+            response.set_streamed_body(compressor);
+        } else {
+            if buffer {
+                response.set_header(::rocket::http::Header::new(
+                    CONTENT_ENCODING.as_str(),
+                    encoding.to_string(),
+                ));
+                response.set_sized_body(None, BufferedBody::new(compressor));
+            } else {
+                CompressionUtils::set_body_and_encoding(response, compressor, encoding);
+            }
+            CompressionUtils::set_vary(response, options.vary_headers);
+        }
+
+        CompressionUtils::set_original_length(
+            response,
+            options.original_length_header,
+            known_size.map(|size| size as u64),
+        );
 
-            CompressionUtils::set_body_and_encoding(response, compressor, Encoding::Gzip);
+        if let Some(after_compression) = options.after_compression {
+            after_compression(response);
         }
     }
 }
@@ -0,0 +1,119 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use rocket::{
+    fs::NamedFile,
+    http::{hyper::header::CONTENT_ENCODING, ContentType, Header},
+    response::{self, Responder},
+    tokio::fs::File,
+    Request,
+};
+
+use crate::{AcceptEncoding, CompressionUtils, Encoding};
+
+/// A file responder that serves precompressed `.br` and `.gz` siblings of a
+/// file when the client accepts them.
+///
+/// Opening `app.js` also looks for `app.js.br` and `app.js.gz` next to it.
+/// When responding, the best sibling acceptable to the client is served with
+/// the appropriate `Content-Encoding`, falling back to the original file.
+/// Missing siblings are silently ignored.  The `Content-Type` is always derived
+/// from the original file's extension and `Vary: Accept-Encoding` is set.
+///
+/// # Usage
+///
+/// ```rust
+/// # #[macro_use] extern crate rocket;
+/// use std::path::{Path, PathBuf};
+///
+/// use rocket_async_compression::NamedCompressedFile;
+///
+/// #[get("/static/<file..>")]
+/// async fn files(file: PathBuf) -> Option<NamedCompressedFile> {
+///     NamedCompressedFile::open(Path::new("static/").join(file)).await.ok()
+/// }
+/// ```
+#[derive(Debug)]
+pub struct NamedCompressedFile {
+    path: PathBuf,
+    original: File,
+    gzip: Option<File>,
+    brotli: Option<File>,
+}
+
+impl NamedCompressedFile {
+    /// Opens the file at `path` along with any `.br` and `.gz` siblings.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the original file can't be opened, mirroring
+    /// [`NamedFile::open`].  Errors opening siblings are ignored.
+    pub async fn open<P: AsRef<Path>>(path: P) -> io::Result<NamedCompressedFile> {
+        let path = path.as_ref().to_path_buf();
+        let original = NamedFile::open(&path).await?.take_file();
+        let gzip = open_sibling(&path, "gz").await;
+        let brotli = open_sibling(&path, "br").await;
+
+        Ok(NamedCompressedFile {
+            path,
+            original,
+            gzip,
+            brotli,
+        })
+    }
+
+    /// Returns the path of the original file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Opens `<path>.<extension>` if it exists and is a regular file.
+async fn open_sibling(path: &Path, extension: &str) -> Option<File> {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(".");
+    sibling.push(extension);
+
+    let file = File::open(&sibling).await.ok()?;
+    match file.metadata().await {
+        Ok(metadata) if metadata.is_file() => Some(file),
+        _ => None,
+    }
+}
+
+impl<'r> Responder<'r, 'static> for NamedCompressedFile {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let accept = AcceptEncoding::from_request(request);
+        let mut available = Vec::with_capacity(2);
+        if self.brotli.is_some() {
+            available.push(Encoding::Brotli);
+        }
+        if self.gzip.is_some() {
+            available.push(Encoding::Gzip);
+        }
+
+        let (file, encoding) = match (accept.preferred(&available), self.brotli, self.gzip) {
+            (Some(Encoding::Brotli), Some(brotli), _) => (brotli, Some(Encoding::Brotli)),
+            (Some(Encoding::Gzip), _, Some(gzip)) => (gzip, Some(Encoding::Gzip)),
+            _ => (self.original, None),
+        };
+
+        let mut response = file.respond_to(request)?;
+        if let Some(content_type) = self
+            .path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(ContentType::from_extension)
+        {
+            response.set_header(content_type);
+        }
+        if let Some(encoding) = encoding {
+            response.set_header(Header::new(CONTENT_ENCODING.as_str(), encoding.to_string()));
+        }
+        CompressionUtils::add_vary(&mut response, "Accept-Encoding");
+
+        Ok(response)
+    }
+}
@@ -0,0 +1,126 @@
+use std::{fmt, net::IpAddr, str::FromStr};
+
+/// A range of IP addresses in CIDR notation, such as `10.0.0.0/8` or
+/// `fd00::/8`.  An address without a prefix length, such as `127.0.0.1`, is a
+/// range containing only itself.
+///
+/// IPv4 addresses mapped into IPv6, such as `::ffff:10.0.0.1`, are treated as
+/// the IPv4 addresses they map, so IPv4 ranges match them too.
+///
+/// ```rust
+/// use rocket_async_compression::IpNetwork;
+///
+/// let network: IpNetwork = "10.0.0.0/8".parse().unwrap();
+/// assert!(network.contains("10.1.2.3".parse().unwrap()));
+/// assert!(!network.contains("192.168.0.1".parse().unwrap()));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNetwork {
+    /// Returns the range of addresses sharing the first `prefix_len` bits of
+    /// `addr`, or `None` if `prefix_len` is longer than the address.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Option<IpNetwork> {
+        let addr = canonical(addr);
+        if prefix_len > max_prefix_len(addr) {
+            return None;
+        }
+        Some(IpNetwork { addr, prefix_len })
+    }
+
+    /// Returns `true` if `ip` is in the range.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, canonical(ip)) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Returns `addr`, or the IPv4 address it maps if it is an IPv4-mapped IPv6
+/// address.
+fn canonical(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(v6),
+        },
+        v4 => v4,
+    }
+}
+
+fn max_prefix_len(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+/// Returns `true` if the first `prefix_len` bits of `a` and `b` are equal.
+fn prefix_matches(a: &[u8], b: &[u8], prefix_len: u8) -> bool {
+    let full_bytes = (prefix_len / 8) as usize;
+    let remaining_bits = prefix_len % 8;
+    if a[..full_bytes] != b[..full_bytes] {
+        return false;
+    }
+    if remaining_bits == 0 {
+        return true;
+    }
+
+    let mask = 0xFFu8 << (8 - remaining_bits);
+    a[full_bytes] & mask == b[full_bytes] & mask
+}
+
+impl FromStr for IpNetwork {
+    type Err = InvalidNetwork;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidNetwork(s.to_owned());
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.trim().parse().map_err(|_| invalid())?,
+            None => return Ok(IpNetwork::from(addr)),
+        };
+        IpNetwork::new(addr, prefix_len).ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl From<IpAddr> for IpNetwork {
+    fn from(addr: IpAddr) -> Self {
+        let addr = canonical(addr);
+        IpNetwork {
+            addr,
+            prefix_len: max_prefix_len(addr),
+        }
+    }
+}
+
+/// The error returned when parsing an [`IpNetwork`] fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidNetwork(String);
+
+impl fmt::Display for InvalidNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid IP network `{}`", self.0)
+    }
+}
+
+impl std::error::Error for InvalidNetwork {}
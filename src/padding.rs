@@ -0,0 +1,48 @@
+/// Offset of the flags in a gzip header.
+const FLG: usize = 3;
+/// Flag set when the header has a CRC, which would have to cover the padding.
+const FHCRC: u8 = 0x02;
+/// Flag set when the header has an extra field.
+const FEXTRA: u8 = 0x04;
+/// Length of the fixed part of a gzip header, after which the extra field
+/// goes.
+const HEADER_LEN: usize = 10;
+/// Length of the extra field's length and of its padding subfield's header.
+const EXTRA_OVERHEAD: usize = 2 + 4;
+
+/// Pads the gzip member `body` with an extra field in its header so that its
+/// length is a multiple of `bucket`.  Decoders skip the extra field, so the
+/// decompressed content is unchanged.
+///
+/// Bodies that already have an extra field or a header CRC, or that would
+/// need an extra field longer than the gzip format allows, are returned
+/// unchanged.
+pub(crate) fn pad_gzip(mut body: Vec<u8>, bucket: usize) -> Vec<u8> {
+    if bucket == 0
+        || body.len() < HEADER_LEN
+        || body[..2] != [0x1f, 0x8b]
+        || body[FLG] & (FHCRC | FEXTRA) != 0
+        || body.len().is_multiple_of(bucket)
+    {
+        return body;
+    }
+
+    let mut padding = bucket - body.len() % bucket;
+    while padding < EXTRA_OVERHEAD {
+        padding += bucket;
+    }
+    let extra_len = padding - 2;
+    if extra_len > u16::MAX as usize {
+        return body;
+    }
+
+    // A single subfield with the ID `PD`, filled with zeroes.
+    let mut extra = Vec::with_capacity(padding);
+    extra.extend_from_slice(&(extra_len as u16).to_le_bytes());
+    extra.extend_from_slice(b"PD");
+    extra.extend_from_slice(&((extra_len - 4) as u16).to_le_bytes());
+    extra.resize(padding, 0);
+    body[FLG] |= FEXTRA;
+    body.splice(HEADER_LEN..HEADER_LEN, extra);
+    body
+}
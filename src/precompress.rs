@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use async_compression::Level;
+use rocket::tokio::fs;
+
+use crate::{
+    fairing::CachedEncoding,
+    file_server::{is_compressible, sibling_path, write_atomically},
+    CompressionUtils, Encoding,
+};
+
+/// Options for [`precompress_dir`].
+#[derive(Clone, Copy, Debug)]
+pub struct PrecompressOptions {
+    /// The level used to generate `.gz` siblings, or `None` to skip gzip.
+    pub gzip: Option<Level>,
+    /// The level used to generate `.br` siblings, or `None` to skip brotli.
+    pub brotli: Option<Level>,
+}
+
+impl Default for PrecompressOptions {
+    /// Generates both siblings at [`Level::Best`].
+    fn default() -> Self {
+        PrecompressOptions {
+            gzip: Some(Level::Best),
+            brotli: Some(Level::Best),
+        }
+    }
+}
+
+/// The result of a [`precompress_dir`] call.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PrecompressSummary {
+    /// The number of files for which at least one sibling was written.
+    pub files_processed: usize,
+    /// The number of siblings written.
+    pub siblings_written: usize,
+    /// The total number of bytes saved by the written siblings compared to
+    /// their original files.
+    pub bytes_saved: u64,
+}
+
+/// Walks `dir` recursively and writes `.gz` and `.br` siblings next to each
+/// compressible file, so that they can be served by
+/// [`CompressedFileServer`](crate::CompressedFileServer) or
+/// [`NamedCompressedFile`](crate::NamedCompressedFile).
+///
+/// Whether a file is compressible is determined from its extension using the
+/// default excluded content types; files with unknown extensions are skipped.
+/// Siblings that were modified after their original file are left alone, so
+/// calling this repeatedly only compresses files that changed.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use rocket_async_compression::{precompress_dir, PrecompressOptions};
+///
+/// # async fn run() -> std::io::Result<()> {
+/// let summary = precompress_dir("static".as_ref(), PrecompressOptions::default()).await?;
+/// println!("Saved {} bytes", summary.bytes_saved);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn precompress_dir(
+    dir: &Path,
+    options: PrecompressOptions,
+) -> std::io::Result<PrecompressSummary> {
+    let encodings = [
+        (Encoding::Gzip, options.gzip),
+        (Encoding::Brotli, options.brotli),
+    ];
+    let mut summary = PrecompressSummary::default();
+    let mut pending: Vec<PathBuf> = vec![dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            if !file_type.is_file() || !is_compressible(&path) {
+                continue;
+            }
+
+            let modified = entry.metadata().await?.modified()?;
+            let mut original: Option<Vec<u8>> = None;
+            let mut processed = false;
+            for (encoding, level) in &encodings {
+                let Some(level) = level else {
+                    continue;
+                };
+                let sibling = sibling_path(&path, encoding);
+                let fresh = match fs::metadata(&sibling).await {
+                    Ok(metadata) => metadata.modified()? >= modified,
+                    Err(_) => false,
+                };
+                if fresh {
+                    continue;
+                }
+
+                let original = match &mut original {
+                    Some(original) => original,
+                    None => original.insert(fs::read(&path).await?),
+                };
+                let Some(cached_encoding) = CachedEncoding::from_encoding(encoding) else {
+                    continue;
+                };
+                let compressed =
+                    CompressionUtils::compress_body(&original[..], cached_encoding, *level).await?;
+                write_atomically(&sibling, &compressed).await?;
+                debug!(
+                    "Precompressed {} from {} to {} bytes",
+                    sibling.display(),
+                    original.len(),
+                    compressed.len()
+                );
+
+                processed = true;
+                summary.siblings_written += 1;
+                summary.bytes_saved += original.len().saturating_sub(compressed.len()) as u64;
+            }
+            if processed {
+                summary.files_processed += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
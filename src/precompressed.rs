@@ -0,0 +1,136 @@
+use std::{borrow::Cow, io::Cursor};
+
+use rocket::{
+    http::{hyper::header::CONTENT_ENCODING, Header, Status},
+    response::{self, Responder, Response},
+    Request,
+};
+
+use crate::{CompressionUtils, Encoding};
+
+/// Serves bytes that were compressed ahead of time, picking the best
+/// representation for each request.
+///
+/// `PrecompressedBytes` holds up to three representations of the same
+/// content: the identity bytes and optional gzip and brotli compressed copies.
+/// The representation is chosen according to the request's `Accept-Encoding`
+/// header, preferring brotli over gzip over identity, and the
+/// `Content-Encoding` and `Vary` headers are set accordingly.
+///
+/// If no identity bytes are provided and the client accepts none of the
+/// compressed representations, the gzip (or, failing that, brotli) copy is
+/// decompressed on the fly.
+///
+/// This is ideal for artifacts compressed at build time, embedded in the
+/// binary or fetched from object storage.  Combine it with a `ContentType` to
+/// set the response's type.
+///
+/// # Usage
+///
+/// ```rust
+/// use rocket::http::ContentType;
+/// use rocket_async_compression::PrecompressedBytes;
+///
+/// # let (gzipped, brotlied): (&'static [u8], &'static [u8]) = (&[], &[]);
+/// let response = (
+///     ContentType::JavaScript,
+///     PrecompressedBytes::new().gzip(gzipped).brotli(brotlied),
+/// );
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct PrecompressedBytes {
+    identity: Option<Cow<'static, [u8]>>,
+    gzip: Option<Cow<'static, [u8]>>,
+    brotli: Option<Cow<'static, [u8]>>,
+}
+
+impl PrecompressedBytes {
+    /// Creates an empty set of representations.  At least one representation
+    /// must be added before responding.
+    pub fn new() -> PrecompressedBytes {
+        PrecompressedBytes::default()
+    }
+
+    /// Sets the uncompressed representation.
+    pub fn identity(self, bytes: impl Into<Cow<'static, [u8]>>) -> PrecompressedBytes {
+        PrecompressedBytes {
+            identity: Some(bytes.into()),
+            ..self
+        }
+    }
+
+    /// Sets the gzip-compressed representation.
+    pub fn gzip(self, bytes: impl Into<Cow<'static, [u8]>>) -> PrecompressedBytes {
+        PrecompressedBytes {
+            gzip: Some(bytes.into()),
+            ..self
+        }
+    }
+
+    /// Sets the brotli-compressed representation.
+    pub fn brotli(self, bytes: impl Into<Cow<'static, [u8]>>) -> PrecompressedBytes {
+        PrecompressedBytes {
+            brotli: Some(bytes.into()),
+            ..self
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for PrecompressedBytes {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let (accepts_gzip, accepts_br) = CompressionUtils::accepted_algorithms(request, None);
+        let mut response = Response::new();
+
+        match self {
+            PrecompressedBytes {
+                brotli: Some(brotli),
+                ..
+            } if accepts_br => {
+                response.set_header(Header::new(
+                    CONTENT_ENCODING.as_str(),
+                    Encoding::Brotli.to_string(),
+                ));
+                response.set_sized_body(brotli.len(), Cursor::new(brotli));
+            }
+            PrecompressedBytes {
+                gzip: Some(gzip), ..
+            } if accepts_gzip => {
+                response.set_header(Header::new(
+                    CONTENT_ENCODING.as_str(),
+                    Encoding::Gzip.to_string(),
+                ));
+                response.set_sized_body(gzip.len(), Cursor::new(gzip));
+            }
+            PrecompressedBytes {
+                identity: Some(identity),
+                ..
+            } => {
+                response.set_sized_body(identity.len(), Cursor::new(identity));
+            }
+            #[cfg(feature = "gzip")]
+            PrecompressedBytes {
+                gzip: Some(gzip), ..
+            } => {
+                response.set_streamed_body(async_compression::tokio::bufread::GzipDecoder::new(
+                    Cursor::new(gzip),
+                ));
+            }
+            #[cfg(feature = "brotli")]
+            PrecompressedBytes {
+                brotli: Some(brotli),
+                ..
+            } => {
+                response.set_streamed_body(async_compression::tokio::bufread::BrotliDecoder::new(
+                    Cursor::new(brotli),
+                ));
+            }
+            PrecompressedBytes { .. } => {
+                error!("`PrecompressedBytes` responded without any representations");
+                return Err(Status::InternalServerError);
+            }
+        }
+
+        CompressionUtils::add_vary(&mut response, "Accept-Encoding");
+        Ok(response)
+    }
+}
@@ -0,0 +1,147 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    core::Collector, Histogram, HistogramOpts, IntCounter, IntCounterVec, Opts, Registry,
+};
+
+use crate::{CompressionEvent, CompressionHook, Encoding};
+
+lazy_static! {
+    static ref METRICS: PrometheusMetrics = PrometheusMetrics::new();
+}
+
+/// Prometheus metrics updated by this crate's fairings and responders, available
+/// with the `prometheus` feature.
+///
+/// The metrics are process-wide and are not registered anywhere by default;
+/// register them with the application's registry using
+/// [`PrometheusMetrics::register`], or register the individual handles with
+/// e.g. `rocket_prometheus`.
+///
+/// Byte counts and durations of streamed responses are recorded once their
+/// body has been read to the end.
+///
+/// # Usage
+///
+/// ```rust
+/// use prometheus::Registry;
+/// use rocket_async_compression::PrometheusMetrics;
+///
+/// let registry = Registry::new();
+/// PrometheusMetrics::global().register(&registry).unwrap();
+/// ```
+pub struct PrometheusMetrics {
+    /// Responses compressed, labelled by `encoding`.
+    pub responses: IntCounterVec,
+    /// Bytes read from original bodies.
+    pub bytes_in: IntCounter,
+    /// Compressed bytes produced.
+    pub bytes_out: IntCounter,
+    /// Responses served from a compression cache.
+    pub cache_hits: IntCounter,
+    /// Cacheable responses that had to be compressed.
+    pub cache_misses: IntCounter,
+    /// Bodies evicted from the [`CachedCompress`](crate::CachedCompress) cache.
+    pub cache_evictions: IntCounter,
+    /// Time spent compressing responses, in seconds.
+    pub duration: Histogram,
+}
+
+impl PrometheusMetrics {
+    fn new() -> PrometheusMetrics {
+        let counter = |name: &str, help: &str| {
+            IntCounter::new(name, help).expect("compression metric options are valid")
+        };
+
+        PrometheusMetrics {
+            responses: IntCounterVec::new(
+                Opts::new(
+                    "compression_responses_total",
+                    "Responses compressed, by encoding.",
+                ),
+                &["encoding"],
+            )
+            .expect("compression metric options are valid"),
+            bytes_in: counter(
+                "compression_bytes_in_total",
+                "Bytes read from original response bodies.",
+            ),
+            bytes_out: counter(
+                "compression_bytes_out_total",
+                "Compressed response bytes produced.",
+            ),
+            cache_hits: counter(
+                "compression_cache_hits_total",
+                "Responses served from a compression cache.",
+            ),
+            cache_misses: counter(
+                "compression_cache_misses_total",
+                "Cacheable responses that had to be compressed.",
+            ),
+            cache_evictions: counter(
+                "compression_cache_evictions_total",
+                "Compressed bodies evicted from the responder cache.",
+            ),
+            duration: Histogram::with_opts(HistogramOpts::new(
+                "compression_duration_seconds",
+                "Time spent compressing responses.",
+            ))
+            .expect("compression metric options are valid"),
+        }
+    }
+
+    /// Returns the metrics shared by every fairing and responder.
+    pub fn global() -> &'static PrometheusMetrics {
+        &METRICS
+    }
+
+    /// Returns all of the metrics as collectors.
+    pub fn collectors(&self) -> Vec<Box<dyn Collector>> {
+        vec![
+            Box::new(self.responses.clone()),
+            Box::new(self.bytes_in.clone()),
+            Box::new(self.bytes_out.clone()),
+            Box::new(self.cache_hits.clone()),
+            Box::new(self.cache_misses.clone()),
+            Box::new(self.cache_evictions.clone()),
+            Box::new(self.duration.clone()),
+        ]
+    }
+
+    /// Registers all of the metrics with `registry`.
+    pub fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+        self.collectors()
+            .into_iter()
+            .try_for_each(|collector| registry.register(collector))
+    }
+
+    pub(crate) fn record_encoding(&self, encoding: &Encoding) {
+        self.responses
+            .with_label_values(&[&encoding.to_string()])
+            .inc();
+    }
+
+    /// Records the sizes and duration of a compressed response.  Cache hits
+    /// only count as hits.
+    pub(crate) fn record_event(&self, event: &CompressionEvent) {
+        if event.cache_hit {
+            self.cache_hits.inc();
+            return;
+        }
+
+        if let Some(original_size) = event.original_size {
+            self.bytes_in.inc_by(original_size);
+        }
+        self.bytes_out.inc_by(event.compressed_size);
+        self.duration.observe(event.duration.as_secs_f64());
+    }
+
+    /// Returns a hook recording events before passing them on to `hook`.
+    pub(crate) fn hook(hook: Option<CompressionHook>) -> CompressionHook {
+        std::sync::Arc::new(move |event| {
+            PrometheusMetrics::global().record_event(&event);
+            if let Some(hook) = &hook {
+                hook(event);
+            }
+        })
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{ready, Context, Poll},
+};
+
+#[cfg(feature = "brotli")]
+use async_compression::tokio::bufread::BrotliDecoder;
+#[cfg(feature = "gzip")]
+use async_compression::tokio::bufread::GzipDecoder;
+use async_compression::tokio::bufread::ZlibDecoder;
+use rocket::{
+    data::{self, ByteUnit, Data, FromData},
+    http::Status,
+    tokio::io::{AsyncRead, BufReader, ReadBuf},
+    Request,
+};
+
+use crate::{CountingReader, Encoding};
+
+/// The number of compressed bytes below which the expansion ratio isn't
+/// enforced, so that small bodies made of repetitive data aren't rejected.
+const RATIO_FLOOR: u64 = 1024;
+
+/// Limits applied while decoding compressed request bodies.
+///
+/// Compression formats allow tiny bodies to expand enormously: a couple of
+/// kilobytes of gzip can decode to gigabytes.  Reads from decoded bodies fail
+/// with [`RequestDecodingError::TooLarge`] as soon as either limit is
+/// exceeded, without reading past it.
+///
+/// The limits used by the request body guards are taken from managed state,
+/// falling back to [`DecompressionLimits::default`]:
+///
+/// ```rust
+/// use rocket::data::ToByteUnit;
+/// use rocket_async_compression::DecompressionLimits;
+///
+/// rocket::build().manage(DecompressionLimits {
+///     max_size: Some(16.mebibytes()),
+///     max_ratio: Some(50),
+/// });
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecompressionLimits {
+    /// The maximum size of a decoded body.  When unset, the limit passed when
+    /// opening the body, which is usually one of Rocket's data limits, bounds
+    /// the decoded size as well as the encoded size.
+    pub max_size: Option<ByteUnit>,
+    /// The maximum ratio between the decoded and encoded sizes of a body.
+    /// Bodies smaller than 1 KiB when encoded may expand up to this ratio of
+    /// 1 KiB.
+    pub max_ratio: Option<u64>,
+}
+
+impl Default for DecompressionLimits {
+    /// No size limit beyond the one used to open the body, and a maximum
+    /// expansion ratio of 200.
+    fn default() -> Self {
+        DecompressionLimits {
+            max_size: None,
+            max_ratio: Some(200),
+        }
+    }
+}
+
+/// The error produced by request body guards when the request's
+/// `Content-Encoding` can't be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestDecodingError {
+    /// The request was encoded with a coding this crate can't decode.  Requests
+    /// failing with this error are answered with `415 Unsupported Media Type`.
+    UnsupportedEncoding(String),
+    /// The request body was encoded more than once, e.g. with
+    /// `Content-Encoding: gzip, gzip`.  Requests failing with this error are
+    /// answered with `415 Unsupported Media Type`.
+    NestedEncoding,
+    /// The decoded body exceeded the configured [`DecompressionLimits`].
+    /// Requests failing with this error are answered with `413 Payload Too
+    /// Large`.
+    TooLarge,
+}
+
+impl RequestDecodingError {
+    /// Returns the status a request failing with this error is answered with.
+    pub fn status(&self) -> Status {
+        match self {
+            RequestDecodingError::UnsupportedEncoding(_) | RequestDecodingError::NestedEncoding => {
+                Status::UnsupportedMediaType
+            }
+            RequestDecodingError::TooLarge => Status::PayloadTooLarge,
+        }
+    }
+}
+
+impl From<RequestDecodingError> for std::io::Error {
+    fn from(err: RequestDecodingError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+    }
+}
+
+impl fmt::Display for RequestDecodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestDecodingError::UnsupportedEncoding(coding) => {
+                write!(f, "unsupported request content coding `{}`", coding)
+            }
+            RequestDecodingError::NestedEncoding => {
+                f.write_str("request body is encoded more than once")
+            }
+            RequestDecodingError::TooLarge => {
+                f.write_str("decoded request body exceeds the decompression limits")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RequestDecodingError {}
+
+/// Returns `true` if request bodies encoded with `encoding` can be decoded.
+// The arms only look like `matches!` when every decoder feature is enabled.
+#[allow(clippy::match_like_matches_macro)]
+fn is_decodable(encoding: &Encoding) -> bool {
+    match encoding {
+        Encoding::Gzip => cfg!(feature = "gzip"),
+        Encoding::Brotli => cfg!(feature = "brotli"),
+        Encoding::Deflate => true,
+        Encoding::Zstd => cfg!(feature = "zstd"),
+        _ => false,
+    }
+}
+
+/// Returns the content codings applied to the request body, in the order they
+/// were applied.  `identity` is omitted.
+fn request_encodings(request: &Request<'_>) -> Result<Vec<Encoding>, RequestDecodingError> {
+    let mut encodings = Vec::new();
+    for coding in request
+        .headers()
+        .get("Content-Encoding")
+        .flat_map(|value| value.split(','))
+        .map(|coding| coding.trim().to_ascii_lowercase())
+        .filter(|coding| !coding.is_empty())
+    {
+        let encoding = match coding.parse::<Encoding>() {
+            Ok(encoding) => encoding,
+            Err(never) => match never {},
+        };
+        if encoding == Encoding::Identity {
+            continue;
+        }
+        if !is_decodable(&encoding) {
+            return Err(RequestDecodingError::UnsupportedEncoding(coding));
+        }
+        encodings.push(encoding);
+    }
+
+    // Every layer multiplies the possible expansion of the body, and no legitimate client needs
+    // more than one.
+    if encodings.len() > 1 {
+        return Err(RequestDecodingError::NestedEncoding);
+    }
+
+    Ok(encodings)
+}
+
+/// Wraps `body` in a decoder for `encoding`.
+fn decoder<'r>(
+    body: Pin<Box<dyn AsyncRead + Send + 'r>>,
+    encoding: &Encoding,
+) -> Pin<Box<dyn AsyncRead + Send + 'r>> {
+    let body = BufReader::new(body);
+    match encoding {
+        #[cfg(feature = "gzip")]
+        Encoding::Gzip => Box::pin(GzipDecoder::new(body)),
+        #[cfg(feature = "brotli")]
+        Encoding::Brotli => Box::pin(BrotliDecoder::new(body)),
+        // The `deflate` content coding is the zlib format, not raw deflate.
+        Encoding::Deflate => Box::pin(ZlibDecoder::new(body)),
+        #[cfg(feature = "zstd")]
+        Encoding::Zstd => Box::pin(async_compression::tokio::bufread::ZstdDecoder::new(body)),
+        other => unreachable!("no request body decoder for {}", other),
+    }
+}
+
+/// Fails reads from a decoded body once it exceeds its size or expansion ratio
+/// limit.
+struct LimitedReader<'r> {
+    inner: Pin<Box<dyn AsyncRead + Send + 'r>>,
+    encoded: Arc<AtomicU64>,
+    decoded: u64,
+    max_size: u64,
+    max_ratio: Option<u64>,
+}
+
+impl LimitedReader<'_> {
+    fn exceeds_limits(&self) -> bool {
+        if self.decoded > self.max_size {
+            return true;
+        }
+
+        match self.max_ratio {
+            Some(max_ratio) => {
+                let encoded = self.encoded.load(Ordering::Relaxed).max(RATIO_FLOOR);
+                self.decoded > encoded.saturating_mul(max_ratio)
+            }
+            None => false,
+        }
+    }
+}
+
+impl AsyncRead for LimitedReader<'_> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(self.inner.as_mut().poll_read(cx, buf))?;
+        self.decoded += (buf.filled().len() - filled) as u64;
+        if self.exceeds_limits() {
+            // The data read past the limit is discarded rather than handed to the caller.
+            buf.set_filled(filled);
+            return Poll::Ready(Err(RequestDecodingError::TooLarge.into()));
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// A data guard for request bodies that may have been compressed by the
+/// client.
+///
+/// The request's `Content-Encoding` is inspected when the guard runs:
+/// bodies encoded with `gzip`, `br`, `deflate` or, with the `zstd` feature,
+/// `zstd` are decoded transparently when read through
+/// [`CompressedBody::open`], and bodies without a `Content-Encoding` are read
+/// as-is.  Requests using any other coding, or more than one coding, fail with
+/// `415 Unsupported Media Type`.
+///
+/// Decoded bodies are subject to the [`DecompressionLimits`] in managed state.
+/// Reads failing because of them return an I/O error wrapping
+/// [`RequestDecodingError::TooLarge`].
+///
+/// # Usage
+///
+/// ```rust
+/// # #[macro_use] extern crate rocket;
+/// use rocket::data::ToByteUnit;
+/// use rocket::tokio::io::AsyncReadExt;
+/// use rocket_async_compression::CompressedBody;
+///
+/// #[post("/upload", data = "<body>")]
+/// async fn upload(body: CompressedBody<'_>) -> std::io::Result<String> {
+///     let mut json = String::new();
+///     body.open(10.mebibytes()).read_to_string(&mut json).await?;
+///     Ok(json)
+/// }
+/// ```
+pub struct CompressedBody<'r> {
+    data: Data<'r>,
+    encodings: Vec<Encoding>,
+    limits: DecompressionLimits,
+}
+
+impl<'r> CompressedBody<'r> {
+    /// Returns the content codings applied to the body, in the order they were
+    /// applied.  Empty if the body isn't encoded.
+    pub fn encodings(&self) -> &[Encoding] {
+        &self.encodings
+    }
+
+    /// Returns the request body as received, without decoding it.
+    pub fn into_data(self) -> Data<'r> {
+        self.data
+    }
+
+    /// Returns a reader over the decoded body.
+    ///
+    /// `limit` bounds the number of bytes read from the request, i.e. the size
+    /// of the body *before* it is decoded.  Unless
+    /// [`DecompressionLimits::max_size`] is set, it bounds the size of the
+    /// decoded body as well.
+    pub fn open(self, limit: ByteUnit) -> Pin<Box<dyn AsyncRead + Send + 'r>> {
+        let encoded = Arc::new(AtomicU64::new(0));
+        let mut body: Pin<Box<dyn AsyncRead + Send + 'r>> =
+            Box::pin(CountingReader::new(self.data.open(limit), encoded.clone()));
+        if self.encodings.is_empty() {
+            return body;
+        }
+
+        for encoding in self.encodings.iter().rev() {
+            body = decoder(body, encoding);
+        }
+        Box::pin(LimitedReader {
+            inner: body,
+            encoded,
+            decoded: 0,
+            max_size: self.limits.max_size.unwrap_or(limit).as_u64(),
+            max_ratio: self.limits.max_ratio,
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromData<'r> for CompressedBody<'r> {
+    type Error = RequestDecodingError;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let limits = req
+            .rocket()
+            .state::<DecompressionLimits>()
+            .copied()
+            .unwrap_or_default();
+        match request_encodings(req) {
+            Ok(encodings) => data::Outcome::Success(CompressedBody {
+                data,
+                encodings,
+                limits,
+            }),
+            Err(err) => data::Outcome::Error((err.status(), err)),
+        }
+    }
+}
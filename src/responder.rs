@@ -1,15 +1,28 @@
+use std::borrow::Cow;
+
+use async_compression::Level;
+use rocket::http::{hyper::header::CONTENT_ENCODING, Header, MediaType, Status};
 use rocket::response::{self, Responder, Response};
+use rocket::tokio::io::BufReader;
 use rocket::Request;
 
-use super::CompressionUtils;
+use super::{CompressionConfig, CompressionOptions, CompressionUtils, Encoding, EXCLUSIONS};
 
 /// Compresses responses with Brotli or Gzip compression using the `async-compression` crate.
 ///
 /// The `Compress` type implements brotli and gzip compression for responses in
 /// accordance with the `Accept-Encoding` header. If accepted, brotli
-/// compression is preferred over gzip.
+/// compression is preferred over gzip.  Use [`Compress::with_encoding`] to
+/// require a specific encoding instead.
 ///
 /// Responses that already have a `Content-Encoding` header are not compressed.
+/// Responses with a `Content-Type` matching the same default exclusions used by
+/// the [`Compression`](super::Compression) fairing (images, video, archives,
+/// etc.) are not compressed either; use [`Compress::force`] to compress them
+/// anyway.
+///
+/// Settings beyond the compression level, such as [`Compress::force`], return
+/// a [`ConfiguredCompress`] responder.
 ///
 /// # Usage
 ///
@@ -20,31 +33,442 @@ use super::CompressionUtils;
 ///
 /// let response = Compress::default("Hi.");
 /// ```
+///
+/// The wrapped responder's status and headers are kept, so responders setting
+/// a status such as `status::Custom` can be wrapped, or can wrap `Compress`:
+///
+/// ```rust
+/// use rocket::http::Status;
+/// use rocket::response::status;
+/// use rocket_async_compression::Compress;
+///
+/// #[rocket::post("/")]
+/// fn create() -> Compress<status::Custom<&'static str>> {
+///     Compress::default(status::Custom(Status::Created, "Created."))
+/// }
+/// ```
 #[derive(Debug)]
-pub struct Compress<R>(pub R, pub async_compression::Level);
+pub struct Compress<R>(pub R, pub Level);
 
 impl<'r, 'o: 'r, R: Responder<'r, 'o>> Compress<R> {
+    /// Compresses the wrapped responder with the provided compression level.
+    pub fn with_level(r: R, level: Level) -> Compress<R> {
+        Compress(r, level)
+    }
+
+    /// Compresses the wrapped responder following the settings of `config`.
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{Compress, CompressionConfig};
+    ///
+    /// let config = CompressionConfig::default();
+    /// let response = Compress::from_config("Hi.", &config);
+    /// ```
+    pub fn from_config(r: R, config: &CompressionConfig) -> ConfiguredCompress<R> {
+        ConfiguredCompress::from_config(r, config)
+    }
+
+    /// Compresses the wrapped responder following the [`CompressionConfig`]
+    /// in managed state, such as the one managed by
+    /// [`Compression::from_config`](super::Compression::from_config), or with
+    /// the default settings if no config is managed.
+    ///
+    /// The config is looked up when the response is produced, so responders
+    /// follow the application-wide policy without repeating it at every call
+    /// site.
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compress;
+    ///
+    /// let response = Compress::managed("Hi.");
+    /// ```
+    pub fn managed(r: R) -> ConfiguredCompress<R> {
+        ConfiguredCompress {
+            managed: true,
+            ..Compress::default(r).configure()
+        }
+    }
+
+    /// Compresses the wrapped responder with the provided compression level,
+    /// leaving responses with a `Content-Type` matching any of `exclusions`
+    /// uncompressed.  `exclusions` replaces the default exclusions; `*` may be
+    /// used as the subtype to exclude a whole top-level type.
+    ///
+    /// ```rust
+    /// use rocket::http::MediaType;
+    /// use rocket_async_compression::{Compress, Level};
+    ///
+    /// let response = Compress::with_exclusions("Hi.", Level::Default, vec![MediaType::PNG]);
+    /// ```
+    pub fn with_exclusions(
+        r: R,
+        level: Level,
+        exclusions: Vec<MediaType>,
+    ) -> ConfiguredCompress<R> {
+        ConfiguredCompress {
+            exclusions: Cow::Owned(exclusions),
+            ..Compress::with_level(r, level).configure()
+        }
+    }
+
+    /// Compresses the wrapped responder with the default compression level.
+    pub fn new(r: R) -> Compress<R> {
+        Compress::with_level(r, Level::Default)
+    }
+
     pub fn default(r: R) -> Compress<R> {
-        Compress(r, async_compression::Level::Default)
+        Compress::with_level(r, Level::Default)
     }
 
     pub fn best(r: R) -> Compress<R> {
-        Compress(r, async_compression::Level::Best)
+        Compress::with_level(r, Level::Best)
     }
 
     pub fn fastest(r: R) -> Compress<R> {
-        Compress(r, async_compression::Level::Fastest)
+        Compress::with_level(r, Level::Fastest)
+    }
+
+    /// Compresses a streaming responder such as an `EventStream` or
+    /// `ByteStream`, flushing the encoder every time the stream yields data.
+    ///
+    /// Normally the encoder buffers data until it can be compressed well,
+    /// which delays delivery of slowly produced streams; in this mode every
+    /// chunk, such as a server-sent event, can be decoded by the client as soon
+    /// as it is sent.  Flushing after each chunk lowers the compression ratio,
+    /// especially when chunks are small, so prefer the regular mode for bodies
+    /// that aren't latency sensitive.
+    ///
+    /// Unlike the other constructors, `text/event-stream`,
+    /// `application/x-ndjson` and `application/json-seq` responses are not
+    /// excluded from compression in this mode.
+    ///
+    /// ```rust
+    /// use rocket::response::stream::{Event, EventStream};
+    /// use rocket_async_compression::Compress;
+    ///
+    /// let response = Compress::streaming(EventStream! {
+    ///     yield Event::data("hello");
+    /// });
+    /// ```
+    pub fn streaming(r: R) -> ConfiguredCompress<R> {
+        let exclusions = EXCLUSIONS
+            .iter()
+            .filter(|media_type| {
+                **media_type != MediaType::EventStream
+                    && !(media_type.top() == "application"
+                        && (media_type.sub() == "x-ndjson" || media_type.sub() == "json-seq"))
+            })
+            .cloned()
+            .collect();
+
+        ConfiguredCompress {
+            exclusions: Cow::Owned(exclusions),
+            flush_each_chunk: true,
+            ..Compress::default(r).configure()
+        }
+    }
+
+    /// Returns a [`ConfiguredCompress`] responder with the same responder and
+    /// level and the default settings, to be configured further.
+    pub fn configure(self) -> ConfiguredCompress<R> {
+        ConfiguredCompress {
+            responder: self.0,
+            level: self.1,
+            exclusions: Cow::Borrowed(EXCLUSIONS.as_slice()),
+            encoding: None,
+            min_size: None,
+            flush_each_chunk: false,
+            server_encodings: None,
+            managed: false,
+            forced: false,
+        }
+    }
+
+    /// Compresses the response regardless of its `Content-Type`, ignoring the
+    /// default exclusions.
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compress;
+    ///
+    /// let response = Compress::default("Hi.").force();
+    /// ```
+    pub fn force(self) -> ConfiguredCompress<R> {
+        self.configure().force()
+    }
+
+    /// Compresses the response with `encoding` instead of negotiating one,
+    /// which may be [`Encoding::Zstd`] with the `zstd` feature.  See
+    /// [`ConfiguredCompress::with_encoding`].
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{Compress, Encoding};
+    ///
+    /// let response = Compress::default("Hi.").with_encoding(Encoding::Gzip);
+    /// ```
+    pub fn with_encoding(self, encoding: Encoding) -> ConfiguredCompress<R> {
+        self.configure().with_encoding(encoding)
+    }
+
+    /// Leaves the response uncompressed if its body is smaller than
+    /// `min_size` bytes.  See [`ConfiguredCompress::min_size`].
+    ///
+    /// ```rust
+    /// use rocket_async_compression::Compress;
+    ///
+    /// let response = Compress::new("Hi.").min_size(512);
+    /// ```
+    pub fn min_size(self, min_size: usize) -> ConfiguredCompress<R> {
+        self.configure().min_size(min_size)
     }
 }
 
 impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Compress<R> {
     #[inline(always)]
     fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        self.configure().respond_to(request)
+    }
+}
+
+/// A [`Compress`] responder with settings beyond the compression level, built
+/// with methods such as [`Compress::force`] or [`Compress::managed`].
+///
+/// ```rust
+/// use rocket_async_compression::{Compress, ConfiguredCompress, Encoding};
+///
+/// #[rocket::get("/")]
+/// fn index() -> ConfiguredCompress<&'static str> {
+///     Compress::best("Hi.").with_encoding(Encoding::Gzip).min_size(512)
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ConfiguredCompress<R> {
+    responder: R,
+    level: Level,
+    exclusions: Cow<'static, [MediaType]>,
+    encoding: Option<Encoding>,
+    min_size: Option<usize>,
+    flush_each_chunk: bool,
+    server_encodings: Option<Vec<Encoding>>,
+    managed: bool,
+    forced: bool,
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> ConfiguredCompress<R> {
+    fn from_config(r: R, config: &CompressionConfig) -> ConfiguredCompress<R> {
+        ConfiguredCompress {
+            exclusions: Cow::Owned(config.excluded_content_types.clone()),
+            min_size: config.min_size,
+            server_encodings: config.encodings.clone(),
+            ..Compress::with_level(r, config.level).configure()
+        }
+    }
+
+    /// Compresses the response regardless of its `Content-Type`, ignoring the
+    /// default exclusions.
+    ///
+    /// This also overrides the content types registered with
+    /// [`register_compressed_content_type`](crate::register_compressed_content_type).
+    pub fn force(self) -> ConfiguredCompress<R> {
+        ConfiguredCompress {
+            exclusions: Cow::Owned(Vec::new()),
+            forced: true,
+            ..self
+        }
+    }
+
+    /// Compresses the response with `encoding` instead of negotiating one.
+    ///
+    /// If the client doesn't accept `encoding`, the response is sent
+    /// uncompressed; no other encoding is substituted.  Only the encodings
+    /// enabled by the crate's features can be produced: [`Encoding::Gzip`]
+    /// with `gzip`, [`Encoding::Brotli`] with `brotli` and [`Encoding::Zstd`]
+    /// with `zstd`, as well as codings added with
+    /// [`register_encoder`](crate::register_encoder).  Any other encoding
+    /// disables compression.
+    ///
+    /// ```rust
+    /// use rocket_async_compression::{Compress, Encoding};
+    ///
+    /// let response = Compress::default("Hi.").with_encoding(Encoding::Brotli);
+    /// # #[cfg(feature = "zstd")]
+    /// let response = Compress::default("Hi.").with_encoding(Encoding::Zstd);
+    /// ```
+    pub fn with_encoding(self, encoding: Encoding) -> ConfiguredCompress<R> {
+        ConfiguredCompress {
+            encoding: Some(encoding),
+            ..self
+        }
+    }
+
+    /// Leaves the response uncompressed if its body is smaller than
+    /// `min_size` bytes.
+    ///
+    /// Only bodies with a known size are checked: sized bodies and streamed
+    /// bodies whose handler set a `Content-Length` header.  Other streamed
+    /// bodies are always compressed.
+    pub fn min_size(self, min_size: usize) -> ConfiguredCompress<R> {
+        ConfiguredCompress {
+            min_size: Some(min_size),
+            ..self
+        }
+    }
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for ConfiguredCompress<R> {
+    #[inline(always)]
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        let config = if self.managed {
+            request.rocket().state::<CompressionConfig>()
+        } else {
+            None
+        };
+        let this = match config {
+            Some(config) => ConfiguredCompress {
+                flush_each_chunk: self.flush_each_chunk,
+                encoding: self.encoding,
+                forced: self.forced,
+                ..ConfiguredCompress::from_config(self.responder, config)
+            },
+            _ => self,
+        };
         let mut response = Response::build()
-            .merge(self.0.respond_to(request)?)
+            .merge(this.responder.respond_to(request)?)
             .finalize();
 
-        CompressionUtils::compress_response(request, &mut response, &[], self.1);
+        CompressionUtils::compress_response(
+            request,
+            &mut response,
+            &CompressionOptions {
+                forced_encoding: this.encoding.as_ref(),
+                forced: this.forced,
+                min_size: this.min_size,
+                flush_each_chunk: this.flush_each_chunk,
+                server_encodings: this.server_encodings.as_deref(),
+                ..CompressionOptions::new(&this.exclusions, this.level)
+            },
+        );
         Ok(response)
     }
 }
+
+/// What [`Compressed`] does when the client doesn't accept the body's encoding.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum UnacceptedEncodingPolicy {
+    /// Serve the compressed body anyway.
+    #[default]
+    Serve,
+    /// Respond with an empty `406 Not Acceptable`, carrying
+    /// `Vary: Accept-Encoding` so that shared caches don't serve it to clients
+    /// accepting the encoding.
+    NotAcceptable,
+    /// Decompress the body on the fly and serve it uncompressed.  Only gzip and
+    /// brotli bodies can be decompressed; other encodings result in
+    /// `406 Not Acceptable`.
+    Decompress,
+}
+
+/// Marks a response body as already compressed.
+///
+/// `Compressed` sets the `Content-Encoding` header of the wrapped responder's
+/// response to the provided encoding.  Because the response then already has a
+/// `Content-Encoding`, neither the fairings nor [`Compress`] will compress it
+/// again.  This is useful for serving data that was compressed ahead of time,
+/// such as pre-gzipped objects pulled from a blob store.
+///
+/// By default the body is served as-is regardless of the request's
+/// `Accept-Encoding`.  Use [`Compressed::unaccepted`] to respond with `406 Not
+/// Acceptable` or to decompress the body when the client doesn't accept its
+/// encoding.
+///
+/// # Usage
+///
+/// ```rust
+/// use rocket_async_compression::{Compressed, UnacceptedEncodingPolicy};
+///
+/// # let pre_gzipped_bytes: Vec<u8> = Vec::new();
+/// let response = Compressed::gzip(pre_gzipped_bytes)
+///     .unaccepted(UnacceptedEncodingPolicy::Decompress);
+/// ```
+#[derive(Debug)]
+pub struct Compressed<R> {
+    responder: R,
+    encoding: Encoding,
+    unaccepted: UnacceptedEncodingPolicy,
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Compressed<R> {
+    /// Marks the wrapped responder's body as compressed with `encoding`.
+    pub fn new(r: R, encoding: Encoding) -> Compressed<R> {
+        Compressed {
+            responder: r,
+            encoding,
+            unaccepted: UnacceptedEncodingPolicy::Serve,
+        }
+    }
+
+    /// Marks the wrapped responder's body as gzip-compressed.
+    pub fn gzip(r: R) -> Compressed<R> {
+        Compressed::new(r, Encoding::Gzip)
+    }
+
+    /// Marks the wrapped responder's body as brotli-compressed.
+    pub fn brotli(r: R) -> Compressed<R> {
+        Compressed::new(r, Encoding::Brotli)
+    }
+
+    /// Sets what happens when the client doesn't accept the body's encoding.
+    pub fn unaccepted(self, policy: UnacceptedEncodingPolicy) -> Compressed<R> {
+        Compressed {
+            unaccepted: policy,
+            ..self
+        }
+    }
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Compressed<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        let mut response = self.responder.respond_to(request)?;
+        // Unless the body is served whatever the client accepts, the response depends on the
+        // request's `Accept-Encoding`.
+        if self.unaccepted != UnacceptedEncodingPolicy::Serve {
+            CompressionUtils::set_vary(&mut response, &[]);
+        }
+
+        if self.unaccepted == UnacceptedEncodingPolicy::Serve
+            || CompressionUtils::accepts_encoding(request, &self.encoding)
+        {
+            response.set_header(Header::new(
+                CONTENT_ENCODING.as_str(),
+                self.encoding.to_string(),
+            ));
+            return Ok(response);
+        }
+
+        if self.unaccepted == UnacceptedEncodingPolicy::NotAcceptable {
+            return not_acceptable();
+        }
+
+        let body = BufReader::new(response.body_mut().take());
+        match self.encoding {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => response
+                .set_streamed_body(async_compression::tokio::bufread::GzipDecoder::new(body)),
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => response
+                .set_streamed_body(async_compression::tokio::bufread::BrotliDecoder::new(body)),
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => response
+                .set_streamed_body(async_compression::tokio::bufread::ZstdDecoder::new(body)),
+            _ => return not_acceptable(),
+        }
+        Ok(response)
+    }
+}
+
+/// Returns an empty `406 Not Acceptable` response, which varies with the
+/// request's `Accept-Encoding` like the response it replaces.
+fn not_acceptable<'o>() -> response::Result<'o> {
+    let mut response = Response::build().status(Status::NotAcceptable).finalize();
+    CompressionUtils::set_vary(&mut response, &[]);
+    Ok(response)
+}
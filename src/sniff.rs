@@ -0,0 +1,154 @@
+use rocket::{
+    tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, ReadBuf},
+    Response,
+};
+use std::{
+    io::{self, Cursor, SeekFrom},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use crate::NoContentTypePolicy;
+
+/// Number of bytes at the start of an untyped body that are inspected.
+const SNIFF_LEN: usize = 512;
+
+/// Number of distinct byte values in the first [`SNIFF_LEN`] bytes from
+/// which a binary body is considered incompressible.
+const RANDOM_DISTINCT_BYTES: usize = 200;
+
+/// Signatures of formats that are already compressed.
+const COMPRESSED_MAGIC: &[&[u8]] = &[
+    // gzip
+    b"\x1f\x8b",
+    // zip, and the many formats based on it
+    b"PK\x03\x04",
+    // PNG
+    b"\x89PNG\r\n\x1a\n",
+    // JPEG
+    b"\xff\xd8\xff",
+    // GIF
+    b"GIF8",
+    // zstd
+    b"\x28\xb5\x2f\xfd",
+];
+
+/// Resolves [`NoContentTypePolicy::Sniff`] for `response` to
+/// [`NoContentTypePolicy::Compress`] or [`NoContentTypePolicy::Skip`] by
+/// peeking at the start of its body.
+///
+/// Only untyped bodies with a known size are sniffed; others are compressed.
+/// The peeked bytes are put back in front of the rest of the body, which keeps
+/// its size.  Peeking consumes the body, so this is only called for responses
+/// that would otherwise be compressed.
+pub(crate) async fn resolve(response: &mut Response<'_>) -> NoContentTypePolicy {
+    let size = match response.body().preset_size() {
+        Some(size) if response.content_type().is_none() && size > 0 => size,
+        _ => return NoContentTypePolicy::Compress,
+    };
+
+    let mut body = response.body_mut().take();
+    let mut prefix = Vec::with_capacity(SNIFF_LEN.min(size));
+    // `Body::take` shadows `AsyncReadExt::take`.
+    let peeked = AsyncReadExt::take(&mut body, SNIFF_LEN as u64)
+        .read_to_end(&mut prefix)
+        .await;
+    let policy = match peeked {
+        Ok(_) if !looks_compressible(&prefix, prefix.len() < size) => NoContentTypePolicy::Skip,
+        _ => NoContentTypePolicy::Compress,
+    };
+
+    response.set_sized_body(size, Peeked::new(prefix, body));
+    policy
+}
+
+/// A body whose first bytes were read ahead, made of the bytes read and the
+/// rest of the body.
+///
+/// Rocket requires sized bodies to be seekable, but the rest of the body
+/// can't be seeked, so only seeks that don't move are supported.  Rocket
+/// doesn't seek sized bodies whose size is preset.
+struct Peeked<R> {
+    prefix: Cursor<Vec<u8>>,
+    rest: R,
+    position: u64,
+    seek: Option<SeekFrom>,
+}
+
+impl<R> Peeked<R> {
+    fn new(prefix: Vec<u8>, rest: R) -> Peeked<R> {
+        Peeked {
+            prefix: Cursor::new(prefix),
+            rest,
+            position: 0,
+            seek: None,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Peeked<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let filled_before = buf.filled().len();
+        if (this.prefix.position() as usize) < this.prefix.get_ref().len() {
+            ready!(Pin::new(&mut this.prefix).poll_read(cx, buf))?;
+        } else {
+            ready!(Pin::new(&mut this.rest).poll_read(cx, buf))?;
+        }
+        this.position += (buf.filled().len() - filled_before) as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncSeek for Peeked<R> {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        self.seek = Some(position);
+        Ok(())
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let position = self.position;
+        Poll::Ready(match self.seek.take() {
+            None | Some(SeekFrom::Current(0)) => Ok(position),
+            Some(SeekFrom::Start(start)) if start == position => Ok(position),
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "a sniffed body can't be seeked",
+            )),
+        })
+    }
+}
+
+/// Returns `true` if `prefix`, the start of a body, looks compressible.
+/// `truncated` is whether the body goes on after `prefix`.
+///
+/// Text is compressible, while the signatures of already compressed formats
+/// aren't.  Other binary data is only considered incompressible if its bytes
+/// are as varied as those of compressed or encrypted data; anything else is
+/// compressed, as untyped bodies are without sniffing.
+fn looks_compressible(prefix: &[u8], truncated: bool) -> bool {
+    if COMPRESSED_MAGIC
+        .iter()
+        .any(|magic| prefix.starts_with(magic))
+    {
+        return false;
+    }
+
+    match std::str::from_utf8(prefix) {
+        Ok(_) => return true,
+        // The prefix may end in the middle of a multi-byte character.
+        Err(error) if truncated && error.error_len().is_none() => return true,
+        Err(_) => {}
+    }
+
+    // Random bytes take about 220 distinct values in 512 bytes, structured binary data far fewer.
+    let mut seen = [false; 256];
+    for &byte in prefix {
+        seen[byte as usize] = true;
+    }
+    prefix.len() < SNIFF_LEN || seen.iter().filter(|&&seen| seen).count() < RANDOM_DISTINCT_BYTES
+}
@@ -0,0 +1,281 @@
+#[cfg(feature = "metrics")]
+use std::sync::Mutex;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use lazy_static::lazy_static;
+#[cfg(feature = "metrics")]
+use rocket::http::ContentType;
+use rocket::{Build, Rocket};
+
+use crate::Encoding;
+
+lazy_static! {
+    static ref STATS: CompressionStats = CompressionStats::default();
+}
+
+/// Why a response considered for compression was left uncompressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SkipReason {
+    /// The client doesn't accept any of the available encodings.
+    NotAccepted,
+    /// The response's content type is excluded or already compressed, or the
+    /// response has no content type and untyped responses are skipped.
+    ExcludedType,
+    /// The response already has a `Content-Encoding`.
+    AlreadyEncoded,
+    /// The response body is smaller than the configured minimum size.
+    TooSmall,
+    /// The machine was overloaded.  See [`LoadBreaker`](crate::LoadBreaker).
+    Overloaded,
+}
+
+impl SkipReason {
+    /// Every reason, in the order of their counters.
+    pub const ALL: [SkipReason; 5] = [
+        SkipReason::NotAccepted,
+        SkipReason::ExcludedType,
+        SkipReason::AlreadyEncoded,
+        SkipReason::TooSmall,
+        SkipReason::Overloaded,
+    ];
+}
+
+/// Process-wide counters describing the work done by this crate's fairings
+/// and responders.
+///
+/// All counters are plain atomics updated with relaxed ordering, so recording
+/// is cheap enough to leave on in production.
+///
+/// `CompressionStats` is a handle to the shared counters, so clones observe
+/// the same values.  The fairings make it available as managed state when the
+/// application ignites, so it can be used as a route guard with
+/// `&State<CompressionStats>`.
+///
+/// # Usage
+///
+/// ```rust
+/// use rocket_async_compression::{CompressionStats, Encoding};
+///
+/// let counts = CompressionStats::global().encoding_counts();
+/// let brotli = counts.get(&Encoding::Brotli).copied().unwrap_or(0);
+/// ```
+#[derive(Clone, Default)]
+pub struct CompressionStats {
+    counters: Arc<Counters>,
+    pub(crate) bytes_in: Arc<AtomicU64>,
+    pub(crate) bytes_out: Arc<AtomicU64>,
+}
+
+#[derive(Default)]
+struct Counters {
+    gzip: AtomicU64,
+    brotli: AtomicU64,
+    #[cfg(feature = "zstd")]
+    zstd: AtomicU64,
+    considered: AtomicU64,
+    skipped: [AtomicU64; SkipReason::ALL.len()],
+    saved: AtomicU64,
+    cache_degraded: AtomicBool,
+    #[cfg(feature = "metrics")]
+    content_types: Mutex<HashMap<String, Arc<ContentTypeCounters>>>,
+}
+
+/// Byte counters for the responses of a single content type.
+#[cfg(feature = "metrics")]
+#[derive(Default)]
+pub(crate) struct ContentTypeCounters {
+    pub(crate) original: Arc<AtomicU64>,
+    pub(crate) compressed: Arc<AtomicU64>,
+}
+
+/// Total original and compressed sizes of the responses of a single content
+/// type.
+#[cfg(feature = "metrics")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SizeTotals {
+    /// Total size of the bodies before compression.
+    pub original: u64,
+    /// Total size of the bodies after compression.
+    pub compressed: u64,
+}
+
+impl CompressionStats {
+    /// Returns the statistics shared by every fairing and responder.
+    pub fn global() -> &'static CompressionStats {
+        &STATS
+    }
+
+    /// Manages the global statistics in `rocket` unless they already are.
+    pub(crate) fn manage(rocket: Rocket<Build>) -> Rocket<Build> {
+        if rocket.state::<CompressionStats>().is_some() {
+            return rocket;
+        }
+
+        rocket.manage(CompressionStats::global().clone())
+    }
+
+    pub(crate) fn record_considered(&self) {
+        self.counters.considered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_skipped(&self, reason: SkipReason) {
+        self.counters.skipped[reason as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records the sizes of a response compressed without streaming.
+    pub(crate) fn record_saved(&self, original: u64, compressed: u64) {
+        self.counters
+            .saved
+            .fetch_add(original.saturating_sub(compressed), Ordering::Relaxed);
+    }
+
+    /// Returns the number of responses considered for compression by the
+    /// fairings and the [`Compress`](crate::Compress) responder.
+    pub fn responses_considered(&self) -> u64 {
+        self.counters.considered.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of responses that have been compressed or served
+    /// from a compression cache, across all encodings.
+    pub fn responses_compressed(&self) -> u64 {
+        self.encoding_counts().values().sum()
+    }
+
+    /// Returns the number of considered responses that were left uncompressed
+    /// for `reason`.
+    pub fn responses_skipped(&self, reason: SkipReason) -> u64 {
+        self.counters.skipped[reason as usize].load(Ordering::Relaxed)
+    }
+
+    /// Returns the total number of bytes saved by compression, i.e. the
+    /// difference between the original and compressed sizes of compressed
+    /// responses.  Responses served from a cache aren't counted.
+    pub fn bytes_saved(&self) -> u64 {
+        self.counters.saved.load(Ordering::Relaxed)
+            + self.bytes_in().saturating_sub(self.bytes_out())
+    }
+
+    pub(crate) fn record_encoding(&self, encoding: &Encoding) {
+        let counter = match encoding {
+            Encoding::Gzip => &self.counters.gzip,
+            Encoding::Brotli => &self.counters.brotli,
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => &self.counters.zstd,
+            _ => return,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "prometheus")]
+        crate::PrometheusMetrics::global().record_encoding(encoding);
+    }
+
+    /// Returns the number of responses that have been served with each
+    /// content coding, including responses served from the compression cache.
+    pub fn encoding_counts(&self) -> HashMap<Encoding, u64> {
+        let mut counts = HashMap::with_capacity(3);
+        counts.insert(Encoding::Gzip, self.counters.gzip.load(Ordering::Relaxed));
+        counts.insert(
+            Encoding::Brotli,
+            self.counters.brotli.load(Ordering::Relaxed),
+        );
+        #[cfg(feature = "zstd")]
+        counts.insert(Encoding::Zstd, self.counters.zstd.load(Ordering::Relaxed));
+        counts
+    }
+
+    /// Returns the total number of uncompressed bytes read from response
+    /// bodies that were compressed while streaming.
+    pub fn bytes_in(&self) -> u64 {
+        self.bytes_in.load(Ordering::Relaxed)
+    }
+
+    /// Returns the total number of compressed bytes produced while streaming.
+    pub fn bytes_out(&self) -> u64 {
+        self.bytes_out.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if the [`CachedCompression`](crate::CachedCompression)
+    /// cache has reached its soft ceiling, so that new responses are
+    /// compressed without being cached.
+    pub fn cache_degraded(&self) -> bool {
+        self.counters.cache_degraded.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_cache_degraded(&self, degraded: bool) {
+        self.counters
+            .cache_degraded
+            .store(degraded, Ordering::Relaxed);
+    }
+
+    /// Returns the counters for responses with `content_type`, creating them
+    /// if necessary.  Parameters are ignored and untyped responses are grouped
+    /// under `"none"`.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn content_type_counters(
+        &self,
+        content_type: Option<&ContentType>,
+    ) -> Arc<ContentTypeCounters> {
+        let key = match content_type {
+            Some(content_type) => {
+                format!("{}/{}", content_type.top(), content_type.sub()).to_ascii_lowercase()
+            }
+            None => "none".to_owned(),
+        };
+
+        self.counters
+            .content_types
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .entry(key)
+            .or_default()
+            .clone()
+    }
+
+    /// Adds a response whose original and compressed sizes are both known to
+    /// the totals for its content type.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn record_content_type_sizes(
+        &self,
+        content_type: Option<&ContentType>,
+        original: u64,
+        compressed: u64,
+    ) {
+        debug!(
+            "Compressed {:?} response from {} to {} bytes",
+            content_type.map(|content_type| content_type.to_string()),
+            original,
+            compressed
+        );
+        let counters = self.content_type_counters(content_type);
+        counters.original.fetch_add(original, Ordering::Relaxed);
+        counters.compressed.fetch_add(compressed, Ordering::Relaxed);
+    }
+
+    /// Returns the total original and compressed sizes of compressed
+    /// responses, keyed by content type (without parameters).
+    ///
+    /// Requires the `metrics` feature.
+    #[cfg(feature = "metrics")]
+    pub fn content_type_sizes(&self) -> HashMap<String, SizeTotals> {
+        self.counters
+            .content_types
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .map(|(content_type, counters)| {
+                (
+                    content_type.clone(),
+                    SizeTotals {
+                        original: counters.original.load(Ordering::Relaxed),
+                        compressed: counters.compressed.load(Ordering::Relaxed),
+                    },
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    io::{self, SeekFrom},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+#[cfg(feature = "brotli")]
+use async_compression::tokio::write::BrotliEncoder;
+#[cfg(feature = "gzip")]
+use async_compression::tokio::write::GzipEncoder;
+use async_compression::Level;
+use rocket::tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+
+use crate::{Encoding, ZstdParams};
+
+/// Size of the buffer used to read from the wrapped body.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// The encoders are boxed since their sizes differ widely.
+enum WriteEncoder {
+    #[cfg(feature = "gzip")]
+    Gzip(Box<GzipEncoder<Vec<u8>>>),
+    #[cfg(feature = "brotli")]
+    Brotli(Box<BrotliEncoder<Vec<u8>>>),
+    #[cfg(feature = "zstd")]
+    Zstd(Box<async_compression::tokio::write::ZstdEncoder<Vec<u8>>>),
+}
+
+impl WriteEncoder {
+    fn writer(&mut self) -> Pin<&mut (dyn AsyncWrite + Send + Unpin)> {
+        match self {
+            #[cfg(feature = "gzip")]
+            WriteEncoder::Gzip(encoder) => Pin::new(encoder),
+            #[cfg(feature = "brotli")]
+            WriteEncoder::Brotli(encoder) => Pin::new(encoder),
+            #[cfg(feature = "zstd")]
+            WriteEncoder::Zstd(encoder) => Pin::new(encoder),
+        }
+    }
+
+    fn take_output(&mut self) -> Vec<u8> {
+        match self {
+            #[cfg(feature = "gzip")]
+            WriteEncoder::Gzip(encoder) => std::mem::take(encoder.get_mut()),
+            #[cfg(feature = "brotli")]
+            WriteEncoder::Brotli(encoder) => std::mem::take(encoder.get_mut()),
+            #[cfg(feature = "zstd")]
+            WriteEncoder::Zstd(encoder) => std::mem::take(encoder.get_mut()),
+        }
+    }
+}
+
+/// An encoder that flushes its output every time the wrapped body yields a
+/// chunk of data, or every fixed number of input bytes.
+///
+/// Regular encoders buffer output until enough input has been seen to
+/// compress it well, which delays delivery of slow streams such as server-sent
+/// events.  This encoder instead performs a sync flush after every read from
+/// the wrapped body, so every chunk becomes decodable by the client as soon as
+/// it is sent.  This lowers the compression ratio, especially for streams made
+/// of many small chunks.
+///
+/// Flushing every fixed number of input bytes instead makes the output only
+/// depend on the body's bytes, not on how they are split into chunks, so that
+/// it is identical across runs.
+pub(crate) struct FlushingEncoder<R> {
+    inner: R,
+    encoder: WriteEncoder,
+    /// Number of input bytes between two flushes, or `None` to flush after
+    /// every chunk.
+    flush_every: Option<usize>,
+    /// Number of input bytes written since the last flush.
+    unflushed: usize,
+    input: Box<[u8]>,
+    output: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R> FlushingEncoder<R> {
+    /// Wraps `inner` in an encoder for `encoding`.  Only gzip, brotli and, with
+    /// the `zstd` feature, zstd are supported.  The zstd window log is taken
+    /// from `zstd`.  The output is flushed every `flush_every` input bytes, or
+    /// after every chunk if `None`.
+    pub(crate) fn new(
+        inner: R,
+        encoding: &Encoding,
+        level: Level,
+        #[cfg_attr(not(feature = "zstd"), allow(unused_variables))] zstd: &ZstdParams,
+        flush_every: Option<usize>,
+    ) -> FlushingEncoder<R> {
+        let encoder = match encoding {
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => {
+                WriteEncoder::Brotli(Box::new(BrotliEncoder::with_quality(Vec::new(), level)))
+            }
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => {
+                WriteEncoder::Gzip(Box::new(GzipEncoder::with_quality(Vec::new(), level)))
+            }
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => WriteEncoder::Zstd(Box::new(
+                async_compression::tokio::write::ZstdEncoder::with_quality_and_params(
+                    Vec::new(),
+                    level,
+                    &zstd.parameters(),
+                ),
+            )),
+            other => unreachable!("no flushing encoder for {}", other),
+        };
+
+        FlushingEncoder {
+            inner,
+            encoder,
+            flush_every: flush_every.map(|bytes| bytes.max(1)),
+            unflushed: 0,
+            input: vec![0; CHUNK_SIZE].into_boxed_slice(),
+            output: Vec::new(),
+            position: 0,
+            finished: false,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for FlushingEncoder<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        // Reading into a full buffer must not pull more data from the wrapped body, which would
+        // then be held here instead of applying backpressure to its producer.
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        loop {
+            if this.position < this.output.len() {
+                let len = buf.remaining().min(this.output.len() - this.position);
+                buf.put_slice(&this.output[this.position..this.position + len]);
+                this.position += len;
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.finished {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut input = ReadBuf::new(&mut this.input);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut input))?;
+            let chunk = input.filled();
+
+            // The encoders write into a `Vec`, which never returns `Pending`, so none of these
+            // calls can leave a chunk partially written.
+            let mut writer = this.encoder.writer();
+            if chunk.is_empty() {
+                ready!(writer.as_mut().poll_shutdown(cx))?;
+                this.finished = true;
+            } else if let Some(flush_every) = this.flush_every {
+                // Flushing exactly at every boundary keeps the output independent of the chunking.
+                let mut written = 0;
+                while written < chunk.len() {
+                    let end = chunk.len().min(written + flush_every - this.unflushed);
+                    let count = ready!(writer.as_mut().poll_write(cx, &chunk[written..end]))?;
+                    written += count;
+                    this.unflushed += count;
+                    if this.unflushed == flush_every {
+                        ready!(writer.as_mut().poll_flush(cx))?;
+                        this.unflushed = 0;
+                    }
+                }
+            } else {
+                let mut written = 0;
+                while written < chunk.len() {
+                    written += ready!(writer.as_mut().poll_write(cx, &chunk[written..]))?;
+                }
+                ready!(writer.as_mut().poll_flush(cx))?;
+            }
+
+            this.output = this.encoder.take_output();
+            this.position = 0;
+        }
+    }
+}
+
+/// A body that reads the wrapped reader to the end the first time it is read
+/// or seeked, and then serves the buffered bytes.
+///
+/// Being seekable, it can be set as a sized body whose size is only known once
+/// the wrapped reader, typically an encoder, has been read, so that Rocket
+/// sends a `Content-Length` instead of chunking the body.
+pub(crate) struct BufferedBody<R> {
+    inner: R,
+    input: Box<[u8]>,
+    buffer: Vec<u8>,
+    filled: bool,
+    position: u64,
+    seek: Option<SeekFrom>,
+}
+
+impl<R> BufferedBody<R> {
+    pub(crate) fn new(inner: R) -> BufferedBody<R> {
+        BufferedBody {
+            inner,
+            input: vec![0; CHUNK_SIZE].into_boxed_slice(),
+            buffer: Vec::new(),
+            filled: false,
+            position: 0,
+            seek: None,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> BufferedBody<R> {
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.filled {
+            let mut input = ReadBuf::new(&mut self.input);
+            ready!(Pin::new(&mut self.inner).poll_read(cx, &mut input))?;
+            if input.filled().is_empty() {
+                self.filled = true;
+            } else {
+                self.buffer.extend_from_slice(input.filled());
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for BufferedBody<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        ready!(this.poll_fill(cx))?;
+        let start = (this.position as usize).min(this.buffer.len());
+        let len = buf.remaining().min(this.buffer.len() - start);
+        buf.put_slice(&this.buffer[start..start + len]);
+        this.position += len as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncSeek for BufferedBody<R> {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        self.seek = Some(position);
+        Ok(())
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = &mut *self;
+        ready!(this.poll_fill(cx))?;
+        let (base, offset) = match this.seek.take() {
+            None => return Poll::Ready(Ok(this.position)),
+            Some(SeekFrom::Start(position)) => (position, 0),
+            Some(SeekFrom::End(offset)) => (this.buffer.len() as u64, offset),
+            Some(SeekFrom::Current(offset)) => (this.position, offset),
+        };
+        this.position = base.checked_add_signed(offset).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Poll::Ready(Ok(this.position))
+    }
+}
@@ -0,0 +1,164 @@
+//! Compression and decompression of in-memory bytes.
+//!
+//! These are the routines used by the fairings and responders, exposed for
+//! code that needs compressed bodies outside of a response, such as cache
+//! warmers.
+//!
+//! ```rust
+//! use rocket_async_compression::{util, Encoding, Level};
+//!
+//! # rocket::async_test(async {
+//! let compressed = util::compress(b"Hello, world!", Encoding::Brotli, Level::Best).await?;
+//! let original = util::decompress(&compressed, Encoding::Brotli).await?;
+//! assert_eq!(original, b"Hello, world!");
+//! # Ok::<_, std::io::Error>(())
+//! # }).unwrap();
+//! ```
+
+use std::io;
+
+#[cfg(feature = "brotli")]
+use async_compression::tokio::bufread::{BrotliDecoder, BrotliEncoder};
+#[cfg(feature = "gzip")]
+use async_compression::tokio::bufread::{GzipDecoder, GzipEncoder};
+use async_compression::{tokio::bufread::ZlibDecoder, Level};
+use rocket::{
+    tokio::io::{AsyncRead, AsyncReadExt, BufReader},
+    Request,
+};
+
+use crate::{backend, CompressionOptions, CompressionUtils, Encoding};
+
+/// Compresses `bytes` with `encoding` at `level`.
+///
+/// Gzip, brotli and, with the `zstd` feature, zstd are supported; other
+/// encodings fail with [`io::ErrorKind::InvalidInput`].  As in the fairings,
+/// [`Level::Default`] compresses brotli at quality 4 rather than the brotli
+/// library's default of 11, which is too slow for dynamic content.
+pub async fn compress(bytes: &[u8], encoding: Encoding, level: Level) -> io::Result<Vec<u8>> {
+    compress_reader(bytes, &encoding, level).await
+}
+
+/// Compresses `bytes` with the encoding negotiated with the client of
+/// `request`, at `level`, and returns the compressed bytes with the encoding.
+///
+/// Unlike the [`Compress`](crate::Compress) responder, which compresses the
+/// body of the response it wraps, this returns the payload so that handlers
+/// can assemble the response themselves, e.g. to set their own headers or to
+/// embed the payload in another body.  The caller is responsible for setting
+/// `Content-Encoding` and `Vary: Accept-Encoding`.  If the client accepts no
+/// supported encoding, `bytes` are returned unchanged with
+/// [`Encoding::Identity`].
+///
+/// ```rust
+/// use rocket::http::{ContentType, Header};
+/// use rocket::response::Response;
+/// use rocket::Request;
+/// use rocket_async_compression::{util, Encoding, Level};
+///
+/// async fn report<'r>(request: &Request<'_>, report: String) -> std::io::Result<Response<'r>> {
+///     let (body, encoding) = util::compress_for(request, report.as_bytes(), Level::Default).await?;
+///     let mut response = Response::build();
+///     response.header(ContentType::JSON);
+///     if encoding != Encoding::Identity {
+///         response
+///             .header(Header::new("Content-Encoding", encoding.to_string()))
+///             .header(Header::new("Vary", "Accept-Encoding"));
+///     }
+///     Ok(response.sized_body(body.len(), std::io::Cursor::new(body)).finalize())
+/// }
+/// ```
+pub async fn compress_for(
+    request: &Request<'_>,
+    bytes: &[u8],
+    level: Level,
+) -> io::Result<(Vec<u8>, Encoding)> {
+    let options = CompressionOptions::new(&[], level);
+    let encoding = match CompressionUtils::negotiate_encoding(request, &options) {
+        Some(encoding) => encoding,
+        None => return Ok((bytes.to_vec(), Encoding::Identity)),
+    };
+
+    let compressed = match backend::registered_encoder(&encoding) {
+        Some(encoder) => {
+            let mut out = Vec::new();
+            encoder(Box::pin(bytes), level)
+                .read_to_end(&mut out)
+                .await?;
+            out
+        }
+        None => compress_reader(bytes, &encoding, level).await?,
+    };
+    Ok((compressed, encoding))
+}
+
+/// Decompresses `bytes` encoded with `encoding`.
+///
+/// Gzip, brotli, deflate (in the zlib format used by the HTTP `deflate`
+/// coding) and, with the `zstd` feature, zstd are supported; other encodings
+/// fail with [`io::ErrorKind::InvalidInput`].
+pub async fn decompress(bytes: &[u8], encoding: Encoding) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    match encoding {
+        #[cfg(feature = "gzip")]
+        Encoding::Gzip => copy(GzipDecoder::new(bytes), &mut out).await?,
+        #[cfg(feature = "brotli")]
+        Encoding::Brotli => copy(BrotliDecoder::new(bytes), &mut out).await?,
+        Encoding::Deflate => copy(ZlibDecoder::new(bytes), &mut out).await?,
+        #[cfg(feature = "zstd")]
+        Encoding::Zstd => {
+            copy(
+                async_compression::tokio::bufread::ZstdDecoder::new(bytes),
+                &mut out,
+            )
+            .await?
+        }
+        other => return Err(unsupported(&other)),
+    }
+    Ok(out)
+}
+
+/// Reads `body` to the end, compressing it with `encoding` at `level`.
+pub(crate) async fn compress_reader<R: AsyncRead + Unpin>(
+    body: R,
+    encoding: &Encoding,
+    level: Level,
+) -> io::Result<Vec<u8>> {
+    let body = BufReader::new(body);
+    let level = crate::CompressionUtils::clamp_precise(encoding, level);
+    let mut out = Vec::new();
+    match encoding {
+        #[cfg(feature = "brotli")]
+        Encoding::Brotli => {
+            let level = crate::CompressionUtils::effective_level(
+                crate::fairing::CachedEncoding::Brotli,
+                level,
+            );
+            copy(BrotliEncoder::with_quality(body, level), &mut out).await?
+        }
+        #[cfg(feature = "gzip")]
+        Encoding::Gzip => copy(GzipEncoder::with_quality(body, level), &mut out).await?,
+        #[cfg(feature = "zstd")]
+        Encoding::Zstd => {
+            copy(
+                async_compression::tokio::bufread::ZstdEncoder::with_quality(body, level),
+                &mut out,
+            )
+            .await?
+        }
+        other => return Err(unsupported(other)),
+    }
+    Ok(out)
+}
+
+async fn copy<R: AsyncRead + Unpin>(mut reader: R, out: &mut Vec<u8>) -> io::Result<()> {
+    rocket::tokio::io::copy(&mut reader, out).await?;
+    Ok(())
+}
+
+fn unsupported(encoding: &Encoding) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("unsupported encoding `{}`", encoding),
+    )
+}
@@ -0,0 +1,59 @@
+use async_compression::Level;
+
+/// Parameters of the zstd encoder, used when the `zstd` feature is enabled.
+///
+/// [`Level`]s are mapped to zstd levels as follows:
+///
+/// - [`Level::Fastest`] is mapped to 1.
+/// - [`Level::Default`] is mapped to 3, zstd's own default, which is a good
+///   fit for dynamic and streamed responses.
+/// - [`Level::Best`] is mapped to 19, a good fit for assets that are
+///   compressed once and cached.  The "ultra" levels above 19 use much more
+///   memory for little gain.
+/// - [`Level::Precise`] is mapped to the provided level, clamped to `1..=22`.
+///
+/// The window log sets the size of the window the encoder may reference back
+/// to, as a power of two.  Larger windows improve the ratio of large assets,
+/// but decoders must allocate the whole window; browsers only accept windows
+/// of up to 8 MiB, i.e. a window log of at most 23.  When unset, zstd picks the
+/// window size from the level.
+#[derive(Clone, Copy, Debug)]
+pub struct ZstdParams {
+    pub level: Level,
+    pub window_log: Option<u32>,
+}
+
+impl Default for ZstdParams {
+    fn default() -> Self {
+        ZstdParams {
+            level: Level::Default,
+            window_log: None,
+        }
+    }
+}
+
+impl ZstdParams {
+    /// Returns the zstd level that `self.level` maps to.
+    pub fn zstd_level(&self) -> i32 {
+        match self.level {
+            Level::Fastest => 1,
+            Level::Best => 19,
+            Level::Precise(level) => level.clamp(1, 22),
+            _ => 3,
+        }
+    }
+
+    /// Returns the level passed to the encoder.
+    pub(crate) fn quality(&self) -> Level {
+        Level::Precise(self.zstd_level())
+    }
+
+    /// Returns the parameters passed to the encoder.
+    #[cfg(feature = "zstd")]
+    pub(crate) fn parameters(&self) -> Vec<async_compression::zstd::CParameter> {
+        self.window_log
+            .map(async_compression::zstd::CParameter::window_log)
+            .into_iter()
+            .collect()
+    }
+}
@@ -0,0 +1,180 @@
+mod common;
+
+use std::{
+    io::Cursor,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use common::{client, encoding, get, text};
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::ContentType,
+    tokio::io::{AsyncRead, ReadBuf},
+    Request, Response,
+};
+use rocket_async_compression::{
+    compress_response, Compression, CompressionBackend, CustomCoding, Encoding, Level,
+};
+
+/// Reads the wrapped body to the end and yields its bytes reversed.
+struct Reverse<'r> {
+    inner: Pin<Box<dyn AsyncRead + Send + 'r>>,
+    read: Vec<u8>,
+    reversed: Option<Cursor<Vec<u8>>>,
+}
+
+impl AsyncRead for Reverse<'_> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        while this.reversed.is_none() {
+            let mut chunk = [0; 1024];
+            let mut chunk = ReadBuf::new(&mut chunk);
+            ready!(this.inner.as_mut().poll_read(cx, &mut chunk))?;
+            if chunk.filled().is_empty() {
+                let mut read = std::mem::take(&mut this.read);
+                read.reverse();
+                this.reversed = Some(Cursor::new(read));
+            } else {
+                this.read.extend_from_slice(chunk.filled());
+            }
+        }
+        Pin::new(this.reversed.as_mut().unwrap()).poll_read(cx, buf)
+    }
+}
+
+fn reverse<'r>(
+    body: Pin<Box<dyn AsyncRead + Send + 'r>>,
+    _level: Level,
+) -> Pin<Box<dyn AsyncRead + Send + 'r>> {
+    Box::pin(Reverse {
+        inner: body,
+        read: Vec::new(),
+        reversed: None,
+    })
+}
+
+/// A backend "compressing" gzip by reversing the body.
+struct Reversing;
+
+impl CompressionBackend for Reversing {
+    fn supports(&self, encoding: &Encoding) -> bool {
+        *encoding == Encoding::Gzip
+    }
+
+    fn encode<'r>(
+        &self,
+        body: Pin<Box<dyn AsyncRead + Send + 'r>>,
+        _encoding: &Encoding,
+        level: Level,
+    ) -> Pin<Box<dyn AsyncRead + Send + 'r>> {
+        reverse(body, level)
+    }
+}
+
+fn reversed(body: &str) -> String {
+    body.chars().rev().collect()
+}
+
+#[rocket::get("/backend/<kind>")]
+fn backend(kind: &str) -> (ContentType, String) {
+    match kind {
+        "png" => (ContentType::PNG, text(2048)),
+        _ => (ContentType::Plain, text(2048)),
+    }
+}
+
+#[rocket::async_test]
+async fn fairing_uses_the_backend_encoder() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![backend])
+        .attach(Compression::fairing().backend(Reversing));
+    let client = client(rocket).await;
+
+    let response = get(&client, "/backend/text", "br, gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(response.headers().get_one("Vary"), Some("Accept-Encoding"));
+    assert_eq!(response.into_string().await.unwrap(), reversed(&text(2048)));
+
+    // Encodings the backend doesn't support aren't negotiated.
+    let response = get(&client, "/backend/text", "br").await;
+    assert_eq!(encoding(&response), None);
+
+    let response = get(&client, "/backend/png", "gzip").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.into_string().await.unwrap(), text(2048));
+}
+
+/// A custom fairing reusing the pipeline with the reversing backend.
+struct ReversingFairing;
+
+#[rocket::async_trait]
+impl Fairing for ReversingFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Reversing",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        compress_response(request, response, &Reversing, Level::Default);
+    }
+}
+
+#[rocket::async_test]
+async fn custom_fairings_reuse_the_pipeline() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![backend])
+        .attach(ReversingFairing);
+    let client = client(rocket).await;
+
+    let response = get(&client, "/backend/text", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(response.into_string().await.unwrap(), reversed(&text(2048)));
+
+    let response = get(&client, "/backend/png", "gzip").await;
+    assert_eq!(encoding(&response), None);
+}
+
+#[rocket::get("/coding")]
+fn coding() -> String {
+    text(2048)
+}
+
+#[rocket::async_test]
+async fn registered_codings_are_negotiated() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![coding])
+        .attach(Compression::fairing().register_coding(CustomCoding::new("x-reverse", reverse)));
+    let client = client(rocket).await;
+
+    let response = get(&client, "/coding", "x-reverse").await;
+    assert_eq!(encoding(&response).as_deref(), Some("x-reverse"));
+    assert_eq!(response.into_string().await.unwrap(), reversed(&text(2048)));
+
+    // Built-in codings come first at equal priority and quality.
+    let response = get(&client, "/coding", "x-reverse, gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    let response = get(&client, "/coding", "x-reverse, gzip;q=0.5").await;
+    assert_eq!(encoding(&response).as_deref(), Some("x-reverse"));
+}
+
+#[rocket::async_test]
+async fn coding_priority_wins_among_equal_qualities() {
+    let rocket = rocket::build().mount("/", rocket::routes![coding]).attach(
+        Compression::fairing().register_coding(CustomCoding::new("x-reverse", reverse).priority(1)),
+    );
+    let client = client(rocket).await;
+
+    let response = get(&client, "/coding", "gzip, br, x-reverse").await;
+    assert_eq!(encoding(&response).as_deref(), Some("x-reverse"));
+    assert_eq!(response.into_string().await.unwrap(), reversed(&text(2048)));
+
+    let response = get(&client, "/coding", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+}
@@ -0,0 +1,62 @@
+//! Kept apart from the other `CachedCompression` tests since the cache and its
+//! ceiling are shared by every fairing in the process.
+
+mod common;
+
+use common::{client, decoded_body, encoding, get, text, Recorder};
+use rocket_async_compression::{CachedCompression, CompressionStats};
+
+#[rocket::get("/ceiling/<_name>")]
+fn ceiling(_name: &str) -> String {
+    text(4096)
+}
+
+#[rocket::async_test]
+async fn full_cache_streams_misses_until_cleared() {
+    CachedCompression::clear_cache();
+    let recorder = Recorder::default();
+    let fairing =
+        CachedCompression::path_prefix_fairing(vec!["/ceiling/".to_owned()]).max_cache_bytes(1);
+    let rules = fairing.rules();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![ceiling])
+        .attach(fairing)
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    // The body reaching the ceiling is still cached.
+    assert!(!CompressionStats::global().cache_degraded());
+    get(&client, "/ceiling/a", "gzip").await;
+    assert!(CompressionStats::global().cache_degraded());
+
+    for _ in 0..2 {
+        let response = get(&client, "/ceiling/b", "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+        assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+        assert_eq!(recorder.last().unwrap().cache_hit, Some(false));
+        assert!(CompressionStats::global().cache_degraded());
+    }
+
+    get(&client, "/ceiling/a", "gzip").await;
+    assert_eq!(recorder.last().unwrap().cache_hit, Some(true));
+
+    CachedCompression::clear_cache();
+    assert!(!CompressionStats::global().cache_degraded());
+    get(&client, "/ceiling/b", "gzip").await;
+    assert_eq!(recorder.last().unwrap().cache_hit, Some(false));
+    let response = get(&client, "/ceiling/b", "gzip").await;
+    assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+    assert_eq!(recorder.last().unwrap().cache_hit, Some(true));
+    assert!(CompressionStats::global().cache_degraded());
+
+    // Purging the bodies of a runtime rule frees space as well.
+    CachedCompression::clear_cache();
+    rules.add_exact_path("/ceiling/purged");
+    get(&client, "/ceiling/purged", "gzip").await;
+    assert!(CompressionStats::global().cache_degraded());
+    assert!(rules.remove_rule("/ceiling/purged", true));
+    assert!(!CompressionStats::global().cache_degraded());
+    get(&client, "/ceiling/c", "gzip").await;
+    get(&client, "/ceiling/c", "gzip").await;
+    assert_eq!(recorder.last().unwrap().cache_hit, Some(true));
+}
@@ -0,0 +1,62 @@
+//! Kept apart from the other `CachedCompression` tests since it clears the
+//! cache shared by every fairing in the process.
+
+mod common;
+
+use std::{path::Path, time::Duration};
+
+use common::{client, decoded_body, get, temp_dir, text};
+use rocket_async_compression::{util, CachedCompression, Encoding, Level};
+
+#[rocket::get("/persisted/app.js")]
+fn persisted() -> (rocket::http::ContentType, String) {
+    (rocket::http::ContentType::JavaScript, text(4096))
+}
+
+/// Waits for `path` to be written, since bodies are persisted in the
+/// background.
+async fn read_eventually(path: &Path) -> Vec<u8> {
+    for _ in 0..100 {
+        if let Ok(bytes) = std::fs::read(path) {
+            return bytes;
+        }
+        rocket::tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("{} was never written", path.display());
+}
+
+#[rocket::async_test]
+async fn bodies_are_persisted_per_encoding_and_reloaded() {
+    let dir = temp_dir("persist");
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![persisted])
+        .attach(
+            CachedCompression::exact_path_fairing(vec!["/persisted/app.js".to_owned()])
+                .persist_to_dir(&dir),
+        );
+    let client = client(rocket).await;
+
+    for (accept, encoding, extension) in [
+        ("br", Encoding::Brotli, "br"),
+        ("gzip", Encoding::Gzip, "gz"),
+    ] {
+        let response = get(&client, "/persisted/app.js", accept).await;
+        assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+
+        let file = dir.join(format!("_2Fpersisted_2Fapp.js.{}", extension));
+        let persisted = read_eventually(&file).await;
+        assert_eq!(
+            util::decompress(&persisted, encoding).await.unwrap(),
+            text(4096).into_bytes()
+        );
+    }
+
+    // Once evicted from memory, bodies are served from the directory.
+    let replacement = util::compress(b"from disk", Encoding::Gzip, Level::Default)
+        .await
+        .unwrap();
+    std::fs::write(dir.join("_2Fpersisted_2Fapp.js.gz"), replacement).unwrap();
+    CachedCompression::clear_cache();
+    let response = get(&client, "/persisted/app.js", "gzip").await;
+    assert_eq!(decoded_body(response).await, b"from disk");
+}
@@ -0,0 +1,798 @@
+mod common;
+
+use common::{client, decoded_body, encoding, get, text, Recorder, Streamed};
+use rocket::http::{ContentType, MediaType, Status};
+use rocket_async_compression::{
+    util, CachedCompression, Compression, Encoding, FailurePolicy, Level, SkipReason,
+};
+
+#[rocket::get("/over-cap")]
+fn over_cap() -> Streamed {
+    Streamed(ContentType::Plain, text(4096).into_bytes())
+}
+
+#[rocket::async_test]
+async fn body_over_the_buffer_cap_is_served_uncached() {
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![over_cap])
+        .attach(
+            CachedCompression::exact_path_fairing(vec!["/over-cap".to_owned()])
+                .max_buffer_size(1024),
+        )
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    for _ in 0..2 {
+        let response = get(&client, "/over-cap", "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+        assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+        assert_eq!(recorder.last().unwrap().cache_hit, Some(false));
+    }
+}
+
+#[rocket::get("/padded")]
+fn padded() -> String {
+    text(3000)
+}
+
+#[rocket::async_test]
+async fn padded_gzip_bodies_decompress_to_the_original() {
+    let rocket = rocket::build().mount("/", rocket::routes![padded]).attach(
+        CachedCompression::exact_path_fairing(vec!["/padded".to_owned()]).pad_to_bucket(256),
+    );
+    let client = client(rocket).await;
+
+    for _ in 0..2 {
+        let response = get(&client, "/padded", "br, gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+        let body = response.into_bytes().await.unwrap();
+        assert_eq!(body.len() % 256, 0);
+        let decoded = rocket_async_compression::util::decompress(
+            &body,
+            rocket_async_compression::Encoding::Gzip,
+        )
+        .await
+        .unwrap();
+        assert_eq!(decoded, text(3000).into_bytes());
+    }
+
+    let response = get(&client, "/padded", "br").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.into_string().await.unwrap(), text(3000));
+}
+
+#[rocket::get("/eager")]
+fn eager() -> String {
+    text(4096)
+}
+
+#[rocket::async_test]
+async fn eager_encodings_are_cached_on_the_first_miss() {
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![eager])
+        .attach(
+            CachedCompression::exact_path_fairing(vec!["/eager".to_owned()])
+                .eager_encodings(vec![Encoding::Gzip, Encoding::Brotli]),
+        )
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    let response = get(&client, "/eager", "gzip").await;
+    assert_eq!(recorder.last().unwrap().cache_hit, Some(false));
+    assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+
+    let response = get(&client, "/eager", "br").await;
+    assert_eq!(encoding(&response).as_deref(), Some("br"));
+    assert_eq!(recorder.last().unwrap().cache_hit, Some(true));
+    assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+}
+
+#[derive(rocket::Responder)]
+struct Tagged {
+    body: String,
+    etag: rocket::http::Header<'static>,
+}
+
+#[rocket::get("/tagged/<_name>")]
+fn tagged(_name: &str) -> Tagged {
+    Tagged {
+        body: text(4096),
+        etag: rocket::http::Header::new("ETag", "\"shared-body\""),
+    }
+}
+
+#[rocket::get("/untagged/<_name>")]
+fn untagged(_name: &str) -> String {
+    text(4096)
+}
+
+#[rocket::async_test]
+async fn paths_with_the_same_etag_share_a_cache_entry() {
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![tagged, untagged])
+        .attach(
+            CachedCompression::path_prefix_fairing(vec![
+                "/tagged/".to_owned(),
+                "/untagged/".to_owned(),
+            ])
+            .key_by_etag(true),
+        )
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    let response = get(&client, "/tagged/a", "gzip").await;
+    assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+    assert_eq!(recorder.last().unwrap().cache_hit, Some(false));
+
+    let response = get(&client, "/tagged/b", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+    assert_eq!(recorder.last().unwrap().cache_hit, Some(true));
+
+    // Without an `ETag`, bodies are keyed by path.
+    get(&client, "/untagged/a", "gzip").await;
+    assert_eq!(recorder.last().unwrap().cache_hit, Some(false));
+    get(&client, "/untagged/b", "gzip").await;
+    assert_eq!(recorder.last().unwrap().cache_hit, Some(false));
+    get(&client, "/untagged/b", "gzip").await;
+    assert_eq!(recorder.last().unwrap().cache_hit, Some(true));
+}
+
+#[rocket::get("/both/<_name>")]
+fn both(_name: &str) -> String {
+    text(4096)
+}
+
+#[rocket::async_test]
+async fn cached_fairing_wins_when_both_are_attached() {
+    for cached_first in [true, false] {
+        let recorder = Recorder::default();
+        let cached =
+            CachedCompression::exact_path_fairing(vec![format!("/both/cached-{}", cached_first)]);
+        let rocket = rocket::build().mount("/", rocket::routes![both]);
+        let rocket = if cached_first {
+            rocket.attach(cached).attach(Compression::fairing())
+        } else {
+            rocket.attach(Compression::fairing()).attach(cached)
+        };
+        let client = client(rocket.attach(recorder.clone())).await;
+
+        let path = format!("/both/cached-{}", cached_first);
+        for hit in [false, true] {
+            let response = get(&client, &path, "gzip").await;
+            assert_eq!(
+                response
+                    .headers()
+                    .get("Content-Encoding")
+                    .collect::<Vec<_>>(),
+                ["gzip"]
+            );
+            assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+            assert_eq!(recorder.last().unwrap().cache_hit, Some(hit));
+        }
+
+        let response = get(&client, "/both/dynamic", "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+        assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+        assert_eq!(recorder.last().unwrap().cache_hit, None);
+    }
+}
+
+/// Pseudo-random bytes, which don't compress.
+fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+#[rocket::get("/ratio/<kind>")]
+fn ratio(kind: &str) -> (ContentType, Vec<u8>) {
+    match kind {
+        "noise" => (ContentType::Plain, noise(4096)),
+        _ => (ContentType::Plain, text(4096).into_bytes()),
+    }
+}
+
+#[rocket::async_test]
+async fn poorly_compressing_bodies_are_served_identity_and_not_cached() {
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![ratio])
+        .attach(
+            CachedCompression::path_prefix_fairing(vec!["/ratio/".to_owned()]).min_cache_ratio(0.9),
+        )
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    for _ in 0..2 {
+        let response = get(&client, "/ratio/noise", "br, gzip").await;
+        assert_eq!(encoding(&response), None);
+        assert_eq!(response.into_bytes().await.unwrap(), noise(4096));
+        assert_eq!(recorder.last().unwrap().cache_hit, Some(false));
+    }
+
+    for hit in [false, true] {
+        let response = get(&client, "/ratio/text", "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+        assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+        assert_eq!(recorder.last().unwrap().cache_hit, Some(hit));
+    }
+}
+
+#[rocket::async_test]
+async fn poorly_compressing_bodies_are_not_cached_eagerly() {
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![ratio])
+        .attach(
+            CachedCompression::path_prefix_fairing(vec!["/ratio/".to_owned()])
+                .min_cache_ratio(0.9)
+                .eager_encodings(vec![Encoding::Gzip, Encoding::Brotli]),
+        )
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    for accept in ["gzip", "br", "br"] {
+        let response = get(&client, "/ratio/noise", accept).await;
+        assert_eq!(encoding(&response), None, "{}", accept);
+        assert_eq!(response.into_bytes().await.unwrap(), noise(4096));
+        assert_eq!(recorder.last().unwrap().cache_hit, Some(false));
+    }
+}
+
+/// A body failing after yielding some bytes.
+struct Failing(usize);
+
+impl rocket::tokio::io::AsyncRead for Failing {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+        buf: &mut rocket::tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        if self.0 == 0 {
+            return std::task::Poll::Ready(Err(std::io::Error::other("disk on fire")));
+        }
+        let len = self.0.min(buf.remaining()).min(100);
+        buf.put_slice(&text(len).into_bytes());
+        self.0 -= len;
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+struct FailingBody;
+
+impl<'r> rocket::response::Responder<'r, 'static> for FailingBody {
+    fn respond_to(self, _: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        rocket::Response::build()
+            .header(ContentType::Plain)
+            .streamed_body(Failing(1000))
+            .ok()
+    }
+}
+
+#[rocket::get("/failing/<_policy>")]
+fn failing(_policy: &str) -> FailingBody {
+    FailingBody
+}
+
+#[rocket::async_test]
+async fn failing_bodies_follow_the_failure_policy() {
+    for (name, policy) in [
+        ("identity", FailurePolicy::ServeIdentity),
+        ("error", FailurePolicy::ServeError),
+        ("status", FailurePolicy::Status500),
+    ] {
+        let path = format!("/failing/{}", name);
+        let rocket = rocket::build().mount("/", rocket::routes![failing]).attach(
+            CachedCompression::exact_path_fairing(vec![path.clone()]).failure_policy(policy),
+        );
+        let client = client(rocket).await;
+
+        let response = get(&client, &path, "gzip").await;
+        if policy == FailurePolicy::Status500 {
+            assert_eq!(response.status(), Status::InternalServerError);
+            assert_eq!(encoding(&response), None);
+        } else {
+            // Reading the original body is what failed, so there is nothing to fall back to and
+            // the error is served.
+            assert_eq!(response.status(), Status::Ok, "{}", name);
+            assert!(response.into_bytes().await.is_none(), "{}", name);
+        }
+    }
+}
+
+#[rocket::get("/levels/<_kind>")]
+fn levels(_kind: &str) -> String {
+    text(64 * 1024)
+}
+
+#[rocket::async_test]
+async fn cached_and_streaming_levels_are_independent() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![levels])
+        .attach(CachedCompression::exact_path_fairing(vec!["/levels/cached".to_owned()]).best())
+        .attach(Compression::precise(5));
+    let client = client(rocket).await;
+    let original = text(64 * 1024).into_bytes();
+
+    for (path, level) in [
+        ("/levels/cached", Level::Best),
+        ("/levels/dynamic", Level::Precise(5)),
+        ("/levels/cached", Level::Best),
+    ] {
+        let response = get(&client, path, "br").await;
+        assert_eq!(encoding(&response).as_deref(), Some("br"));
+        let expected = util::compress(&original, Encoding::Brotli, level)
+            .await
+            .unwrap();
+        assert_eq!(response.into_bytes().await.unwrap(), expected, "{}", path);
+    }
+}
+
+#[rocket::get("/parallel/<index>")]
+fn parallel(index: usize) -> String {
+    text(4096 + index)
+}
+
+#[rocket::async_test]
+async fn parallel_misses_lose_no_inserts() {
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![parallel])
+        .attach(CachedCompression::path_prefix_fairing(vec![
+            "/parallel/".to_owned()
+        ]))
+        .attach(recorder.clone());
+    let client = std::sync::Arc::new(client(rocket).await);
+
+    let tasks: Vec<_> = (0..64)
+        .map(|task| {
+            let client = client.clone();
+            rocket::tokio::spawn(async move {
+                let index = task % 16;
+                let path = format!("/parallel/{}", index);
+                let response = get(&client, &path, "gzip").await;
+                assert_eq!(
+                    decoded_body(response).await,
+                    text(4096 + index).into_bytes()
+                );
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    for index in 0..16 {
+        let path = format!("/parallel/{}", index);
+        let response = get(&client, &path, "gzip").await;
+        assert_eq!(
+            decoded_body(response).await,
+            text(4096 + index).into_bytes()
+        );
+        assert_eq!(recorder.last().unwrap().cache_hit, Some(true), "{}", path);
+    }
+}
+
+#[rocket::get("/exports/<_name>")]
+fn exports(_name: &str) -> (ContentType, String) {
+    (ContentType::Binary, text(4096))
+}
+
+#[rocket::async_test]
+async fn instance_exclusions_allow_octet_stream() {
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![exports])
+        .attach(
+            CachedCompression::exact_path_fairing(vec!["/exports/allowed".to_owned()])
+                .exclude_content_types(vec![MediaType::new("image", "*")]),
+        )
+        .attach(CachedCompression::exact_path_fairing(vec![
+            "/exports/default".to_owned(),
+        ]))
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    for hit in [false, true] {
+        let response = get(&client, "/exports/allowed", "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+        assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+        assert_eq!(recorder.last().unwrap().cache_hit, Some(hit));
+    }
+
+    // Other instances keep the default exclusions.
+    let response = get(&client, "/exports/default", "gzip").await;
+    assert_eq!(encoding(&response), None);
+}
+
+#[rocket::get("/per-encoding-levels")]
+fn per_encoding_levels() -> String {
+    text(64 * 1024)
+}
+
+#[rocket::async_test]
+async fn gzip_and_brotli_levels_are_separate() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![per_encoding_levels])
+        .attach(
+            CachedCompression::exact_path_fairing(vec!["/per-encoding-levels".to_owned()])
+                .gzip_level(Level::Fastest)
+                .brotli_level(Level::Best),
+        );
+    let client = client(rocket).await;
+    let original = text(64 * 1024).into_bytes();
+
+    for (accept, encoding, level) in [
+        ("gzip", Encoding::Gzip, Level::Fastest),
+        ("br", Encoding::Brotli, Level::Best),
+    ] {
+        let expected = util::compress(&original, encoding.clone(), level)
+            .await
+            .unwrap();
+        let default = util::compress(&original, encoding, Level::Default)
+            .await
+            .unwrap();
+        assert_ne!(expected, default);
+
+        let response = get(&client, "/per-encoding-levels", accept).await;
+        assert_eq!(response.into_bytes().await.unwrap(), expected, "{}", accept);
+    }
+}
+
+#[derive(rocket::Responder)]
+struct Varying {
+    body: String,
+    vary: rocket::http::Header<'static>,
+}
+
+#[rocket::get("/vary")]
+fn vary() -> Varying {
+    Varying {
+        body: text(4096),
+        vary: rocket::http::Header::new("Vary", "Cookie"),
+    }
+}
+
+#[rocket::async_test]
+async fn vary_is_set_on_hits_and_misses() {
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![vary])
+        .attach(CachedCompression::exact_path_fairing(vec![
+            "/vary".to_owned()
+        ]))
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    for hit in [false, true] {
+        let response = get(&client, "/vary", "gzip").await;
+        assert_eq!(recorder.last().unwrap().cache_hit, Some(hit));
+        let vary: Vec<_> = response
+            .headers()
+            .get("Vary")
+            .flat_map(|value| value.split(','))
+            .map(|name| name.trim().to_ascii_lowercase())
+            .collect();
+        assert!(vary.contains(&"cookie".to_owned()), "{:?}", vary);
+        assert_eq!(
+            vary.iter()
+                .filter(|name| *name == "accept-encoding")
+                .count(),
+            1,
+            "{:?}",
+            vary
+        );
+    }
+}
+
+#[rocket::get("/tiny/<kind>")]
+fn tiny(kind: &str) -> String {
+    match kind {
+        "small" => text(300),
+        _ => text(4096),
+    }
+}
+
+#[rocket::async_test]
+async fn bodies_below_min_size_are_served_untouched_and_not_cached() {
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![tiny])
+        .attach(CachedCompression::path_prefix_fairing(vec!["/tiny/".to_owned()]).min_size(1024))
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    for _ in 0..2 {
+        let response = get(&client, "/tiny/small", "gzip").await;
+        assert_eq!(encoding(&response), None);
+        assert_eq!(response.into_string().await.unwrap(), text(300));
+        let info = recorder.last().unwrap();
+        assert_eq!(info.skip_reason, Some(SkipReason::TooSmall));
+        assert_ne!(info.cache_hit, Some(true));
+    }
+
+    for hit in [false, true] {
+        let response = get(&client, "/tiny/large", "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+        assert_eq!(recorder.last().unwrap().cache_hit, Some(hit));
+    }
+}
+
+/// A body of `x`s that never ends.
+struct Endless;
+
+impl rocket::tokio::io::AsyncRead for Endless {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+        buf: &mut rocket::tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        let len = buf.remaining().min(1024);
+        buf.put_slice(&vec![b'x'; len]);
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+struct EndlessBody;
+
+impl<'r> rocket::response::Responder<'r, 'static> for EndlessBody {
+    fn respond_to(self, _: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        rocket::Response::build()
+            .header(ContentType::Plain)
+            .streamed_body(Endless)
+            .ok()
+    }
+}
+
+#[rocket::get("/endless")]
+fn endless() -> EndlessBody {
+    EndlessBody
+}
+
+#[rocket::async_test]
+async fn never_ending_bodies_are_streamed_instead_of_buffered() {
+    use rocket::tokio::io::AsyncReadExt;
+
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![endless])
+        .attach(
+            CachedCompression::exact_path_fairing(vec!["/endless".to_owned()])
+                .max_buffer_size(64 * 1024),
+        )
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    let response = rocket::tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        get(&client, "/endless", "gzip"),
+    )
+    .await
+    .expect("the response should start without buffering the whole body");
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(recorder.last().unwrap().cache_hit, Some(false));
+
+    let mut decoded = async_compression::tokio::bufread::GzipDecoder::new(
+        rocket::tokio::io::BufReader::new(response),
+    );
+    let mut read = vec![0; 256 * 1024];
+    decoded.read_exact(&mut read).await.unwrap();
+    assert!(read.iter().all(|&byte| byte == b'x'));
+}
+
+#[rocket::get("/configured/<file>")]
+fn configured(file: &str) -> String {
+    text(4096 + file.len())
+}
+
+#[rocket::async_test]
+async fn cached_paths_are_read_from_the_figment() {
+    let figment = rocket::Config::figment()
+        .merge(("cached_compression.cached_path_suffixes", vec![".js"]))
+        .merge((
+            "cached_compression.excluded_path_prefixes",
+            vec!["/configured/vendor"],
+        ))
+        .merge(("cached_compression.level", "fastest"));
+    let rocket = rocket::custom(figment);
+    let fairing = CachedCompression::from_figment(rocket.figment()).unwrap();
+    let recorder = Recorder::default();
+    let rocket = rocket
+        .mount("/", rocket::routes![configured])
+        .attach(fairing)
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    for hit in [false, true] {
+        let response = get(&client, "/configured/app.js", "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+        assert_eq!(decoded_body(response).await, text(4102).into_bytes());
+        assert_eq!(recorder.last().unwrap().cache_hit, Some(hit));
+    }
+
+    let response = get(&client, "/configured/vendor.js", "gzip").await;
+    assert_eq!(encoding(&response), None);
+    let response = get(&client, "/configured/app.css", "gzip").await;
+    assert_eq!(encoding(&response), None);
+}
+
+#[test]
+fn invalid_figment_levels_are_errors() {
+    let figment = rocket::Config::figment().merge(("cached_compression.level", "smallest"));
+    assert!(CachedCompression::from_figment(&figment).is_err());
+
+    // Without a `cached_compression` table, nothing is cached.
+    assert!(CachedCompression::from_figment(&rocket::Config::figment()).is_ok());
+}
+
+#[rocket::get("/verified")]
+fn verified() -> String {
+    text(8192)
+}
+
+#[rocket::async_test]
+async fn verified_bodies_are_served_compressed_and_cached() {
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![verified])
+        .attach(
+            CachedCompression::exact_path_fairing(vec!["/verified".to_owned()]).debug_verify(true),
+        )
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    for accept in ["gzip", "br"] {
+        for hit in [false, true] {
+            let response = get(&client, "/verified", accept).await;
+            assert_eq!(encoding(&response).as_deref(), Some(accept));
+            assert_eq!(decoded_body(response).await, text(8192).into_bytes());
+            assert_eq!(recorder.last().unwrap().cache_hit, Some(hit));
+        }
+    }
+}
+
+#[rocket::get("/runtime/<page>")]
+fn runtime(page: &str) -> String {
+    text(4096 + page.len())
+}
+
+#[rocket::async_test]
+async fn runtime_rules_apply_to_the_following_requests() {
+    let recorder = Recorder::default();
+    let fairing = CachedCompression::exact_path_fairing(Vec::new());
+    let rules = fairing.rules();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![runtime])
+        .attach(fairing)
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    let response = get(&client, "/runtime/sale", "gzip").await;
+    assert_eq!(encoding(&response), None);
+
+    rules.add_exact_path("/runtime/sale");
+    for hit in [false, true] {
+        let response = get(&client, "/runtime/sale", "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+        assert_eq!(decoded_body(response).await, text(4100).into_bytes());
+        assert_eq!(recorder.last().unwrap().cache_hit, Some(hit));
+    }
+
+    // Purged bodies are compressed again when another rule still matches.
+    rules.add_prefix("/runtime/");
+    assert!(rules.remove_rule("/runtime/sale", true));
+    assert!(!rules.remove_rule("/runtime/sale", true));
+    let response = get(&client, "/runtime/sale", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(recorder.last().unwrap().cache_hit, Some(false));
+
+    assert!(rules.remove_rule("/runtime/", false));
+    let response = get(&client, "/runtime/sale", "gzip").await;
+    assert_eq!(encoding(&response), None);
+}
+
+#[rocket::get("/entry/<_>")]
+fn entry() -> String {
+    text(8192)
+}
+
+/// Returns whether the second response to `path`, with the entry size limit
+/// set to `max_cache_entry_bytes`, came from the cache.
+async fn second_request_hits(path: &str, max_cache_entry_bytes: usize) -> Option<bool> {
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![entry])
+        .attach(
+            CachedCompression::exact_path_fairing(vec![path.to_owned()])
+                .max_cache_entry_bytes(max_cache_entry_bytes),
+        )
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    for _ in 0..2 {
+        let response = get(&client, path, "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+        assert_eq!(decoded_body(response).await, text(8192).into_bytes());
+    }
+    recorder.last().unwrap().cache_hit
+}
+
+#[rocket::async_test]
+async fn oversized_entries_are_compressed_but_not_cached() {
+    assert_eq!(
+        second_request_hits("/entry/small", 1024 * 1024).await,
+        Some(true)
+    );
+    assert_eq!(second_request_hits("/entry/large", 16).await, Some(false));
+}
+
+#[rocket::get("/eager-verified")]
+fn eager_verified() -> String {
+    text(4096)
+}
+
+#[rocket::async_test]
+async fn eager_bodies_are_verified_and_cached() {
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![eager_verified])
+        .attach(
+            CachedCompression::exact_path_fairing(vec!["/eager-verified".to_owned()])
+                .eager_encodings(vec![Encoding::Gzip, Encoding::Brotli])
+                .debug_verify(true),
+        )
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    for (accept, hit) in [("gzip", false), ("br", true)] {
+        let response = get(&client, "/eager-verified", accept).await;
+        assert_eq!(encoding(&response).as_deref(), Some(accept));
+        assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+        assert_eq!(recorder.last().unwrap().cache_hit, Some(hit));
+    }
+}
+
+#[rocket::get("/quality/<_>")]
+fn quality() -> String {
+    text(4096)
+}
+
+#[rocket::async_test]
+async fn both_fairings_negotiate_qualities_alike() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![quality])
+        .attach(CachedCompression::exact_path_fairing(vec![
+            "/quality/cached".to_owned(),
+        ]))
+        .attach(Compression::fairing());
+    let client = client(rocket).await;
+
+    for (accept, expected) in [
+        ("gzip;q=1, br;q=0.1", "gzip"),
+        ("gzip;q=0.5, br", "br"),
+        ("gzip, br", "br"),
+        ("br;q=0, *", "gzip"),
+    ] {
+        for path in ["/quality/cached", "/quality/dynamic"] {
+            let response = get(&client, path, accept).await;
+            assert_eq!(
+                encoding(&response).as_deref(),
+                Some(expected),
+                "{} with {}",
+                path,
+                accept
+            );
+            assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+        }
+    }
+}
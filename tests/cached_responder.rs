@@ -0,0 +1,62 @@
+mod common;
+
+use common::{client, decoded_body, encoding, get, text};
+use rocket_async_compression::CachedCompress;
+
+#[rocket::get("/report")]
+fn report() -> CachedCompress<String> {
+    CachedCompress::new("/report", text(4096))
+}
+
+#[rocket::async_test]
+async fn cached_responder_compresses_caches_and_varies() {
+    let client = client(rocket::build().mount("/", rocket::routes![report])).await;
+
+    let first = get(&client, "/report", "gzip").await;
+    assert_eq!(encoding(&first).as_deref(), Some("gzip"));
+    assert_eq!(first.headers().get_one("Vary"), Some("Accept-Encoding"));
+    assert_eq!(first.body().preset_size(), None);
+    assert_eq!(decoded_body(first).await, text(4096).into_bytes());
+
+    let second = get(&client, "/report", "gzip").await;
+    assert_eq!(second.headers().get_one("Vary"), Some("Accept-Encoding"));
+    assert!(second.body().preset_size().is_some());
+    assert_eq!(decoded_body(second).await, text(4096).into_bytes());
+}
+
+#[rocket::async_test]
+async fn cached_responder_leaves_unaccepted_responses_alone() {
+    let client = client(rocket::build().mount("/", rocket::routes![report])).await;
+
+    let response = get(&client, "/report", "identity").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.into_string().await.unwrap(), text(4096));
+}
+
+#[rocket::get("/keyed/<key>")]
+fn keyed(key: &str) -> CachedCompress<String> {
+    CachedCompress::new(format!("/keyed/{}", key), format!("{}{}", key, text(4096)))
+}
+
+#[rocket::async_test]
+async fn cached_responder_caches_each_key_separately() {
+    let client = client(rocket::build().mount("/", rocket::routes![keyed])).await;
+
+    for key in ["a", "b"] {
+        let path = format!("/keyed/{}", key);
+        let expected = format!("{}{}", key, text(4096)).into_bytes();
+
+        let miss = get(&client, &path, "gzip").await;
+        assert_eq!(miss.body().preset_size(), None);
+        assert_eq!(decoded_body(miss).await, expected);
+
+        let hit = get(&client, &path, "gzip").await;
+        assert!(hit.body().preset_size().is_some());
+        assert_eq!(decoded_body(hit).await, expected);
+
+        // Each encoding is cached separately.
+        let other_encoding = get(&client, &path, "br").await;
+        assert_eq!(other_encoding.body().preset_size(), None);
+        assert_eq!(decoded_body(other_encoding).await, expected);
+    }
+}
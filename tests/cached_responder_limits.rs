@@ -0,0 +1,43 @@
+//! Kept apart from the other `CachedCompress` tests since the size bound is
+//! shared by every responder in the process.
+
+mod common;
+
+use common::{client, decoded_body, get, text};
+use rocket_async_compression::{set_cached_compress_max_bytes, CachedCompress};
+
+#[rocket::get("/bounded/<key>")]
+fn bounded(key: &str) -> CachedCompress<String> {
+    CachedCompress::new(format!("/bounded/{}", key), text(4096))
+}
+
+#[rocket::async_test]
+async fn oldest_entries_are_evicted_past_the_bound() {
+    let client = client(rocket::build().mount("/", rocket::routes![bounded])).await;
+    let cached =
+        |response: &rocket::local::asynchronous::LocalResponse<'_>| response.body().preset_size();
+
+    let miss = get(&client, "/bounded/a", "gzip").await;
+    assert_eq!(cached(&miss), None);
+    let compressed_len = miss.into_bytes().await.unwrap().len();
+
+    // Only a single entry fits from now on.
+    set_cached_compress_max_bytes(compressed_len);
+    let hit = get(&client, "/bounded/a", "gzip").await;
+    assert_eq!(cached(&hit), Some(compressed_len));
+    assert_eq!(decoded_body(hit).await, text(4096).into_bytes());
+
+    get(&client, "/bounded/b", "gzip").await.into_bytes().await;
+    let hit = get(&client, "/bounded/b", "gzip").await;
+    assert_eq!(cached(&hit), Some(compressed_len));
+
+    let evicted = get(&client, "/bounded/a", "gzip").await;
+    assert_eq!(cached(&evicted), None);
+    assert_eq!(decoded_body(evicted).await, text(4096).into_bytes());
+
+    // Entries larger than the bound are never cached.
+    set_cached_compress_max_bytes(compressed_len - 1);
+    get(&client, "/bounded/c", "gzip").await.into_bytes().await;
+    let miss = get(&client, "/bounded/c", "gzip").await;
+    assert_eq!(cached(&miss), None);
+}
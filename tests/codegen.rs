@@ -0,0 +1,94 @@
+mod common;
+
+use common::{client, decoded_body, encoding, get, text};
+use rocket::{get, http::Status, routes};
+use rocket_async_compression::compress;
+
+#[get("/sync")]
+#[compress]
+fn sync_route() -> String {
+    text(2048)
+}
+
+#[compress(level = "best")]
+#[get("/async")]
+async fn async_route() -> String {
+    rocket::tokio::task::yield_now().await;
+    text(2048)
+}
+
+#[get("/fallible/<fail>")]
+#[compress(level = "9", encoding = "gzip")]
+fn fallible(fail: bool) -> Result<String, Status> {
+    let body: Result<String, Status> = if fail {
+        Err(Status::ImATeapot)
+    } else {
+        Ok(text(2048))
+    };
+    let body = body?;
+    if body.is_empty() {
+        return Err(Status::NoContent);
+    }
+    Ok(body)
+}
+
+#[get("/echo/<name>")]
+#[compress(level = "fastest", encoding = "br")]
+fn echo(name: &str) -> &str {
+    name
+}
+
+#[get("/plain")]
+fn undecorated() -> String {
+    text(2048)
+}
+
+fn rocket() -> rocket::Rocket<rocket::Build> {
+    rocket::build().mount(
+        "/",
+        routes![sync_route, async_route, fallible, echo, undecorated],
+    )
+}
+
+#[rocket::async_test]
+async fn decorated_sync_and_async_routes_are_compressed() {
+    let client = client(rocket()).await;
+    for path in ["/sync", "/async"] {
+        let response = get(&client, path, "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+        assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+    }
+}
+
+#[rocket::async_test]
+async fn undecorated_routes_are_left_alone() {
+    let client = client(rocket()).await;
+    let response = get(&client, "/plain", "gzip").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.into_string().await.unwrap(), text(2048));
+}
+
+#[rocket::async_test]
+async fn question_mark_and_return_keep_the_original_responder_type() {
+    let client = client(rocket()).await;
+
+    let response = get(&client, "/fallible/false", "br, gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+
+    let response = get(&client, "/fallible/true", "br, gzip").await;
+    assert_eq!(response.status(), Status::ImATeapot);
+}
+
+#[rocket::async_test]
+async fn forced_encodings_are_parsed_and_arguments_can_be_borrowed() {
+    let client = client(rocket()).await;
+
+    let response = get(&client, "/echo/hello", "br").await;
+    assert_eq!(encoding(&response).as_deref(), Some("br"));
+    assert_eq!(decoded_body(response).await, b"hello");
+
+    let response = get(&client, "/echo/hello", "gzip").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.into_string().await.unwrap(), "hello");
+}
@@ -0,0 +1,156 @@
+#![allow(dead_code)]
+
+use std::{
+    io::Cursor,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::{ContentType, Header},
+    local::asynchronous::{Client, LocalResponse},
+    response::{self, Responder},
+    Build, Request, Response, Rocket,
+};
+use rocket_async_compression::{util, CompressionInfo, Encoding};
+
+/// A body long and repetitive enough to be worth compressing.
+pub fn text(len: usize) -> String {
+    "Hello, world! ".repeat(len / 14 + 1)[..len].to_owned()
+}
+
+/// Creates an empty directory unique to this test run.
+pub fn temp_dir(name: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let dir = std::env::temp_dir().join(format!(
+        "rocket_async_compression-{}-{}-{}",
+        name,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A responder with a streamed body, whose size is unknown to the fairings.
+pub struct Streamed(pub ContentType, pub Vec<u8>);
+
+impl<'r> Responder<'r, 'static> for Streamed {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .header(self.0)
+            .streamed_body(Cursor::new(self.1))
+            .ok()
+    }
+}
+
+/// A responder with a sized body and no `Content-Type`.
+pub struct Untyped(pub Vec<u8>);
+
+impl<'r> Responder<'r, 'static> for Untyped {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .sized_body(self.0.len(), Cursor::new(self.0))
+            .ok()
+    }
+}
+
+/// A fairing recording the [`CompressionInfo`] of every response, to be
+/// attached after the fairing under test.
+#[derive(Clone, Default)]
+pub struct Recorder(pub Arc<Mutex<Vec<Option<CompressionInfo>>>>);
+
+impl Recorder {
+    pub fn infos(&self) -> Vec<Option<CompressionInfo>> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn last(&self) -> Option<CompressionInfo> {
+        self.infos().pop().flatten()
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Recorder {
+    fn info(&self) -> Info {
+        Info {
+            name: "Recorder",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, _: &mut Response<'r>) {
+        self.0.lock().unwrap().push(CompressionInfo::of(request));
+    }
+}
+
+/// A fairing recording the size Rocket sends as the `Content-Length` of every
+/// response, or `None` for chunked responses, to be attached after the
+/// fairing under test.
+#[derive(Clone, Default)]
+pub struct BodySizes(pub Arc<Mutex<Vec<Option<usize>>>>);
+
+impl BodySizes {
+    pub fn last(&self) -> Option<usize> {
+        self.0.lock().unwrap().last().copied().flatten()
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for BodySizes {
+    fn info(&self) -> Info {
+        Info {
+            name: "BodySizes",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, _: &'r Request<'_>, response: &mut Response<'r>) {
+        let size = response.body_mut().size().await;
+        self.0.lock().unwrap().push(size);
+    }
+}
+
+pub async fn client(rocket: Rocket<Build>) -> Client {
+    Client::untracked(rocket).await.expect("valid rocket")
+}
+
+/// Requests `path` accepting `accept_encoding`.
+pub async fn get<'c>(
+    client: &'c Client,
+    path: &'c str,
+    accept_encoding: &str,
+) -> LocalResponse<'c> {
+    client
+        .get(path)
+        .header(Header::new("Accept-Encoding", accept_encoding.to_owned()))
+        .dispatch()
+        .await
+}
+
+/// Returns the `Content-Encoding` of `response`, if any.
+pub fn encoding(response: &LocalResponse<'_>) -> Option<String> {
+    response
+        .headers()
+        .get_one("Content-Encoding")
+        .map(|encoding| encoding.to_owned())
+}
+
+/// Reads the body of `response` and decodes it according to its
+/// `Content-Encoding`.
+pub async fn decoded_body(response: LocalResponse<'_>) -> Vec<u8> {
+    let encoding = encoding(&response);
+    let body = response.into_bytes().await.expect("a body");
+    match encoding.as_deref() {
+        None | Some("identity") => body,
+        Some(encoding) => util::decompress(&body, encoding.parse::<Encoding>().unwrap())
+            .await
+            .expect("a valid compressed body"),
+    }
+}
@@ -0,0 +1,877 @@
+mod common;
+
+use common::{client, decoded_body, encoding, get, text, BodySizes, Streamed, Untyped};
+use rocket::http::{ContentType, Header, MediaType, Status};
+use rocket_async_compression::{
+    register_compressed_content_type, util, CachedCompression, Compress, Compression,
+    ConfiguredCompress, Encoding, HostConfig, Level, NoContentTypePolicy, ProxiedPolicy,
+    ORIGINAL_CONTENT_LENGTH,
+};
+
+const PNG_MAGIC: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+fn png() -> Vec<u8> {
+    let mut body = PNG_MAGIC.to_vec();
+    body.extend(text(2048).into_bytes());
+    body
+}
+
+#[rocket::get("/untyped-text")]
+fn untyped_text() -> Untyped {
+    Untyped(text(2048).into_bytes())
+}
+
+#[rocket::get("/untyped-png")]
+fn untyped_png() -> Untyped {
+    Untyped(png())
+}
+
+fn sniffing() -> rocket::Rocket<rocket::Build> {
+    rocket::build()
+        .mount("/", rocket::routes![untyped_text, untyped_png])
+        .attach(Compression::fairing().no_content_type(NoContentTypePolicy::Sniff))
+}
+
+#[rocket::async_test]
+async fn sniffing_compresses_untyped_text() {
+    let client = client(sniffing()).await;
+    let response = get(&client, "/untyped-text", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+}
+
+#[rocket::async_test]
+async fn sniffing_skips_untyped_png() {
+    let client = client(sniffing()).await;
+    let response = get(&client, "/untyped-png", "gzip").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.body().preset_size(), Some(png().len()));
+    assert_eq!(response.into_bytes().await.unwrap(), png());
+}
+
+#[rocket::async_test]
+async fn sniffing_leaves_the_body_of_uncompressed_responses_alone() {
+    let client = client(sniffing()).await;
+    let response = get(&client, "/untyped-text", "identity").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.body().preset_size(), Some(2048));
+    assert_eq!(
+        response.into_bytes().await.unwrap(),
+        text(2048).into_bytes()
+    );
+}
+
+#[rocket::get("/text")]
+fn plain_text() -> String {
+    text(2048)
+}
+
+#[rocket::async_test]
+async fn matching_user_agents_get_gzip_even_when_accepting_br() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![plain_text])
+        .attach(
+            Compression::fairing().prefer_gzip_for_user_agents(vec!["LegacyClient/1.".to_owned()]),
+        );
+    let client = client(rocket).await;
+
+    let response = client
+        .get("/text")
+        .header(Header::new("Accept-Encoding", "br, gzip"))
+        .header(Header::new("User-Agent", "LegacyClient/1.4"))
+        .dispatch()
+        .await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+
+    let response = client
+        .get("/text")
+        .header(Header::new("Accept-Encoding", "br, gzip"))
+        .header(Header::new("User-Agent", "ModernClient/2.0"))
+        .dispatch()
+        .await;
+    assert_eq!(encoding(&response).as_deref(), Some("br"));
+    assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+}
+
+fn protobuf() -> ContentType {
+    ContentType::new("application", "x-force-test")
+}
+
+#[rocket::get("/forced")]
+fn forced() -> ConfiguredCompress<(ContentType, String)> {
+    Compress::default((protobuf(), text(2048))).force()
+}
+
+#[rocket::get("/unforced")]
+fn unforced() -> Compress<(ContentType, String)> {
+    Compress::default((protobuf(), text(2048)))
+}
+
+#[rocket::async_test]
+async fn force_overrides_registered_compressed_types() {
+    register_compressed_content_type(protobuf().media_type().clone());
+    let client = client(rocket::build().mount("/", rocket::routes![forced, unforced])).await;
+
+    let response = get(&client, "/forced", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+
+    let response = get(&client, "/unforced", "gzip").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.into_string().await.unwrap(), text(2048));
+}
+
+#[rocket::get("/sized/<len>")]
+fn sized(len: usize) -> String {
+    text(len)
+}
+
+#[rocket::async_test]
+async fn small_sized_bodies_keep_a_content_length_and_large_ones_are_chunked() {
+    let sizes = BodySizes::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![sized])
+        .attach(Compression::fairing().max_buffered_body(4096))
+        .attach(sizes.clone());
+    let client = client(rocket).await;
+
+    let response = get(&client, "/sized/4096", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    let body = response.into_bytes().await.unwrap();
+    assert_eq!(sizes.last(), Some(body.len()));
+    let decoded = util::decompress(&body, Encoding::Gzip).await.unwrap();
+    assert_eq!(decoded, text(4096).into_bytes());
+
+    let response = get(&client, "/sized/4097", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(sizes.last(), None);
+    assert_eq!(decoded_body(response).await, text(4097).into_bytes());
+}
+
+#[rocket::async_test]
+async fn bodies_over_64_kib_are_chunked_by_default() {
+    let sizes = BodySizes::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![sized])
+        .attach(Compression::fairing())
+        .attach(sizes.clone());
+    let client = client(rocket).await;
+
+    let response = get(&client, "/sized/65536", "gzip").await;
+    assert!(sizes.last().is_some());
+    assert_eq!(decoded_body(response).await, text(65536).into_bytes());
+
+    let response = get(&client, "/sized/65537", "gzip").await;
+    assert_eq!(sizes.last(), None);
+    assert_eq!(decoded_body(response).await, text(65537).into_bytes());
+}
+
+#[rocket::async_test]
+async fn untyped_responses_follow_the_no_content_type_policy() {
+    for (policy, expected) in [
+        (NoContentTypePolicy::Compress, Some("gzip")),
+        (NoContentTypePolicy::Skip, None),
+    ] {
+        let rocket = rocket::build()
+            .mount("/", rocket::routes![untyped_text])
+            .attach(Compression::fairing().no_content_type(policy));
+        let client = client(rocket).await;
+
+        let response = get(&client, "/untyped-text", "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), expected);
+        assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+    }
+}
+
+#[rocket::async_test]
+async fn accept_encoding_headers_are_combined_across_lines() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![plain_text])
+        .attach(Compression::fairing());
+    let client = client(rocket).await;
+
+    let response = client
+        .get("/text")
+        .header(Header::new("Accept-Encoding", "identity"))
+        .header(Header::new("Accept-Encoding", "gzip"))
+        .dispatch()
+        .await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+
+    let response = client
+        .get("/text")
+        .header(Header::new("Accept-Encoding", "gzip;q=0.5"))
+        .header(Header::new("Accept-Encoding", "br;q=0"))
+        .dispatch()
+        .await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+}
+
+#[rocket::get("/typed/<top>/<sub>")]
+fn typed(top: String, sub: String) -> (ContentType, String) {
+    (ContentType::new(top, sub), text(2048))
+}
+
+#[rocket::async_test]
+async fn default_exclusions_are_valid_and_left_uncompressed() {
+    let exclusions = Compression::fairing().excluded_content_types;
+    assert!(!exclusions.is_empty());
+
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![typed])
+        .attach(Compression::fairing());
+    let client = client(rocket).await;
+    for exclusion in exclusions {
+        let parsed = MediaType::parse_flexible(&exclusion.to_string());
+        assert_eq!(parsed.as_ref(), Some(&exclusion));
+
+        let sub = match exclusion.sub().as_str() {
+            "*" => "anything",
+            sub => sub,
+        };
+        let path = format!("/typed/{}/{}", exclusion.top(), sub);
+        let response = get(&client, &path, "gzip").await;
+        assert_eq!(encoding(&response), None, "{}", exclusion);
+    }
+}
+
+#[rocket::get("/scoped/<_path..>")]
+fn scoped(_path: std::path::PathBuf) -> String {
+    text(2048)
+}
+
+#[rocket::async_test]
+async fn only_included_paths_are_compressed_when_set() {
+    let rocket = rocket::build().mount("/", rocket::routes![scoped]).attach(
+        Compression::fairing()
+            .included_paths(vec!["/scoped/exact".to_owned()])
+            .included_path_prefixes(vec!["/scoped/assets/".to_owned()]),
+    );
+    let scoped_client = client(rocket).await;
+
+    for (path, expected) in [
+        ("/scoped/exact", Some("gzip")),
+        ("/scoped/exact/nested", None),
+        ("/scoped/assets/app.js", Some("gzip")),
+        ("/scoped/other", None),
+    ] {
+        let response = get(&scoped_client, path, "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), expected, "{}", path);
+        assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+    }
+
+    // Without any path rules, every path is compressed.
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![scoped])
+        .attach(Compression::fairing());
+    let unscoped = client(rocket).await;
+    let response = get(&unscoped, "/scoped/other", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+}
+
+#[rocket::get("/disabled")]
+fn disabled() -> String {
+    text(4096)
+}
+
+#[rocket::async_test]
+async fn disabled_fairing_leaves_responses_untouched() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![disabled])
+        .attach(Compression::disabled());
+    let client = client(rocket).await;
+
+    let response = get(&client, "/disabled", "br, gzip").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.headers().get_one("Vary"), None);
+    assert_eq!(response.into_string().await.unwrap(), text(4096));
+}
+
+/// A responder setting its body through the lower-level `Response` setters.
+struct RawBody(&'static str);
+
+impl<'r> rocket::response::Responder<'r, 'static> for RawBody {
+    fn respond_to(self, _: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        let mut response = rocket::Response::new();
+        response.set_header(ContentType::Plain);
+        let body = text(2048).into_bytes();
+        match self.0 {
+            "sized" => response.set_sized_body(body.len(), std::io::Cursor::new(body)),
+            "streamed" => response.set_streamed_body(std::io::Cursor::new(body)),
+            "empty" => response.set_sized_body(0, std::io::Cursor::new(Vec::new())),
+            _ => {}
+        }
+        Ok(response)
+    }
+}
+
+#[rocket::get("/raw/<kind>")]
+fn raw(kind: &str) -> RawBody {
+    RawBody(match kind {
+        "sized" => "sized",
+        "streamed" => "streamed",
+        "empty" => "empty",
+        _ => "none",
+    })
+}
+
+#[rocket::async_test]
+async fn bodies_set_on_the_response_are_compressed() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![raw])
+        .attach(Compression::fairing());
+    let client = client(rocket).await;
+
+    for path in ["/raw/sized", "/raw/streamed"] {
+        let response = get(&client, path, "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"), "{}", path);
+        assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+    }
+
+    for path in ["/raw/empty", "/raw/none"] {
+        let response = get(&client, path, "gzip").await;
+        assert_eq!(encoding(&response), None, "{}", path);
+        assert!(response.into_bytes().await.unwrap_or_default().is_empty());
+    }
+}
+
+#[rocket::get("/identity/<kind>")]
+fn identity(kind: &str) -> (ContentType, String) {
+    match kind {
+        "png" => (ContentType::PNG, text(2048)),
+        "small" => (ContentType::Plain, text(16)),
+        _ => (ContentType::Plain, text(2048)),
+    }
+}
+
+#[rocket::async_test]
+async fn skipped_responses_are_marked_identity_only_when_enabled() {
+    for mark in [true, false] {
+        let rocket = rocket::build()
+            .mount("/", rocket::routes![identity])
+            .attach(Compression {
+                min_size: Some(1024),
+                ..Compression::fairing().mark_identity_when_skipped(mark)
+            });
+        let client = client(rocket).await;
+        let marker = if mark { Some("identity") } else { None };
+
+        for (path, accept) in [
+            ("/identity/png", "gzip"),
+            ("/identity/small", "gzip"),
+            ("/identity/text", "compress"),
+        ] {
+            let response = get(&client, path, accept).await;
+            assert_eq!(encoding(&response).as_deref(), marker, "{}", path);
+        }
+
+        let response = get(&client, "/identity/text", "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    }
+}
+
+#[derive(rocket::Responder)]
+struct RawContentType {
+    body: String,
+    content_type: Header<'static>,
+}
+
+#[rocket::get("/casing/<index>")]
+fn casing(index: usize) -> RawContentType {
+    let content_type = [
+        "Application/JSON; charset=utf-8",
+        "application/json; Charset=\"UTF-8\"; foo=bar",
+        "TEXT/HTML;charset=UTF-8",
+        "IMAGE/PNG; foo=bar",
+        "image/Png",
+    ][index];
+    RawContentType {
+        body: text(2048),
+        content_type: Header::new("Content-Type", content_type),
+    }
+}
+
+#[rocket::async_test]
+async fn content_types_match_regardless_of_casing_and_parameters() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![casing])
+        .attach(Compression::fairing());
+    let client = client(rocket).await;
+
+    for index in 0..3 {
+        let path = format!("/casing/{}", index);
+        let response = get(&client, &path, "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"), "{}", path);
+        assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+    }
+
+    for index in 3..5 {
+        let path = format!("/casing/{}", index);
+        let response = get(&client, &path, "gzip").await;
+        assert_eq!(encoding(&response), None, "{}", path);
+    }
+}
+
+#[rocket::get("/server-encodings")]
+fn server_encodings() -> String {
+    text(2048)
+}
+
+#[rocket::async_test]
+async fn only_server_encodings_are_negotiated() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![server_encodings])
+        .attach(Compression::fairing().server_encodings(vec![Encoding::Gzip]));
+    let client = client(rocket).await;
+
+    let response = get(&client, "/server-encodings", "br, gzip;q=0.5").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+
+    let response = get(&client, "/server-encodings", "br").await;
+    assert_eq!(encoding(&response), None);
+}
+
+#[rocket::async_test]
+async fn server_encodings_are_preferred_in_order() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![server_encodings])
+        .attach(Compression::fairing().server_encodings(vec![Encoding::Gzip, Encoding::Brotli]));
+    let client = client(rocket).await;
+
+    let response = get(&client, "/server-encodings", "br, gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    // The client's qualities still come first.
+    let response = get(&client, "/server-encodings", "br, gzip;q=0.5").await;
+    assert_eq!(encoding(&response).as_deref(), Some("br"));
+}
+
+#[rocket::get("/original-length/sized")]
+fn original_length_sized() -> String {
+    text(2048)
+}
+
+#[rocket::get("/original-length/streamed")]
+fn original_length_streamed() -> Streamed {
+    Streamed(ContentType::Plain, text(2048).into_bytes())
+}
+
+#[rocket::get("/original-length-cached")]
+fn original_length_cached() -> Streamed {
+    Streamed(ContentType::Plain, text(2048).into_bytes())
+}
+
+#[rocket::async_test]
+async fn original_length_is_only_set_when_known() {
+    let rocket = rocket::build()
+        .mount(
+            "/",
+            rocket::routes![
+                original_length_sized,
+                original_length_streamed,
+                original_length_cached
+            ],
+        )
+        .attach(Compression::fairing().original_length_header(ORIGINAL_CONTENT_LENGTH))
+        .attach(
+            CachedCompression::exact_path_fairing(vec!["/original-length-cached".to_owned()])
+                .original_length_header("X-Identity-Length"),
+        );
+    let client = client(rocket).await;
+
+    let response = get(&client, "/original-length/sized", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(
+        response.headers().get_one(ORIGINAL_CONTENT_LENGTH),
+        Some("2048")
+    );
+
+    let response = get(&client, "/original-length/streamed", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(response.headers().get_one(ORIGINAL_CONTENT_LENGTH), None);
+
+    // The cached fairing buffers streamed bodies, so it always knows their size.
+    let response = get(&client, "/original-length-cached", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(
+        response.headers().get_one("X-Identity-Length"),
+        Some("2048")
+    );
+
+    let response = get(&client, "/original-length/sized", "compress").await;
+    assert_eq!(response.headers().get_one(ORIGINAL_CONTENT_LENGTH), None);
+}
+
+/// A streamed body with an accurate `Content-Length` header.
+struct StreamedWithLength(Vec<u8>);
+
+impl<'r> rocket::response::Responder<'r, 'static> for StreamedWithLength {
+    fn respond_to(self, _: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        rocket::Response::build()
+            .header(ContentType::Plain)
+            .header(Header::new("Content-Length", self.0.len().to_string()))
+            .streamed_body(std::io::Cursor::new(self.0))
+            .ok()
+    }
+}
+
+#[rocket::get("/streamed-length/<len>")]
+fn streamed_length(len: usize) -> StreamedWithLength {
+    StreamedWithLength(text(len).into_bytes())
+}
+
+#[rocket::async_test]
+async fn min_size_applies_to_streamed_bodies_with_a_content_length() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![streamed_length])
+        .attach(Compression {
+            min_size: Some(1024),
+            ..Compression::fairing()
+        });
+    let client = client(rocket).await;
+
+    let response = get(&client, "/streamed-length/100", "gzip").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.into_string().await.unwrap(), text(100));
+
+    let response = get(&client, "/streamed-length/4096", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    // The header describes the original body, so it must not survive compression.
+    assert_eq!(response.headers().get_one("Content-Length"), None);
+    assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+}
+
+#[rocket::get("/compressible/<top>/<sub>")]
+fn compressible(top: &str, sub: &str) -> RawContentType {
+    RawContentType {
+        body: text(2048),
+        content_type: Header::new("Content-Type", format!("{}/{}", top, sub)),
+    }
+}
+
+#[rocket::async_test]
+async fn compressible_defaults_only_compress_known_compressible_types() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![compressible])
+        .attach(Compression::compressible_defaults());
+    let client = client(rocket).await;
+
+    for (content_type, compressed) in [
+        ("text/html", true),
+        ("text/csv", true),
+        ("text/event-stream", false),
+        ("application/json", true),
+        ("application/javascript", true),
+        ("application/problem+json", true),
+        ("application/atom+xml", true),
+        ("image/svg+xml", true),
+        ("application/wasm", true),
+        ("font/ttf", true),
+        ("image/bmp", true),
+        ("application/octet-stream", false),
+        ("application/x-unknown-binary", false),
+        ("image/png", false),
+        ("video/mp4", false),
+        ("font/woff2", false),
+    ] {
+        let path = format!("/compressible/{}", content_type);
+        let response = get(&client, &path, "gzip").await;
+        let expected = if compressed { Some("gzip") } else { None };
+        assert_eq!(encoding(&response).as_deref(), expected, "{}", content_type);
+    }
+}
+
+#[rocket::get("/hosts")]
+fn hosts() -> String {
+    text(16 * 1024)
+}
+
+#[rocket::async_test]
+async fn host_configs_apply_per_host() {
+    let rocket = rocket::build().mount("/", rocket::routes![hosts]).attach(
+        Compression::fairing()
+            .host_config("api.example.com", HostConfig::disabled())
+            .host_config("*.example.com", HostConfig::with_level(Level::Fastest))
+            .host_config("localhost:8000", HostConfig::disabled()),
+    );
+    let client = client(rocket).await;
+    // Local requests don't derive their host from a `Host` header, so it is set directly.
+    let request = |host: &'static str| {
+        let mut request = client
+            .get("/hosts")
+            .header(Header::new("Accept-Encoding", "gzip"));
+        request.set_host(rocket::http::uri::Host::parse(host).unwrap());
+        request.dispatch()
+    };
+
+    for host in ["api.example.com", "API.Example.com:8443", "localhost:8000"] {
+        let response = request(host).await;
+        assert_eq!(encoding(&response), None, "{}", host);
+    }
+
+    let fastest = util::compress(text(16 * 1024).as_bytes(), Encoding::Gzip, Level::Fastest)
+        .await
+        .unwrap();
+    let response = request("www.example.com").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(response.into_bytes().await.unwrap(), fastest);
+
+    for host in ["localhost:9000", "example.org"] {
+        let response = request(host).await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"), "{}", host);
+        assert_ne!(response.into_bytes().await.unwrap(), fastest, "{}", host);
+    }
+}
+
+#[cfg(feature = "regex")]
+#[rocket::get("/patterns/<_path..>")]
+fn patterns(_path: std::path::PathBuf) -> String {
+    text(2048)
+}
+
+#[cfg(feature = "regex")]
+#[rocket::async_test]
+async fn paths_matching_excluded_patterns_are_left_uncompressed() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![patterns])
+        .attach(
+            Compression::fairing()
+                .included_path_prefixes(vec!["/patterns/".to_owned()])
+                .exclude_path_patterns(vec![
+                    regex::Regex::new("^/patterns/stream/").unwrap(),
+                    regex::Regex::new(r"\.bin$").unwrap(),
+                ]),
+        );
+    let client = client(rocket).await;
+
+    for (path, compressed) in [
+        ("/patterns/stream/live", false),
+        ("/patterns/data.bin", false),
+        ("/patterns/streams", true),
+        ("/patterns/data.bin.txt", true),
+    ] {
+        let response = get(&client, path, "gzip").await;
+        let expected = if compressed { Some("gzip") } else { None };
+        assert_eq!(encoding(&response).as_deref(), expected, "{}", path);
+        assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+    }
+}
+
+#[rocket::get("/networks")]
+fn networks() -> String {
+    text(2048)
+}
+
+#[rocket::async_test]
+async fn clients_in_skipped_networks_get_uncompressed_responses() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![networks])
+        .attach(Compression::fairing().skip_client_networks(vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "fd00::/8".parse().unwrap(),
+            "192.168.1.7".parse().unwrap(),
+        ]));
+    let client = client(rocket).await;
+
+    for (remote, compressed) in [
+        ("10.1.2.3:5000", false),
+        ("[fd12::1]:5000", false),
+        ("192.168.1.7:80", false),
+        ("192.168.1.8:80", true),
+        ("11.0.0.1:5000", true),
+        ("[2001:db8::1]:5000", true),
+    ] {
+        let response = client
+            .get("/networks")
+            .header(Header::new("Accept-Encoding", "gzip"))
+            .remote(remote.parse().unwrap())
+            .dispatch()
+            .await;
+        let expected = if compressed { Some("gzip") } else { None };
+        assert_eq!(encoding(&response).as_deref(), expected, "{}", remote);
+    }
+
+    // The forwarded address takes precedence over the peer's.
+    let response = client
+        .get("/networks")
+        .header(Header::new("Accept-Encoding", "gzip"))
+        .header(Header::new("X-Real-IP", "10.9.9.9"))
+        .remote("11.0.0.1:5000".parse().unwrap())
+        .dispatch()
+        .await;
+    assert_eq!(encoding(&response), None);
+}
+
+#[rocket::get("/redirect/<status>")]
+fn redirect(status: u16) -> (Status, String) {
+    (Status::new(status), text(4096))
+}
+
+#[rocket::async_test]
+async fn redirection_bodies_are_left_uncompressed() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![redirect])
+        .attach(CachedCompression::exact_path_fairing(vec![
+            "/redirect/301".to_owned()
+        ]))
+        .attach(Compression::fairing());
+    let client = client(rocket).await;
+
+    for status in [301, 302, 308] {
+        let uri = format!("/redirect/{}", status);
+        let response = get(&client, &uri, "gzip").await;
+        assert_eq!(response.status().code, status);
+        assert_eq!(encoding(&response), None);
+        assert_eq!(response.into_string().await.unwrap(), text(4096));
+    }
+
+    let response = get(&client, "/redirect/200", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+}
+
+#[derive(rocket::Responder)]
+struct WithCacheControl {
+    body: String,
+    cache_control: Header<'static>,
+}
+
+#[rocket::get("/proxied/<cache_control>")]
+fn proxied(cache_control: &str) -> WithCacheControl {
+    WithCacheControl {
+        body: text(2048),
+        cache_control: Header::new("Cache-Control", cache_control.to_owned()),
+    }
+}
+
+/// Returns the `Content-Encoding` of `path` requested through a proxy if
+/// `via` is set, with `policy`.
+async fn proxied_encoding(policy: ProxiedPolicy, path: &str, via: bool) -> Option<String> {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![proxied])
+        .attach(Compression::fairing().proxied(policy));
+    let client = client(rocket).await;
+    let mut request = client
+        .get(path)
+        .header(Header::new("Accept-Encoding", "gzip"));
+    if via {
+        request = request.header(Header::new("Via", "1.1 proxy.example"));
+    }
+    let response = request.dispatch().await;
+    encoding(&response)
+}
+
+#[rocket::async_test]
+async fn proxied_requests_follow_the_policy() {
+    let cases = [
+        (ProxiedPolicy::Any, "/proxied/max-age=60", true),
+        (ProxiedPolicy::Off, "/proxied/max-age=60", false),
+        (ProxiedPolicy::Off, "/proxied/private", false),
+        (ProxiedPolicy::Uncacheable, "/proxied/max-age=60", false),
+        (ProxiedPolicy::Uncacheable, "/proxied/private", true),
+        (ProxiedPolicy::Uncacheable, "/proxied/No-Store", true),
+    ];
+    for (policy, path, compressed) in cases {
+        let expected = compressed.then(|| "gzip".to_owned());
+        assert_eq!(
+            proxied_encoding(policy, path, true).await,
+            expected,
+            "{:?} {}",
+            policy,
+            path
+        );
+        // Direct requests are compressed whatever the policy.
+        assert_eq!(
+            proxied_encoding(policy, path, false).await.as_deref(),
+            Some("gzip")
+        );
+    }
+}
+
+#[rocket::get("/healthz/<_..>")]
+fn healthz() -> String {
+    text(2048)
+}
+
+#[rocket::async_test]
+async fn always_skipped_paths_are_never_compressed() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![healthz])
+        .attach(
+            CachedCompression::path_prefix_fairing(vec!["/healthz/cached".to_owned()])
+                .always_skip_paths(vec!["/healthz/cached".to_owned()]),
+        )
+        .attach(
+            Compression::fairing()
+                .always_skip_paths(vec!["/healthz".to_owned(), "/healthz/cached".to_owned()]),
+        );
+    let client = client(rocket).await;
+
+    for path in ["/healthz", "/healthz/cached"] {
+        let response = get(&client, path, "gzip").await;
+        assert_eq!(encoding(&response), None, "{}", path);
+        assert_eq!(response.into_string().await.unwrap(), text(2048));
+    }
+
+    // Only exact paths are skipped.
+    for path in ["/healthz/live", "/healthz/cached/app.js"] {
+        let response = get(&client, path, "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"), "{}", path);
+    }
+}
+
+#[rocket::get("/skip-untyped/<_>")]
+fn skip_untyped() -> Untyped {
+    Untyped(text(2048).into_bytes())
+}
+
+#[rocket::get("/skip-untyped/typed/<_>")]
+fn skip_untyped_typed() -> String {
+    text(2048)
+}
+
+/// Returns the `Content-Encoding` of `path` with `skip_untyped` set to `skip`
+/// on both fairings, the cached one caching `/skip-untyped/cached`.
+async fn skip_untyped_encoding(skip: bool, path: &str) -> Option<String> {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![skip_untyped, skip_untyped_typed])
+        .attach(
+            CachedCompression::exact_path_fairing(vec![
+                "/skip-untyped/cached".to_owned(),
+                "/skip-untyped/typed/cached".to_owned(),
+            ])
+            .skip_untyped(skip),
+        )
+        .attach(
+            Compression::fairing()
+                .no_content_type(NoContentTypePolicy::Compress)
+                .skip_untyped(skip),
+        );
+    let client = client(rocket).await;
+    let response = get(&client, path, "gzip").await;
+    let encoding = encoding(&response);
+    assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+    encoding
+}
+
+#[rocket::async_test]
+async fn skip_untyped_leaves_untyped_responses_uncompressed() {
+    for path in ["/skip-untyped/dynamic", "/skip-untyped/cached"] {
+        assert_eq!(skip_untyped_encoding(true, path).await, None, "{}", path);
+        assert_eq!(
+            skip_untyped_encoding(false, path).await.as_deref(),
+            Some("gzip"),
+            "{}",
+            path
+        );
+    }
+    for path in ["/skip-untyped/typed/dynamic", "/skip-untyped/typed/cached"] {
+        assert_eq!(
+            skip_untyped_encoding(true, path).await.as_deref(),
+            Some("gzip"),
+            "{}",
+            path
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use rocket::tokio::io::AsyncReadExt;
+use rocket_async_compression::{util, CountingReader, Encoding, Level};
+
+#[rocket::async_test]
+async fn counts_every_byte_read() {
+    let count = Arc::new(AtomicU64::new(0));
+    let mut reader = CountingReader::new(&[7u8; 10_000][..], count.clone());
+
+    let mut chunk = [0; 3];
+    reader.read_exact(&mut chunk).await.unwrap();
+    assert_eq!(reader.count(), 3);
+
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).await.unwrap();
+    assert_eq!(rest.len(), 9_997);
+    assert_eq!(count.load(Ordering::Relaxed), 10_000);
+}
+
+#[rocket::async_test]
+async fn readers_sharing_a_counter_add_up() {
+    let count = Arc::new(AtomicU64::new(0));
+    for len in [0, 1, 8192, 100_000] {
+        let body = vec![1u8; len];
+        let mut reader = CountingReader::new(&body[..], count.clone());
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).await.unwrap();
+        assert_eq!(reader.into_inner().len(), 0);
+    }
+    assert_eq!(count.load(Ordering::Relaxed), 108_193);
+}
+
+#[rocket::async_test]
+async fn counts_compressed_bytes_of_an_encoder() {
+    let compressed = util::compress(&[b'a'; 50_000], Encoding::Gzip, Level::Default)
+        .await
+        .unwrap();
+    let count = Arc::new(AtomicU64::new(0));
+    let mut reader = CountingReader::new(&compressed[..], count.clone());
+    let mut read = Vec::new();
+    reader.read_to_end(&mut read).await.unwrap();
+    assert_eq!(count.load(Ordering::Relaxed), compressed.len() as u64);
+}
@@ -0,0 +1,92 @@
+#![cfg(feature = "digest")]
+
+mod common;
+
+use common::{client, get, text};
+use rocket_async_compression::{CachedCompression, DigestAlgorithm};
+use sha2::{Digest, Sha256, Sha512};
+
+/// Encodes `bytes` in padded standard base64.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut bits = String::new();
+    for byte in bytes {
+        bits.push_str(&format!("{:08b}", byte));
+    }
+    let mut encoded: String = bits
+        .as_bytes()
+        .chunks(6)
+        .map(|sextet| {
+            let sextet = format!("{:0<6}", std::str::from_utf8(sextet).unwrap());
+            ALPHABET[usize::from_str_radix(&sextet, 2).unwrap()] as char
+        })
+        .collect();
+    while !encoded.len().is_multiple_of(4) {
+        encoded.push('=');
+    }
+    encoded
+}
+
+#[rocket::get("/digest/<file>")]
+fn digest(file: &str) -> String {
+    text(4096 + file.len())
+}
+
+#[rocket::async_test]
+async fn digests_cover_the_compressed_body() {
+    let rocket = rocket::build().mount("/", rocket::routes![digest]).attach(
+        CachedCompression::exact_path_fairing(vec!["/digest/sha256".to_owned()])
+            .digest(DigestAlgorithm::Sha256),
+    );
+    let client = client(rocket).await;
+
+    let mut fields = Vec::new();
+    for accept in ["gzip", "gzip", "br"] {
+        let response = get(&client, "/digest/sha256", accept).await;
+        let repr = response
+            .headers()
+            .get_one("Repr-Digest")
+            .unwrap()
+            .to_owned();
+        let content = response
+            .headers()
+            .get_one("Content-Digest")
+            .unwrap()
+            .to_owned();
+        let body = response.into_bytes().await.unwrap();
+        let expected = format!("sha-256=:{}:", base64(&Sha256::digest(&body)));
+        assert_eq!(repr, expected);
+        assert_eq!(content, expected);
+        fields.push(repr);
+    }
+    // Hits have the digest of the miss; other encodings have their own.
+    assert_eq!(fields[0], fields[1]);
+    assert_ne!(fields[0], fields[2]);
+}
+
+#[rocket::async_test]
+async fn sha512_digests_and_identity_responses() {
+    let rocket = rocket::build().mount("/", rocket::routes![digest]).attach(
+        CachedCompression::exact_path_fairing(vec!["/digest/sha512".to_owned()])
+            .digest(DigestAlgorithm::Sha512),
+    );
+    let client = client(rocket).await;
+
+    let response = get(&client, "/digest/sha512", "gzip").await;
+    let field = response
+        .headers()
+        .get_one("Content-Digest")
+        .unwrap()
+        .to_owned();
+    let body = response.into_bytes().await.unwrap();
+    assert_eq!(
+        field,
+        format!("sha-512=:{}:", base64(&Sha512::digest(&body)))
+    );
+
+    // Uncompressed responses get no digest.
+    let response = get(&client, "/digest/sha512", "identity").await;
+    assert_eq!(response.headers().get_one("Content-Digest"), None);
+    assert_eq!(response.headers().get_one("Repr-Digest"), None);
+}
@@ -0,0 +1,45 @@
+use rocket_async_compression::Encoding;
+
+fn parse(coding: &str) -> Encoding {
+    coding.parse().unwrap()
+}
+
+#[test]
+fn codings_parse_case_insensitively() {
+    assert_eq!(parse("GZIP"), Encoding::Gzip);
+    assert_eq!(parse("Br"), Encoding::Brotli);
+    assert_eq!(parse("zStd"), Encoding::Zstd);
+    assert_eq!(parse("Identity"), Encoding::Identity);
+}
+
+#[test]
+fn legacy_aliases_parse_as_their_coding() {
+    assert_eq!(parse("x-gzip"), Encoding::Gzip);
+    assert_eq!(parse("X-GZIP"), Encoding::Gzip);
+    assert_eq!(parse("x-compress"), Encoding::Compress);
+}
+
+#[test]
+fn unknown_codings_are_lowercased() {
+    assert_eq!(
+        parse("X-Custom"),
+        Encoding::EncodingExt("x-custom".to_owned())
+    );
+    assert_eq!(parse("X-Custom"), parse("x-custom"));
+}
+
+#[test]
+fn codings_round_trip_through_display() {
+    for coding in [
+        "chunked", "br", "gzip", "zstd", "deflate", "compress", "identity", "trailers", "x-custom",
+    ] {
+        assert_eq!(parse(coding).to_string(), coding);
+        assert_eq!(parse(&parse(coding).to_string()), parse(coding));
+    }
+}
+
+#[test]
+fn aliases_display_as_their_canonical_name() {
+    assert_eq!(parse("x-gzip").to_string(), "gzip");
+    assert_eq!(parse("X-Compress").to_string(), "compress");
+}
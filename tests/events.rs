@@ -0,0 +1,98 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{client, decoded_body, get, text, Streamed};
+use rocket::http::ContentType;
+use rocket_async_compression::{util, CachedCompression, Compression, CompressionEvent, Encoding};
+
+/// Returns a hook pushing events into the returned `Vec`.
+fn capture() -> (
+    Arc<Mutex<Vec<CompressionEvent>>>,
+    impl Fn(CompressionEvent) + Send + Sync + 'static,
+) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+    (events, move |event| sink.lock().unwrap().push(event))
+}
+
+#[rocket::get("/sized")]
+fn sized() -> String {
+    text(4096)
+}
+
+#[rocket::get("/streamed")]
+fn streamed() -> Streamed {
+    Streamed(ContentType::Plain, text(4096).into_bytes())
+}
+
+#[rocket::async_test]
+async fn compression_reports_sized_and_streamed_responses() {
+    let (events, hook) = capture();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![sized, streamed])
+        .attach(Compression::fairing().on_compressed(hook));
+    let client = client(rocket).await;
+
+    let body = get(&client, "/sized", "gzip")
+        .await
+        .into_bytes()
+        .await
+        .unwrap();
+    let decoded = util::decompress(&body, Encoding::Gzip).await.unwrap();
+    assert_eq!(decoded, text(4096).into_bytes());
+    {
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.path, "/sized");
+        assert_eq!(event.encoding, Encoding::Gzip);
+        assert_eq!(event.original_size, Some(4096));
+        assert_eq!(event.compressed_size, body.len() as u64);
+        assert!(!event.cache_hit);
+    }
+
+    // Streamed responses are reported once their body has been read.
+    let response = get(&client, "/streamed", "br").await;
+    assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 2);
+    let event = &events[1];
+    assert_eq!(event.path, "/streamed");
+    assert_eq!(event.encoding, Encoding::Brotli);
+    assert!(event.compressed_size > 0 && event.compressed_size < 4096);
+    assert!(!event.cache_hit);
+}
+
+#[rocket::get("/cached-events")]
+fn cached_events() -> String {
+    text(4096)
+}
+
+#[rocket::async_test]
+async fn cached_compression_reports_misses_and_hits() {
+    let (events, hook) = capture();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![cached_events])
+        .attach(
+            CachedCompression::exact_path_fairing(vec!["/cached-events".to_owned()])
+                .on_compressed(hook),
+        );
+    let client = client(rocket).await;
+
+    for _ in 0..2 {
+        let response = get(&client, "/cached-events", "gzip").await;
+        assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+    }
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 2);
+    let (miss, hit) = (&events[0], &events[1]);
+    assert_eq!(miss.path, "/cached-events");
+    assert_eq!(miss.encoding, Encoding::Gzip);
+    assert_eq!(miss.original_size, Some(4096));
+    assert!(!miss.cache_hit);
+    assert!(hit.cache_hit);
+    assert_eq!(hit.original_size, None);
+    assert_eq!(hit.compressed_size, miss.compressed_size);
+}
@@ -0,0 +1,59 @@
+//! Run with `--no-default-features --features gzip` or `--features brotli` to
+//! check that compiled-out codings are never negotiated.
+#![cfg(not(all(feature = "gzip", feature = "brotli")))]
+
+mod common;
+
+use common::{client, decoded_body, encoding, get, text};
+use rocket_async_compression::{CachedCompression, Compression};
+
+#[rocket::get("/dynamic")]
+fn dynamic() -> String {
+    text(4096)
+}
+
+#[rocket::get("/cached")]
+fn cached() -> String {
+    text(4096)
+}
+
+/// Returns the encoding of the responses to clients preferring `disabled` but
+/// also accepting `enabled`, for both fairings.
+async fn negotiated(disabled: &str, enabled: &str) -> Vec<Option<String>> {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![dynamic, cached])
+        .attach(Compression::fairing())
+        .attach(CachedCompression::exact_path_fairing(vec![
+            "/cached".to_owned()
+        ]));
+    let client = client(rocket).await;
+
+    let mut encodings = Vec::new();
+    for path in ["/dynamic", "/cached", "/cached"] {
+        let response = get(&client, path, disabled).await;
+        assert_eq!(encoding(&response), None);
+
+        let accept = format!("{}, {};q=0.5", disabled, enabled);
+        let response = get(&client, path, &accept).await;
+        encodings.push(encoding(&response));
+        assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+    }
+    encodings
+}
+
+#[cfg(not(feature = "brotli"))]
+#[rocket::async_test]
+async fn brotli_is_never_negotiated_without_its_feature() {
+    let gzip = Some("gzip".to_owned());
+    assert_eq!(
+        negotiated("br", "gzip").await,
+        [gzip.clone(), gzip.clone(), gzip]
+    );
+}
+
+#[cfg(not(feature = "gzip"))]
+#[rocket::async_test]
+async fn gzip_is_never_negotiated_without_its_feature() {
+    let br = Some("br".to_owned());
+    assert_eq!(negotiated("gzip", "br").await, [br.clone(), br.clone(), br]);
+}
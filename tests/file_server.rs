@@ -0,0 +1,135 @@
+mod common;
+
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use common::{client, decoded_body, encoding, get, temp_dir, text, Recorder};
+use rocket::fs::FileServer;
+use rocket_async_compression::{
+    CachedCompression, CompressedFileServer, Encoding, Level, MissingSiblings,
+};
+
+fn set_modified(path: &PathBuf, modified: SystemTime) {
+    std::fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+}
+
+#[rocket::async_test]
+async fn missing_siblings_are_written_to_disk() {
+    let dir = temp_dir("write");
+    std::fs::write(dir.join("app.js"), text(4096)).unwrap();
+    let rocket = rocket::build().mount(
+        "/",
+        CompressedFileServer::from(&dir).missing_siblings(MissingSiblings::WriteToDisk),
+    );
+    let client = client(rocket).await;
+
+    let response = get(&client, "/app.js", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+
+    let sibling = std::fs::read(dir.join("app.js.gz")).unwrap();
+    let decoded = rocket_async_compression::util::decompress(&sibling, Encoding::Gzip)
+        .await
+        .unwrap();
+    assert_eq!(decoded, text(4096).into_bytes());
+    let leftovers = std::fs::read_dir(&dir)
+        .unwrap()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .ends_with(".tmp")
+        })
+        .count();
+    assert_eq!(leftovers, 0);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[rocket::async_test]
+async fn stale_siblings_are_regenerated() {
+    let dir = temp_dir("stale");
+    let original = dir.join("app.js");
+    let sibling = dir.join("app.js.gz");
+    std::fs::write(&original, text(4096)).unwrap();
+    std::fs::write(
+        &sibling,
+        rocket_async_compression::util::compress(b"stale", Encoding::Gzip, Level::Default)
+            .await
+            .unwrap(),
+    )
+    .unwrap();
+    let now = SystemTime::now();
+    set_modified(&sibling, now - Duration::from_secs(60));
+    set_modified(&original, now);
+
+    let rocket = rocket::build().mount(
+        "/",
+        CompressedFileServer::from(&dir).missing_siblings(MissingSiblings::WriteToDisk),
+    );
+    let client = client(rocket).await;
+
+    let response = get(&client, "/app.js", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[rocket::async_test]
+async fn directories_are_served_through_their_index() {
+    let dir = temp_dir("index");
+    std::fs::create_dir(dir.join("docs")).unwrap();
+    std::fs::write(dir.join("docs").join("index.html"), text(2048)).unwrap();
+    let client = client(rocket::build().mount("/", CompressedFileServer::from(&dir))).await;
+
+    let response = get(&client, "/docs", "identity").await;
+    assert_eq!(response.into_string().await.unwrap(), text(2048));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[rocket::async_test]
+async fn static_dir_files_are_cached_as_served() {
+    let dir = temp_dir("static-dir");
+    std::fs::create_dir(dir.join("css")).unwrap();
+    std::fs::write(dir.join("app.js"), text(4096)).unwrap();
+    std::fs::write(dir.join("css").join("site.css"), text(4097)).unwrap();
+    std::fs::write(dir.join("logo.png"), text(4098)).unwrap();
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/static-dir", FileServer::from(&dir))
+        .attach(CachedCompression::from_static_dir("/static-dir/", &dir).unwrap())
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    for (path, len) in [
+        ("/static-dir/app.js", 4096),
+        ("/static-dir/css/site.css", 4097),
+    ] {
+        for hit in [false, true] {
+            let response = get(&client, path, "gzip").await;
+            assert_eq!(encoding(&response).as_deref(), Some("gzip"), "{}", path);
+            assert_eq!(decoded_body(response).await, text(len).into_bytes());
+            assert_eq!(recorder.last().unwrap().cache_hit, Some(hit));
+        }
+    }
+
+    // Incompressible files and files added later aren't cached.
+    std::fs::write(dir.join("late.js"), text(4099)).unwrap();
+    for path in ["/static-dir/logo.png", "/static-dir/late.js"] {
+        let response = get(&client, path, "gzip").await;
+        assert_eq!(encoding(&response), None, "{}", path);
+    }
+
+    assert!(CachedCompression::from_static_dir("/", dir.join("missing")).is_err());
+}
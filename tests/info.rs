@@ -0,0 +1,92 @@
+mod common;
+
+use common::{client, get, text, Recorder, Streamed};
+use rocket::http::ContentType;
+use rocket_async_compression::{CachedCompression, Compression, Encoding, SkipReason};
+
+#[rocket::get("/sized")]
+fn sized() -> String {
+    text(4096)
+}
+
+#[rocket::get("/streamed")]
+fn streamed() -> Streamed {
+    Streamed(ContentType::Plain, text(4096).into_bytes())
+}
+
+#[rocket::get("/png")]
+fn png() -> (ContentType, String) {
+    (ContentType::PNG, text(4096))
+}
+
+#[rocket::get("/empty")]
+fn empty() {}
+
+#[rocket::async_test]
+async fn follow_up_fairings_read_what_was_done() {
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![sized, streamed, png, empty])
+        .attach(Compression::fairing())
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    get(&client, "/sized", "gzip").await;
+    let info = recorder.last().unwrap();
+    assert_eq!(info.encoding, Some(Encoding::Gzip));
+    assert!(info.level.is_some());
+    assert_eq!(info.original_size, Some(4096));
+    assert_eq!(info.cache_hit, None);
+    assert_eq!(info.skip_reason, None);
+
+    get(&client, "/streamed", "br").await;
+    let info = recorder.last().unwrap();
+    assert_eq!(info.encoding, Some(Encoding::Brotli));
+    assert_eq!(info.compressed_size, None);
+
+    get(&client, "/sized", "compress").await;
+    let info = recorder.last().unwrap();
+    assert_eq!(info.encoding, None);
+    assert!(info.level.is_none());
+    assert_eq!(info.skip_reason, Some(SkipReason::NotAccepted));
+
+    get(&client, "/png", "gzip").await;
+    assert_eq!(
+        recorder.last().unwrap().skip_reason,
+        Some(SkipReason::ExcludedType)
+    );
+
+    // Responses never considered for compression have no info.
+    get(&client, "/empty", "gzip").await;
+    assert!(recorder.last().is_none());
+    assert_eq!(recorder.infos().len(), 5);
+}
+
+#[rocket::get("/cached")]
+fn cached() -> String {
+    text(4096)
+}
+
+#[rocket::async_test]
+async fn cached_responses_report_their_compressed_size() {
+    let recorder = Recorder::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![cached])
+        .attach(CachedCompression::exact_path_fairing(vec![
+            "/cached".to_owned()
+        ]))
+        .attach(recorder.clone());
+    let client = client(rocket).await;
+
+    for hit in [false, true] {
+        let body = get(&client, "/cached", "gzip")
+            .await
+            .into_bytes()
+            .await
+            .unwrap();
+        let info = recorder.last().unwrap();
+        assert_eq!(info.encoding, Some(Encoding::Gzip));
+        assert_eq!(info.compressed_size, Some(body.len() as u64));
+        assert_eq!(info.cache_hit, Some(hit));
+    }
+}
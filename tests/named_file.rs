@@ -0,0 +1,120 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::{client, decoded_body, encoding, get, temp_dir, text, BodySizes};
+use rocket::{fs::NamedFile, State};
+use rocket_async_compression::{
+    util, CachedCompression, Compression, Encoding, Level, NamedCompressedFile,
+};
+
+struct Root(PathBuf);
+
+#[rocket::get("/<file>")]
+async fn file(root: &State<Root>, file: &str) -> Option<NamedCompressedFile> {
+    NamedCompressedFile::open(root.0.join(file)).await.ok()
+}
+
+/// Writes `app.js` with the siblings in `siblings`, each holding a body naming
+/// its encoding so the test can tell which file was served.
+async fn tree(siblings: &[Encoding]) -> PathBuf {
+    let dir = temp_dir("named-file");
+    std::fs::write(dir.join("app.js"), "original").unwrap();
+    for encoding in siblings {
+        let (extension, body) = match encoding {
+            Encoding::Brotli => ("br", "from br"),
+            _ => ("gz", "from gz"),
+        };
+        let compressed = util::compress(body.as_bytes(), encoding.clone(), Level::Default)
+            .await
+            .unwrap();
+        std::fs::write(dir.join(format!("app.js.{}", extension)), compressed).unwrap();
+    }
+    dir
+}
+
+#[rocket::async_test]
+async fn the_best_existing_sibling_is_served() {
+    // (siblings, Accept-Encoding, expected Content-Encoding, expected body)
+    let cases: &[(&[Encoding], &str, Option<&str>, &str)] = &[
+        (
+            &[Encoding::Brotli, Encoding::Gzip],
+            "br, gzip",
+            Some("br"),
+            "from br",
+        ),
+        (
+            &[Encoding::Brotli, Encoding::Gzip],
+            "gzip",
+            Some("gzip"),
+            "from gz",
+        ),
+        (
+            &[Encoding::Brotli, Encoding::Gzip],
+            "identity",
+            None,
+            "original",
+        ),
+        (&[Encoding::Gzip], "br, gzip", Some("gzip"), "from gz"),
+        (&[Encoding::Gzip], "br", None, "original"),
+        (&[Encoding::Brotli], "br, gzip", Some("br"), "from br"),
+        (&[], "br, gzip", None, "original"),
+    ];
+
+    for (siblings, accept_encoding, expected_encoding, expected_body) in cases {
+        let dir = tree(siblings).await;
+        let rocket = rocket::build()
+            .mount("/", rocket::routes![file])
+            .manage(Root(dir.clone()));
+        let client = client(rocket).await;
+
+        let response = get(&client, "/app.js", accept_encoding).await;
+        assert_eq!(encoding(&response).as_deref(), *expected_encoding);
+        assert_eq!(
+            response.headers().get_one("Content-Type"),
+            Some("text/javascript")
+        );
+        assert_eq!(response.headers().get_one("Vary"), Some("Accept-Encoding"));
+        assert_eq!(decoded_body(response).await, expected_body.as_bytes());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[rocket::get("/plain/<file>")]
+async fn plain(root: &State<Root>, file: &str) -> Option<NamedFile> {
+    NamedFile::open(root.0.join(file)).await.ok()
+}
+
+#[rocket::async_test]
+async fn named_files_are_compressed_by_both_fairings() {
+    let dir = temp_dir("plain-named-file");
+    for name in ["cached.js", "dynamic.js"] {
+        std::fs::write(dir.join(name), text(8192)).unwrap();
+    }
+    let sizes = BodySizes::default();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![plain])
+        .manage(Root(dir))
+        .attach(Compression::fairing())
+        .attach(CachedCompression::exact_path_fairing(vec![
+            "/plain/cached.js".to_owned(),
+        ]))
+        .attach(sizes.clone());
+    let client = client(rocket).await;
+
+    for path in ["/plain/dynamic.js", "/plain/cached.js", "/plain/cached.js"] {
+        for accept in ["gzip", "br"] {
+            let response = get(&client, path, accept).await;
+            assert_eq!(encoding(&response).as_deref(), Some(accept), "{}", path);
+            let body = response.into_bytes().await.unwrap();
+            let decoded = util::decompress(&body, accept.parse().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(decoded, text(8192).into_bytes(), "{}", path);
+            if path == "/plain/cached.js" {
+                assert_eq!(sizes.last(), Some(body.len()), "{}", path);
+            }
+        }
+    }
+}
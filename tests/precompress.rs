@@ -0,0 +1,77 @@
+mod common;
+
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use common::{temp_dir, text};
+use rocket_async_compression::{
+    precompress_dir, util, Encoding, Level, PrecompressOptions, PrecompressSummary,
+};
+
+async fn assert_sibling(path: &Path, encoding: Encoding, expected: &str) {
+    let compressed = std::fs::read(path).unwrap();
+    let decoded = util::decompress(&compressed, encoding).await.unwrap();
+    assert_eq!(decoded, expected.as_bytes(), "{}", path.display());
+}
+
+#[rocket::async_test]
+async fn siblings_are_written_once_and_refreshed_when_stale() {
+    let dir = temp_dir("precompress");
+    std::fs::create_dir(dir.join("nested")).unwrap();
+    std::fs::write(dir.join("app.js"), text(4096)).unwrap();
+    std::fs::write(dir.join("nested").join("style.css"), text(2048)).unwrap();
+    std::fs::write(dir.join("image.png"), text(2048)).unwrap();
+    std::fs::write(dir.join("data.unknown-extension"), text(2048)).unwrap();
+    let options = PrecompressOptions {
+        gzip: Some(Level::Default),
+        brotli: Some(Level::Default),
+    };
+
+    let summary = precompress_dir(&dir, options).await.unwrap();
+    assert_eq!(summary.files_processed, 2);
+    assert_eq!(summary.siblings_written, 4);
+    assert!(summary.bytes_saved > 0);
+    assert_sibling(&dir.join("app.js.gz"), Encoding::Gzip, &text(4096)).await;
+    assert_sibling(&dir.join("app.js.br"), Encoding::Brotli, &text(4096)).await;
+    let style = dir.join("nested").join("style.css.br");
+    assert_sibling(&style, Encoding::Brotli, &text(2048)).await;
+    assert!(!dir.join("image.png.gz").exists());
+    assert!(!dir.join("data.unknown-extension.gz").exists());
+
+    // Fresh siblings are left alone.
+    let summary = precompress_dir(&dir, options).await.unwrap();
+    assert_eq!(summary, PrecompressSummary::default());
+
+    // Files changed after their siblings were written are compressed again.
+    std::fs::write(dir.join("app.js"), text(8192)).unwrap();
+    std::fs::File::options()
+        .write(true)
+        .open(dir.join("app.js"))
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(60))
+        .unwrap();
+    let summary = precompress_dir(&dir, options).await.unwrap();
+    assert_eq!(summary.files_processed, 1);
+    assert_eq!(summary.siblings_written, 2);
+    assert_sibling(&dir.join("app.js.gz"), Encoding::Gzip, &text(8192)).await;
+
+    // Only the requested encodings are written.
+    let only_gzip = temp_dir("precompress-gzip");
+    std::fs::write(only_gzip.join("app.js"), text(4096)).unwrap();
+    let summary = precompress_dir(
+        &only_gzip,
+        PrecompressOptions {
+            brotli: None,
+            ..options
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(summary.siblings_written, 1);
+    assert!(!only_gzip.join("app.js.br").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    std::fs::remove_dir_all(&only_gzip).unwrap();
+}
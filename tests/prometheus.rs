@@ -0,0 +1,59 @@
+#![cfg(feature = "prometheus")]
+
+mod common;
+
+use common::{client, decoded_body, get, text};
+use prometheus::{Encoder, Registry, TextEncoder};
+use rocket_async_compression::{CachedCompression, Compression, PrometheusMetrics};
+
+/// Returns the value of the sample `name` in the exposition text of `registry`.
+fn sample(registry: &Registry, name: &str) -> f64 {
+    let mut text = Vec::new();
+    TextEncoder::new()
+        .encode(&registry.gather(), &mut text)
+        .unwrap();
+    String::from_utf8(text)
+        .unwrap()
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .map_or(0.0, |value| value.parse().unwrap())
+}
+
+#[rocket::get("/dynamic")]
+fn dynamic() -> String {
+    text(4096)
+}
+
+#[rocket::get("/cached")]
+fn cached() -> String {
+    text(4096)
+}
+
+#[rocket::async_test]
+async fn fairings_update_the_registered_metrics() {
+    let registry = Registry::new();
+    PrometheusMetrics::global().register(&registry).unwrap();
+
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![dynamic, cached])
+        .attach(Compression::fairing())
+        .attach(CachedCompression::exact_path_fairing(vec![
+            "/cached".to_owned()
+        ]));
+    let client = client(rocket).await;
+
+    let response = get(&client, "/dynamic", "gzip").await;
+    assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+    for _ in 0..2 {
+        let response = get(&client, "/cached", "br").await;
+        assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+    }
+
+    assert!(sample(&registry, r#"compression_responses_total{encoding="gzip"}"#) >= 1.0);
+    assert!(sample(&registry, r#"compression_responses_total{encoding="br"}"#) >= 1.0);
+    assert!(sample(&registry, "compression_bytes_in_total") >= 8192.0);
+    assert!(sample(&registry, "compression_bytes_out_total") > 0.0);
+    assert!(sample(&registry, "compression_cache_misses_total") >= 1.0);
+    assert!(sample(&registry, "compression_cache_hits_total") >= 1.0);
+    assert!(sample(&registry, "compression_duration_seconds_count") >= 2.0);
+}
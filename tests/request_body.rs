@@ -0,0 +1,255 @@
+use rocket::{
+    data::ToByteUnit,
+    http::{Header, Status},
+    local::asynchronous::Client,
+    response::status::Custom,
+    tokio::io::AsyncReadExt,
+    Build, Rocket,
+};
+use rocket_async_compression::{
+    util, CompressedBody, Decompressed, DecompressionLimits, Encoding, Level, RequestDecodingError,
+};
+
+#[rocket::post("/echo", data = "<body>")]
+async fn echo(body: CompressedBody<'_>) -> std::io::Result<String> {
+    let mut string = String::new();
+    body.open(1.mebibytes()).read_to_string(&mut string).await?;
+    Ok(string)
+}
+
+/// Reads the decoded body in small chunks and responds with the number of
+/// decoded bytes read, also when the read fails.
+#[rocket::post("/count", data = "<body>")]
+async fn count(body: CompressedBody<'_>) -> Result<String, Custom<String>> {
+    let mut reader = body.open(32.mebibytes());
+    let mut chunk = [0; 4096];
+    let mut read = 0;
+    loop {
+        match reader.read(&mut chunk).await {
+            Ok(0) => return Ok(read.to_string()),
+            Ok(len) => read += len,
+            Err(err) => {
+                let status = match err
+                    .get_ref()
+                    .and_then(|err| err.downcast_ref::<RequestDecodingError>())
+                {
+                    Some(err) => err.status(),
+                    None => Status::InternalServerError,
+                };
+                return Err(Custom(status, read.to_string()));
+            }
+        }
+    }
+}
+
+#[rocket::post("/notes", data = "<note>")]
+fn notes(note: Decompressed<String>) -> String {
+    note.into_inner()
+}
+
+#[cfg(feature = "json")]
+#[derive(rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Thing<'r> {
+    name: &'r str,
+    count: u32,
+}
+
+#[cfg(feature = "json")]
+#[rocket::post("/things", data = "<thing>")]
+fn things(thing: Decompressed<rocket::serde::json::Json<Thing<'_>>>) -> String {
+    format!("{} x{}", thing.name, thing.count)
+}
+
+fn rocket() -> Rocket<Build> {
+    let rocket = rocket::build().mount("/", rocket::routes![echo, count, notes]);
+    #[cfg(feature = "json")]
+    let rocket = rocket.mount("/", rocket::routes![things]);
+    rocket
+}
+
+#[rocket::async_test]
+async fn decodes_encoded_bodies() {
+    let client = Client::tracked(rocket()).await.unwrap();
+    let text = "Hello, world! ".repeat(1000);
+
+    for (encoding, name) in [(Encoding::Gzip, "gzip"), (Encoding::Brotli, "br")] {
+        let compressed = util::compress(text.as_bytes(), encoding, Level::Default)
+            .await
+            .unwrap();
+        let response = client
+            .post("/echo")
+            .header(Header::new("Content-Encoding", name))
+            .body(compressed)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok, "{}", name);
+        assert_eq!(response.into_string().await.unwrap(), text, "{}", name);
+    }
+}
+
+#[rocket::async_test]
+async fn reads_plain_and_identity_bodies_as_is() {
+    let client = Client::tracked(rocket()).await.unwrap();
+
+    let response = client.post("/echo").body("plain").dispatch().await;
+    assert_eq!(response.into_string().await.unwrap(), "plain");
+
+    let response = client
+        .post("/echo")
+        .header(Header::new("Content-Encoding", "identity"))
+        .body("identity")
+        .dispatch()
+        .await;
+    assert_eq!(response.into_string().await.unwrap(), "identity");
+}
+
+#[rocket::async_test]
+async fn rejects_unsupported_and_nested_codings() {
+    let client = Client::tracked(rocket()).await.unwrap();
+
+    for coding in ["compress", "gzip, gzip"] {
+        let response = client
+            .post("/echo")
+            .header(Header::new("Content-Encoding", coding))
+            .body("body")
+            .dispatch()
+            .await;
+        assert_eq!(
+            response.status(),
+            Status::UnsupportedMediaType,
+            "{}",
+            coding
+        );
+    }
+}
+
+/// Posts `body` encoded with gzip to `/count` and returns the status and the
+/// number of decoded bytes the route read.
+async fn post_gzip(client: &Client, body: Vec<u8>) -> (Status, usize) {
+    let response = client
+        .post("/count")
+        .header(Header::new("Content-Encoding", "gzip"))
+        .body(body)
+        .dispatch()
+        .await;
+    let status = response.status();
+    (
+        status,
+        response.into_string().await.unwrap().parse().unwrap(),
+    )
+}
+
+#[rocket::async_test]
+async fn repetitive_bodies_exceeding_the_ratio_are_rejected() {
+    let bomb = util::compress(&vec![0; 16 << 20], Encoding::Gzip, Level::Best)
+        .await
+        .unwrap();
+    assert!(bomb.len() < 64 * 1024);
+
+    let client = Client::tracked(rocket()).await.unwrap();
+    let (status, read) = post_gzip(&client, bomb).await;
+    assert_eq!(status, Status::PayloadTooLarge);
+    // The read stops at the default ratio of 200 rather than decoding the whole body.
+    assert!(read <= 200 * 64 * 1024, "{} bytes read", read);
+
+    // Small bodies may still expand up to the ratio of 1 KiB.
+    let small = util::compress(&[0; 100 * 1024], Encoding::Gzip, Level::Best)
+        .await
+        .unwrap();
+    let (status, read) = post_gzip(&client, small).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(read, 100 * 1024);
+}
+
+#[rocket::async_test]
+async fn bodies_exceeding_the_maximum_size_are_rejected() {
+    let rocket = rocket().manage(DecompressionLimits {
+        max_size: Some(64.kibibytes()),
+        max_ratio: None,
+    });
+    let client = Client::tracked(rocket).await.unwrap();
+
+    let body = util::compress(&[7; 256 * 1024], Encoding::Gzip, Level::Default)
+        .await
+        .unwrap();
+    let (status, read) = post_gzip(&client, body).await;
+    assert_eq!(status, Status::PayloadTooLarge);
+    assert!(read <= 64 * 1024, "{} bytes read", read);
+
+    let body = util::compress(&[7; 64 * 1024], Encoding::Gzip, Level::Default)
+        .await
+        .unwrap();
+    assert_eq!(post_gzip(&client, body).await, (Status::Ok, 64 * 1024));
+}
+
+/// Posts `body` to `path`, gzipped unless `gzip` is `false`.
+async fn post(client: &Client, path: &'static str, body: &str, gzip: bool) -> (Status, String) {
+    let mut request = client.post(path);
+    if gzip {
+        let body = util::compress(body.as_bytes(), Encoding::Gzip, Level::Default)
+            .await
+            .unwrap();
+        request = request
+            .header(Header::new("Content-Encoding", "gzip"))
+            .body(body);
+    } else {
+        request = request.body(body);
+    }
+    let response = request.dispatch().await;
+    (
+        response.status(),
+        response.into_string().await.unwrap_or_default(),
+    )
+}
+
+#[rocket::async_test]
+async fn decompressed_strings_are_decoded_or_passed_through() {
+    let client = Client::tracked(rocket()).await.unwrap();
+
+    let note = "a note ".repeat(500);
+    assert_eq!(
+        post(&client, "/notes", &note, true).await,
+        (Status::Ok, note.clone())
+    );
+    assert_eq!(
+        post(&client, "/notes", &note, false).await,
+        (Status::Ok, note)
+    );
+
+    let response = client
+        .post("/notes")
+        .header(Header::new("Content-Encoding", "compress"))
+        .body("note")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnsupportedMediaType);
+
+    let response = client
+        .post("/notes")
+        .header(Header::new("Content-Encoding", "gzip"))
+        .body("not gzip")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[cfg(feature = "json")]
+#[rocket::async_test]
+async fn decompressed_json_is_decoded_or_passed_through() {
+    let client = Client::tracked(rocket()).await.unwrap();
+
+    let thing = r#"{"name": "widget", "count": 3}"#;
+    for gzip in [true, false] {
+        assert_eq!(
+            post(&client, "/things", thing, gzip).await,
+            (Status::Ok, "widget x3".to_owned())
+        );
+    }
+
+    // The inner guard's errors keep their status.
+    let (status, _) = post(&client, "/things", r#"{"name": "widget"}"#, true).await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    let (status, _) = post(&client, "/things", "{", true).await;
+    assert_eq!(status, Status::BadRequest);
+}
@@ -0,0 +1,306 @@
+mod common;
+
+use common::{client, decoded_body, encoding, get, text};
+use rocket::{
+    http::{ContentType, Header, MediaType, Status},
+    response::status,
+};
+use rocket_async_compression::{
+    util, Compress, Compressed, Compression, CompressionConfig, ConfiguredCompress, Encoding,
+    Level, PrecompressedBytes, UnacceptedEncodingPolicy,
+};
+
+const PNG_MAGIC: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+fn png() -> Vec<u8> {
+    let mut body = PNG_MAGIC.to_vec();
+    body.extend(text(2048).into_bytes());
+    body
+}
+
+#[rocket::get("/png")]
+fn compressed_png() -> Compress<(ContentType, Vec<u8>)> {
+    Compress::default((ContentType::PNG, png()))
+}
+
+#[rocket::get("/text")]
+fn compressed_text() -> Compress<String> {
+    Compress::default(text(2048))
+}
+
+#[rocket::async_test]
+async fn compress_honors_the_default_exclusions() {
+    let rocket = rocket::build().mount("/", rocket::routes![compressed_png, compressed_text]);
+    let client = client(rocket).await;
+
+    let response = get(&client, "/png", "gzip").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.into_bytes().await.unwrap(), png());
+
+    let response = get(&client, "/text", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+}
+
+#[rocket::get("/forced-gzip")]
+fn forced_gzip() -> ConfiguredCompress<String> {
+    Compress::default(text(2048)).with_encoding(Encoding::Gzip)
+}
+
+#[rocket::async_test]
+async fn forced_encodings_are_used_only_when_accepted() {
+    let rocket = rocket::build().mount("/", rocket::routes![forced_gzip, compressed_text]);
+    let client = client(rocket).await;
+
+    let response = get(&client, "/forced-gzip", "br, gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+
+    let response = get(&client, "/forced-gzip", "br").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.into_string().await.unwrap(), text(2048));
+
+    let response = get(&client, "/text", "br, gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("br"));
+    assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+}
+
+#[rocket::get("/sized/<len>")]
+fn with_min_size(len: usize) -> ConfiguredCompress<String> {
+    Compress::default(text(len)).min_size(1024)
+}
+
+#[rocket::async_test]
+async fn bodies_under_the_min_size_are_left_alone() {
+    let client = client(rocket::build().mount("/", rocket::routes![with_min_size])).await;
+
+    let response = get(&client, "/sized/100", "gzip").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.into_string().await.unwrap(), text(100));
+
+    let response = get(&client, "/sized/4096", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+}
+
+#[rocket::get("/pre-gzipped/<policy>")]
+async fn pre_gzipped(policy: &str) -> Compressed<Vec<u8>> {
+    let policy = match policy {
+        "serve" => UnacceptedEncodingPolicy::Serve,
+        "not-acceptable" => UnacceptedEncodingPolicy::NotAcceptable,
+        _ => UnacceptedEncodingPolicy::Decompress,
+    };
+    let body = util::compress(text(2048).as_bytes(), Encoding::Gzip, Level::Default)
+        .await
+        .unwrap();
+    Compressed::gzip(body).unaccepted(policy)
+}
+
+#[rocket::async_test]
+async fn compressed_bodies_follow_the_unaccepted_policy() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![pre_gzipped])
+        .attach(Compression::fairing());
+    let client = client(rocket).await;
+
+    for policy in ["serve", "not-acceptable", "decompress"] {
+        let path = format!("/pre-gzipped/{}", policy);
+        let response = get(&client, &path, "gzip").await;
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+        assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+    }
+
+    let response = get(&client, "/pre-gzipped/serve", "br").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+
+    let response = get(&client, "/pre-gzipped/not-acceptable", "br").await;
+    assert_eq!(response.status(), Status::NotAcceptable);
+
+    let response = get(&client, "/pre-gzipped/decompress", "br").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.into_string().await.unwrap(), text(2048));
+}
+
+#[rocket::async_test]
+async fn compressed_bodies_vary_unless_always_served() {
+    let client = client(rocket::build().mount("/", rocket::routes![pre_gzipped])).await;
+
+    for (policy, accept_encoding, vary) in [
+        ("serve", "br", None),
+        ("not-acceptable", "gzip", Some("Accept-Encoding")),
+        ("not-acceptable", "br", Some("Accept-Encoding")),
+        ("decompress", "gzip", Some("Accept-Encoding")),
+        ("decompress", "br", Some("Accept-Encoding")),
+    ] {
+        let path = format!("/pre-gzipped/{}", policy);
+        let response = get(&client, &path, accept_encoding).await;
+        assert_eq!(
+            response.headers().get_one("Vary"),
+            vary,
+            "{} with {}",
+            policy,
+            accept_encoding
+        );
+    }
+}
+
+async fn representations(identity: bool, gzip: bool, brotli: bool) -> PrecompressedBytes {
+    let original = text(2048).into_bytes();
+    let mut bytes = PrecompressedBytes::new();
+    if identity {
+        bytes = bytes.identity(original.clone());
+    }
+    if gzip {
+        let gzipped = util::compress(&original, Encoding::Gzip, Level::Default);
+        bytes = bytes.gzip(gzipped.await.unwrap());
+    }
+    if brotli {
+        let brotlied = util::compress(&original, Encoding::Brotli, Level::Default);
+        bytes = bytes.brotli(brotlied.await.unwrap());
+    }
+    bytes
+}
+
+#[rocket::get("/precompressed/<identity>/<gzip>/<brotli>")]
+async fn precompressed(identity: bool, gzip: bool, brotli: bool) -> PrecompressedBytes {
+    representations(identity, gzip, brotli).await
+}
+
+#[rocket::async_test]
+async fn precompressed_bytes_pick_the_best_representation() {
+    let client = client(rocket::build().mount("/", rocket::routes![precompressed])).await;
+
+    // (identity, gzip, brotli, Accept-Encoding, expected Content-Encoding)
+    let cases = [
+        (true, true, true, "br, gzip", Some("br")),
+        (true, true, true, "gzip", Some("gzip")),
+        (true, true, true, "identity", None),
+        (true, true, false, "br", None),
+        (true, true, false, "br, gzip", Some("gzip")),
+        (true, false, true, "gzip", None),
+        (false, true, true, "br", Some("br")),
+        (false, true, false, "br", None),
+        (false, false, true, "gzip", None),
+        (false, true, true, "identity", None),
+    ];
+    for (identity, gzip, brotli, accept_encoding, expected) in cases {
+        let path = format!("/precompressed/{}/{}/{}", identity, gzip, brotli);
+        let response = get(&client, &path, accept_encoding).await;
+        assert_eq!(encoding(&response).as_deref(), expected, "{}", path);
+        assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+    }
+}
+
+#[rocket::get("/exclusions/<kind>")]
+fn with_exclusions(kind: &str) -> ConfiguredCompress<(ContentType, Vec<u8>)> {
+    let response = match kind {
+        "png" => (ContentType::PNG, png()),
+        _ => (ContentType::Plain, text(2048).into_bytes()),
+    };
+    Compress::with_exclusions(response, Level::Default, vec![MediaType::PNG])
+}
+
+#[rocket::async_test]
+async fn per_responder_exclusions_are_honored() {
+    let client = client(rocket::build().mount("/", rocket::routes![with_exclusions])).await;
+
+    let response = get(&client, "/exclusions/png", "gzip").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.into_bytes().await.unwrap(), png());
+
+    let response = get(&client, "/exclusions/text", "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+}
+
+#[rocket::get("/managed")]
+fn managed() -> ConfiguredCompress<String> {
+    Compress::managed(text(2048))
+}
+
+/// Returns the `Content-Encoding` of `/managed` for a client accepting brotli
+/// and gzip, with `config` managed.
+async fn managed_encoding(config: Option<CompressionConfig>) -> Option<String> {
+    let rocket = rocket::build().mount("/", rocket::routes![managed]);
+    let rocket = match config {
+        Some(config) => rocket.manage(config),
+        None => rocket,
+    };
+    let client = client(rocket).await;
+    let response = get(&client, "/managed", "br, gzip").await;
+    let encoding = encoding(&response);
+    assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+    encoding
+}
+
+#[rocket::async_test]
+async fn managed_responders_follow_the_managed_config() {
+    assert_eq!(managed_encoding(None).await.as_deref(), Some("br"));
+
+    let gzip_only = CompressionConfig {
+        encodings: Some(vec![Encoding::Gzip]),
+        ..CompressionConfig::default()
+    };
+    assert_eq!(
+        managed_encoding(Some(gzip_only)).await.as_deref(),
+        Some("gzip")
+    );
+
+    let large_only = CompressionConfig {
+        min_size: Some(10_000),
+        ..CompressionConfig::default()
+    };
+    assert_eq!(managed_encoding(Some(large_only)).await, None);
+
+    let no_text = CompressionConfig {
+        excluded_content_types: vec![MediaType::new("text", "*")],
+        ..CompressionConfig::default()
+    };
+    assert_eq!(managed_encoding(Some(no_text)).await, None);
+}
+
+#[rocket::async_test]
+async fn managed_responders_follow_the_fairing_config() {
+    let config = CompressionConfig {
+        encodings: Some(vec![Encoding::Gzip]),
+        ..CompressionConfig::default()
+    };
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![managed])
+        .attach(Compression::from_config(config));
+    let client = client(rocket).await;
+
+    let response = get(&client, "/managed", "br, gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+}
+
+#[rocket::post("/created")]
+fn created() -> Compress<status::Custom<String>> {
+    Compress::default(status::Custom(Status::Created, text(2048)))
+}
+
+#[rocket::post("/accepted")]
+fn accepted() -> status::Custom<Compress<String>> {
+    status::Custom(Status::Accepted, Compress::default(text(2048)))
+}
+
+#[rocket::async_test]
+async fn custom_statuses_are_kept_either_way() {
+    let client = client(rocket::build().mount("/", rocket::routes![created, accepted])).await;
+
+    for (path, status) in [
+        ("/created", Status::Created),
+        ("/accepted", Status::Accepted),
+    ] {
+        let response = client
+            .post(path)
+            .header(Header::new("Accept-Encoding", "gzip"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), status);
+        assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+        assert_eq!(decoded_body(response).await, text(2048).into_bytes());
+    }
+}
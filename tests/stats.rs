@@ -0,0 +1,133 @@
+mod common;
+
+use common::{client, get, text};
+use rocket_async_compression::{Compression, CompressionStats, Encoding, SkipReason};
+
+#[rocket::get("/text")]
+fn plain_text() -> String {
+    text(2048)
+}
+
+fn count(encoding: Encoding) -> u64 {
+    CompressionStats::global()
+        .encoding_counts()
+        .get(&encoding)
+        .copied()
+        .unwrap_or(0)
+}
+
+// The statistics are global and other tests in this file run concurrently, so only lower bounds
+// are asserted.
+#[rocket::async_test]
+async fn encoding_counts_track_each_encoding() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![plain_text])
+        .attach(Compression::fairing());
+    let client = client(rocket).await;
+    let (brotli, gzip) = (count(Encoding::Brotli), count(Encoding::Gzip));
+
+    get(&client, "/text", "br").await.into_bytes().await;
+    get(&client, "/text", "gzip").await.into_bytes().await;
+    get(&client, "/text", "gzip").await.into_bytes().await;
+
+    assert!(count(Encoding::Brotli) > brotli);
+    assert!(count(Encoding::Gzip) >= gzip + 2);
+}
+
+#[cfg(feature = "metrics")]
+#[rocket::get("/metrics-text")]
+fn metrics_text() -> (rocket::http::ContentType, String) {
+    (
+        rocket::http::ContentType::new("text", "x-metrics-test"),
+        text(2048),
+    )
+}
+
+#[cfg(feature = "metrics")]
+#[rocket::async_test]
+async fn sizes_are_totalled_per_content_type() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![metrics_text])
+        .attach(Compression::fairing());
+    let client = client(rocket).await;
+
+    let body = get(&client, "/metrics-text", "gzip")
+        .await
+        .into_bytes()
+        .await
+        .unwrap();
+
+    let sizes = CompressionStats::global().content_type_sizes();
+    let totals = sizes["text/x-metrics-test"];
+    assert_eq!(totals.original, 2048);
+    assert_eq!(totals.compressed, body.len() as u64);
+}
+
+#[rocket::get("/managed/<kind>")]
+fn managed(kind: &str) -> (rocket::http::ContentType, String) {
+    match kind {
+        "png" => (rocket::http::ContentType::PNG, text(2048)),
+        "small" => (rocket::http::ContentType::Plain, text(16)),
+        _ => (rocket::http::ContentType::Plain, text(2048)),
+    }
+}
+
+#[rocket::get("/managed-stats")]
+fn managed_stats(stats: &rocket::State<CompressionStats>) -> String {
+    format!(
+        "{} {} {} {} {}",
+        stats.responses_considered(),
+        stats.responses_compressed(),
+        stats.responses_skipped(SkipReason::NotAccepted),
+        stats.responses_skipped(SkipReason::ExcludedType),
+        stats.responses_skipped(SkipReason::TooSmall),
+    )
+}
+
+async fn managed_counters(client: &rocket::local::asynchronous::Client) -> Vec<u64> {
+    get(client, "/managed-stats", "")
+        .await
+        .into_string()
+        .await
+        .unwrap()
+        .split(' ')
+        .map(|count| count.parse().unwrap())
+        .collect()
+}
+
+#[rocket::async_test]
+async fn managed_stats_count_compressed_and_skipped_responses() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![managed, managed_stats])
+        .attach(Compression {
+            min_size: Some(1024),
+            ..Compression::fairing()
+        });
+    let client = client(rocket).await;
+    let before = managed_counters(&client).await;
+
+    get(&client, "/managed/text", "gzip")
+        .await
+        .into_bytes()
+        .await;
+    get(&client, "/managed/text", "identity")
+        .await
+        .into_bytes()
+        .await;
+    get(&client, "/managed/png", "gzip")
+        .await
+        .into_bytes()
+        .await;
+    get(&client, "/managed/small", "gzip")
+        .await
+        .into_bytes()
+        .await;
+
+    let after = managed_counters(&client).await;
+    // The first stats response is counted as well.
+    assert!(after[0] >= before[0] + 5);
+    assert!(after[1] > before[1]);
+    assert!(after[2] > before[2]);
+    assert!(after[3] > before[3]);
+    assert!(after[4] > before[4]);
+}
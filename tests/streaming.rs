@@ -0,0 +1,224 @@
+mod common;
+
+use std::{
+    io::Cursor,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use async_compression::tokio::bufread::GzipDecoder;
+
+use common::{client, decoded_body, encoding, get, text};
+use rocket::{
+    http::MediaType,
+    response::stream::{Event, EventStream, ReaderStream},
+    tokio::{
+        io::{AsyncRead, AsyncReadExt, BufReader, ReadBuf},
+        sync::Notify,
+    },
+    Build, Rocket, State,
+};
+use rocket_async_compression::{util, Compress, Compression, ConfiguredCompress};
+
+/// A reader yielding its data in small chunks, returning `Poll::Pending` before
+/// each of them like a slow producer would.
+struct Trickle {
+    data: Cursor<Vec<u8>>,
+    chunk: usize,
+    ready: bool,
+}
+
+impl Trickle {
+    fn new(data: Vec<u8>, chunk: usize) -> Trickle {
+        Trickle {
+            data: Cursor::new(data),
+            chunk,
+            ready: false,
+        }
+    }
+}
+
+impl AsyncRead for Trickle {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if !self.ready {
+            self.ready = true;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        self.ready = false;
+        let start = self.data.position() as usize;
+        let end = (start + self.chunk.min(buf.remaining())).min(self.data.get_ref().len());
+        buf.put_slice(&self.data.get_ref()[start..end]);
+        self.data.set_position(end as u64);
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn body() -> Vec<u8> {
+    text(100_000).into_bytes()
+}
+
+#[rocket::get("/stream")]
+fn stream() -> ReaderStream![Trickle] {
+    ReaderStream! {
+        yield Trickle::new(body()[..50_000].to_vec(), 977);
+        yield Trickle::new(Vec::new(), 1);
+        yield Trickle::new(body()[50_000..].to_vec(), 1493);
+    }
+}
+
+#[rocket::get("/flushed")]
+fn flushed() -> ConfiguredCompress<ReaderStream![Trickle]> {
+    Compress::streaming(ReaderStream::one(Trickle::new(body(), 311)))
+}
+
+#[rocket::async_test]
+async fn reader_streams_round_trip_through_the_fairing() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![stream])
+        .attach(Compression::fairing());
+    let client = client(rocket).await;
+
+    for accept_encoding in ["gzip", "br"] {
+        let response = get(&client, "/stream", accept_encoding).await;
+        assert_eq!(encoding(&response).as_deref(), Some(accept_encoding));
+        assert_eq!(response.body().preset_size(), None);
+        assert_eq!(decoded_body(response).await, body());
+    }
+}
+
+#[rocket::async_test]
+async fn reader_streams_round_trip_through_the_flushing_encoder() {
+    let client = client(rocket::build().mount("/", rocket::routes![flushed])).await;
+
+    for accept_encoding in ["gzip", "br"] {
+        let response = get(&client, "/flushed", accept_encoding).await;
+        assert_eq!(encoding(&response).as_deref(), Some(accept_encoding));
+        assert_eq!(decoded_body(response).await, body());
+    }
+}
+
+/// Holds back the second event of `/events` until notified.
+struct Gate(Arc<Notify>);
+
+#[rocket::get("/events")]
+fn events(gate: &State<Gate>) -> ConfiguredCompress<EventStream![]> {
+    let gate = gate.0.clone();
+    Compress::streaming(EventStream! {
+        yield Event::data("one");
+        gate.notified().await;
+        yield Event::data("two");
+    })
+}
+
+#[rocket::get("/fairing-events")]
+fn fairing_events(gate: &State<Gate>) -> EventStream![] {
+    let gate = gate.0.clone();
+    EventStream! {
+        yield Event::data("one");
+        gate.notified().await;
+        yield Event::data("two");
+    }
+}
+
+/// Reads the decoded stream until it contains `needle`, failing if that takes
+/// too long.
+async fn read_until<R: AsyncRead + Unpin>(decoded: &mut R, read: &mut Vec<u8>, needle: &str) {
+    let found = rocket::tokio::time::timeout(Duration::from_secs(5), async {
+        let mut chunk = [0; 256];
+        while !String::from_utf8_lossy(read).contains(needle) {
+            let len = decoded.read(&mut chunk).await.unwrap();
+            assert_ne!(len, 0, "the stream ended before {:?}", needle);
+            read.extend_from_slice(&chunk[..len]);
+        }
+    })
+    .await;
+    assert!(found.is_ok(), "{:?} wasn't decodable in time", needle);
+}
+
+/// Asserts that each event of the stream at `path` can be decoded as soon as it
+/// is sent.
+async fn assert_events_decode_incrementally(rocket: Rocket<Build>, path: &str) {
+    let gate = Arc::new(Notify::new());
+    let client = client(rocket.manage(Gate(gate.clone()))).await;
+
+    let response = get(&client, path, "gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    let mut decoded = GzipDecoder::new(BufReader::new(response));
+    let mut read = Vec::new();
+    read_until(&mut decoded, &mut read, "data:one").await;
+    assert!(!String::from_utf8_lossy(&read).contains("two"));
+
+    gate.notify_one();
+    read_until(&mut decoded, &mut read, "data:two").await;
+}
+
+#[rocket::async_test]
+async fn streamed_events_are_decodable_as_soon_as_they_are_sent() {
+    assert_events_decode_incrementally(
+        rocket::build().mount("/", rocket::routes![events]),
+        "/events",
+    )
+    .await;
+}
+
+#[rocket::async_test]
+async fn event_streams_opted_into_flushing_are_decodable_as_soon_as_they_are_sent() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![fairing_events])
+        .attach(Compression::fairing().flush_each_chunk_for(vec![MediaType::EventStream]));
+    assert_events_decode_incrementally(rocket, "/fairing-events").await;
+}
+
+#[rocket::async_test]
+async fn event_streams_are_left_uncompressed_unless_opted_in() {
+    let gate = Arc::new(Notify::new());
+    gate.notify_one();
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![fairing_events])
+        .manage(Gate(gate))
+        .attach(Compression::fairing());
+    let client = client(rocket).await;
+
+    let response = get(&client, "/fairing-events", "gzip").await;
+    assert_eq!(encoding(&response), None);
+    let body = response.into_string().await.unwrap();
+    assert!(body.contains("data:one") && body.contains("data:two"));
+}
+
+#[rocket::get("/chunked/<chunk>")]
+fn chunked(chunk: usize) -> ReaderStream![Trickle] {
+    ReaderStream::one(Trickle::new(body(), chunk))
+}
+
+#[rocket::async_test]
+async fn flush_every_compresses_identically_whatever_the_chunks() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![chunked])
+        .attach(Compression::fairing().flush_every(4096));
+    let client = client(rocket).await;
+
+    for accept_encoding in ["gzip", "br"] {
+        let mut bodies = Vec::new();
+        for path in ["/chunked/311", "/chunked/1493", "/chunked/1493"] {
+            let response = get(&client, path, accept_encoding).await;
+            assert_eq!(encoding(&response).as_deref(), Some(accept_encoding));
+            bodies.push(response.into_bytes().await.unwrap());
+        }
+        assert_eq!(bodies[0], bodies[1], "{}", accept_encoding);
+        assert_eq!(bodies[1], bodies[2], "{}", accept_encoding);
+        assert_eq!(
+            util::decompress(&bodies[0], accept_encoding.parse().unwrap())
+                .await
+                .unwrap(),
+            body()
+        );
+    }
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::{client, text};
+use rocket::http::Header;
+use rocket_async_compression::{util, Compression, Encoding};
+
+#[rocket::get("/text")]
+fn plain_text() -> String {
+    text(2048)
+}
+
+#[rocket::async_test]
+async fn te_gzip_compresses_with_a_transfer_coding() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![plain_text])
+        .attach(Compression::fairing().transfer_encoding(true));
+    let client = client(rocket).await;
+
+    let response = client
+        .get("/text")
+        .header(Header::new("TE", "gzip"))
+        .dispatch()
+        .await;
+    assert_eq!(
+        response.headers().get_one("Transfer-Encoding"),
+        Some("gzip")
+    );
+    assert_eq!(response.headers().get_one("Content-Encoding"), None);
+    assert_eq!(response.headers().get_one("Content-Length"), None);
+    assert_eq!(response.body().preset_size(), None);
+    let body = response.into_bytes().await.unwrap();
+    let decoded = util::decompress(&body, Encoding::Gzip).await.unwrap();
+    assert_eq!(decoded, text(2048).into_bytes());
+}
+
+#[rocket::async_test]
+async fn accept_encoding_alone_doesnt_negotiate_a_transfer_coding() {
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![plain_text])
+        .attach(Compression::fairing().transfer_encoding(true));
+    let client = client(rocket).await;
+
+    let response = client
+        .get("/text")
+        .header(Header::new("Accept-Encoding", "gzip"))
+        .dispatch()
+        .await;
+    assert_eq!(response.headers().get_one("Transfer-Encoding"), None);
+    assert_eq!(response.headers().get_one("Content-Encoding"), None);
+    assert_eq!(response.into_string().await.unwrap(), text(2048));
+}
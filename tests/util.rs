@@ -0,0 +1,92 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::{client, decoded_body, encoding, get, text};
+use rocket::{
+    http::{Header, Method},
+    route::{Handler, Outcome},
+    Data, Request, Response, Route,
+};
+use rocket_async_compression::{util, Encoding, Level};
+
+#[rocket::async_test]
+async fn every_emitted_coding_round_trips() {
+    let mut encodings = vec![Encoding::Gzip, Encoding::Brotli];
+    if cfg!(feature = "zstd") {
+        encodings.push(Encoding::Zstd);
+    }
+
+    for encoding in encodings {
+        for body in [Vec::new(), b"x".to_vec(), text(100_000).into_bytes()] {
+            for level in [Level::Fastest, Level::Default, Level::Best] {
+                let compressed = util::compress(&body, encoding.clone(), level)
+                    .await
+                    .unwrap();
+                let decompressed = util::decompress(&compressed, encoding.clone())
+                    .await
+                    .unwrap();
+                assert_eq!(decompressed, body, "{} at {:?}", encoding, level);
+            }
+        }
+    }
+}
+
+#[rocket::async_test]
+async fn unsupported_codings_are_invalid_input() {
+    let err = util::compress(b"body", Encoding::Deflate, Level::Default)
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    let err = util::decompress(b"body", Encoding::Identity)
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[rocket::async_test]
+async fn corrupt_input_fails_to_decompress() {
+    assert!(util::decompress(b"not gzip", Encoding::Gzip).await.is_err());
+}
+
+/// Serves a report compressed with `util::compress_for`.
+#[derive(Clone)]
+struct Report;
+
+#[rocket::async_trait]
+impl Handler for Report {
+    async fn handle<'r>(&self, request: &'r Request<'_>, _: Data<'r>) -> Outcome<'r> {
+        let (body, encoding) = util::compress_for(request, text(4096).as_bytes(), Level::Default)
+            .await
+            .unwrap();
+        let mut response = Response::build();
+        if encoding != Encoding::Identity {
+            response.header(Header::new("Content-Encoding", encoding.to_string()));
+        }
+        Outcome::Success(
+            response
+                .sized_body(body.len(), Cursor::new(body))
+                .finalize(),
+        )
+    }
+}
+
+#[rocket::async_test]
+async fn compress_for_uses_the_negotiated_encoding() {
+    let rocket = rocket::build().mount("/", vec![Route::new(Method::Get, "/report", Report)]);
+    let client = client(rocket).await;
+
+    let response = get(&client, "/report", "br;q=0.5, gzip").await;
+    assert_eq!(encoding(&response).as_deref(), Some("gzip"));
+    assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+
+    let response = get(&client, "/report", "br").await;
+    assert_eq!(encoding(&response).as_deref(), Some("br"));
+    assert_eq!(decoded_body(response).await, text(4096).into_bytes());
+
+    // Without an accepted encoding, the bytes are returned unchanged.
+    let response = get(&client, "/report", "compress").await;
+    assert_eq!(encoding(&response), None);
+    assert_eq!(response.into_string().await.unwrap(), text(4096));
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use rocket_async_compression::{Level, ZstdParams};
+
+#[test]
+fn levels_map_to_zstd_levels() {
+    let level = |level| ZstdParams {
+        level,
+        window_log: None,
+    };
+    assert_eq!(level(Level::Fastest).zstd_level(), 1);
+    assert_eq!(level(Level::Default).zstd_level(), 3);
+    assert_eq!(level(Level::Best).zstd_level(), 19);
+    assert_eq!(level(Level::Precise(7)).zstd_level(), 7);
+    assert_eq!(level(Level::Precise(0)).zstd_level(), 1);
+    assert_eq!(level(Level::Precise(40)).zstd_level(), 22);
+}
+
+#[cfg(feature = "zstd")]
+#[rocket::get("/text")]
+fn plain_text() -> common::Streamed {
+    common::Streamed(
+        rocket::http::ContentType::Plain,
+        common::text(100_000).into_bytes(),
+    )
+}
+
+#[cfg(feature = "zstd")]
+#[rocket::async_test]
+async fn zstd_is_negotiated_with_the_configured_window() {
+    use common::{client, decoded_body, encoding, get, text};
+    use rocket_async_compression::Compression;
+
+    let rocket = rocket::build()
+        .mount("/", rocket::routes![plain_text])
+        .attach(Compression::fairing().zstd_params(ZstdParams {
+            level: Level::Default,
+            window_log: Some(20),
+        }));
+    let client = client(rocket).await;
+
+    // zstd is only picked over the other encodings when preferred.
+    let response = get(&client, "/text", "br, gzip, zstd").await;
+    assert_eq!(encoding(&response).as_deref(), Some("br"));
+
+    let response = get(&client, "/text", "zstd").await;
+    assert_eq!(encoding(&response).as_deref(), Some("zstd"));
+    let body = response.into_bytes().await.unwrap();
+    // The frame header's window descriptor follows the magic number and the
+    // frame header descriptor unless the frame is a single segment.
+    assert_eq!(&body[..4], &[0x28, 0xb5, 0x2f, 0xfd]);
+    assert_eq!(body[4] & 0x20, 0, "single segment frame");
+    assert_eq!(10 + (body[5] >> 3), 20);
+
+    let response = get(&client, "/text", "zstd").await;
+    assert_eq!(decoded_body(response).await, text(100_000).into_bytes());
+}